@@ -1,15 +1,26 @@
 use rand::random;
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
-    time::Duration,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    compression::CompressedName,
-    error::DnsError,
-    message::{Encodable, Message, ResponseType, error_in_message},
+    builder::{MessageBuilder, OwnedMessage},
+    client_subnet::ClientSubnet,
+    cookie::CookieJar,
+    dnssec::Ds,
+    edns::{DEFAULT_UDP_PAYLOAD_SIZE, Edns},
+    error::{DnsError, ResponseCodeError},
+    log::{is_dissect, is_verbose},
+    message::{Encodable, Message, Rcode, ResponseType, dissect, error_in_message},
+    name::Name,
     net::convert_mapped_addr,
+    rdata::RData,
+    record::{Record, RecordClass, RecordType},
     server_info::{ServerInfo, sort_server_list},
+    svcb::ServiceBinding,
+    validator::{Security, ValidatedAnswer, ValidatedRecord, Validator, root_trust_anchor},
     vprintln,
 };
 
@@ -17,56 +28,208 @@ const MAX_DEPTH: usize = 8;
 const PORT: u16 = 53;
 const ROOT_SERVER_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 41, 0, 4));
 const GOOGLE_SERVER_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+/// Queries sent to a server answering BADCOOKIE, retrying once with its new server cookie
+const COOKIE_ATTEMPTS: usize = 2;
 
-fn print_domain(domain: &[u8]) -> String {
-    let mut s = String::with_capacity(domain.len() - 1);
-    for &b in &domain[1..] {
-        if b < 32 {
-            s.push('.');
-        } else {
-            s.push(b as char);
+/// Which address records to look up for a name
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+    DualStack,
+}
+
+impl AddressFamily {
+    pub fn record_types(self) -> &'static [RecordType] {
+        match self {
+            AddressFamily::Ipv4 => &[RecordType::A],
+            AddressFamily::Ipv6 => &[RecordType::Aaaa],
+            AddressFamily::DualStack => &[RecordType::A, RecordType::Aaaa],
+        }
+    }
+}
+
+/// Settings used for every query sent while resolving a name
+#[derive(Debug, Clone)]
+pub struct ResolverConfig {
+    /// UDP payload size advertised with EDNS, `None` sends queries without an OPT record
+    pub udp_payload_size: Option<u16>,
+    /// DS records of the root keys, which enable DNSSEC validation of iterative queries
+    pub trust_anchor: Option<Vec<Ds>>,
+    /// Cookies sent in the OPT record, shared by the clones of the config so every query
+    /// reuses the server cookies learned before; `None` sends no cookies
+    pub cookies: Option<Arc<Mutex<CookieJar>>>,
+    /// Network of the client the queries are sent for, so servers can answer with addresses
+    /// close to it; needs EDNS
+    ///
+    /// Iterative queries only send it below the top level domains, since the root and TLD
+    /// servers just refer to other zones and have no use for it (RFC 7871 11.1)
+    pub client_subnet: Option<ClientSubnet>,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            udp_payload_size: Some(DEFAULT_UDP_PAYLOAD_SIZE),
+            trust_anchor: None,
+            cookies: Some(Arc::new(Mutex::new(CookieJar::new()))),
+            client_subnet: None,
+        }
+    }
+}
+
+impl ResolverConfig {
+    fn build_query(
+        &self,
+        domain: &Name,
+        rtype: RecordType,
+        rclass: RecordClass,
+        recursion: bool,
+    ) -> OwnedMessage {
+        let mut builder = MessageBuilder::new(random::<u16>())
+            .question(domain.clone(), rtype, rclass)
+            .rd(recursion);
+        // Signatures are only sent to clients setting the DO bit, which requires EDNS
+        let size = match (self.udp_payload_size, &self.trust_anchor) {
+            (Some(size), _) => Some(size),
+            (None, Some(_)) => Some(DEFAULT_UDP_PAYLOAD_SIZE),
+            (None, None) => None,
+        };
+        if let Some(size) = size {
+            let mut edns = Edns::new(size);
+            edns.dnssec_ok = self.trust_anchor.is_some();
+            // Recursive resolvers pass the subnet on to the servers needing it
+            if recursion {
+                edns.set_client_subnet(self.client_subnet);
+            }
+            builder = builder.edns(edns);
+        }
+        builder.build()
+    }
+
+    /// Responses without EDNS are limited to 512 bytes, and so is the advertised size
+    fn receive_buffer_size(&self) -> usize {
+        let size = match (self.udp_payload_size, &self.trust_anchor) {
+            (None, Some(_)) => DEFAULT_UDP_PAYLOAD_SIZE,
+            (size, _) => size.unwrap_or(512),
+        };
+        size.max(512) as usize
+    }
+
+    fn cookie_jar(&self) -> Option<MutexGuard<'_, CookieJar>> {
+        let jar = self.cookies.as_ref()?;
+        Some(jar.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Encodes `message` with the cookie for `server` in its OPT record, and tells whether
+    /// a cookie was sent
+    fn encode_with_cookie(&self, message: &OwnedMessage, server: IpAddr) -> (Vec<u8>, bool) {
+        let mut buf = Vec::new();
+        match (self.cookie_jar(), message.get_edns()) {
+            (Some(jar), Some(mut edns)) => {
+                edns.set_cookie(Some(jar.cookie_for(server)));
+                let mut message = message.clone();
+                message.set_edns(Some(edns));
+                message.encode(&mut buf);
+                (buf, true)
+            }
+            _ => {
+                message.encode(&mut buf);
+                (buf, false)
+            }
+        }
+    }
+
+    /// Checks the cookie of a response from `server`, see [`CookieJar::accept`]
+    fn accept_cookie(&self, server: IpAddr, response: &[u8]) -> bool {
+        let Some(mut jar) = self.cookie_jar() else {
+            return true;
+        };
+        let edns = Message::from_bytes(response)
+            .and_then(|message| message.get_edns(response))
+            .ok()
+            .flatten();
+        jar.accept(server, edns.as_ref())
+    }
+
+    /// Adds the client subnet to `message`, if it has an OPT record to carry it
+    fn add_client_subnet(&self, message: &mut OwnedMessage) {
+        if let (Some(subnet), Some(mut edns)) = (self.client_subnet, message.get_edns()) {
+            edns.set_client_subnet(Some(subnet));
+            message.set_edns(Some(edns));
         }
     }
-    s
+}
+
+/// Responses carrying a client subnet must repeat the one of the query, see
+/// [`ClientSubnet::is_answered_by`]; the option is ignored if the query had none
+fn accept_client_subnet(query: &OwnedMessage, response: &[u8]) -> bool {
+    let edns = Message::from_bytes(response)
+        .and_then(|message| message.get_edns(response))
+        .ok()
+        .flatten();
+    match (
+        query.get_edns().and_then(|edns| edns.get_client_subnet()),
+        edns.and_then(|edns| edns.get_client_subnet()),
+    ) {
+        (Some(query), Some(answer)) => query.is_answered_by(&answer),
+        _ => true,
+    }
+}
+
+/// Prints the response in presentation format to stderr when verbose logging is enabled
+fn log_response(resp_bytes: &[u8]) {
+    if is_verbose()
+        && let Ok(response) = OwnedMessage::from_bytes(resp_bytes)
+    {
+        eprintln!("{}", response);
+    }
 }
 
 /// Sends message to the servers, quitting after the first received packet that has no error
 ///
+/// Responses must echo the client cookie sent with the message, if it has an OPT record, and
+/// the client subnet of the config if they carry one
+///
+/// Packets that can't be the response, see [`receive_response`], are skipped while waiting
+///
 /// # Errors
 ///
 /// This function will return an error if every server responds with error
 fn send_and_receive(
-    message: &Message,
+    message: &OwnedMessage,
     socket: &UdpSocket,
     servers: &[ServerInfo],
+    config: &ResolverConfig,
 ) -> Result<Vec<u8>, DnsError> {
-    let mut buf = Vec::new();
-    let mut recv = [0u8; 512];
-    message.encode(&mut buf);
+    let mut recv = vec![0u8; config.receive_buffer_size()];
 
     for server in servers.iter() {
         let ip = server.ip.unwrap_or(ROOT_SERVER_IP);
-        vprintln!("Sending message to: {:?}", ip);
-        if let Err(e) = socket.send_to(&buf, SocketAddr::new(ip, PORT)) {
-            eprintln!("Errored in send_to: {}", e);
-            continue;
-        }
-        match socket.recv_from(&mut recv) {
-            Ok((l, recv_addr)) => {
-                let recv_ip = convert_mapped_addr(recv_addr.ip());
-                if recv_ip != ip {
-                    eprintln!(
-                        "Received ip({}) is not the same as the one sent to({})",
-                        recv_ip, ip
-                    );
-                    continue;
+        for _ in 0..COOKIE_ATTEMPTS {
+            let (buf, sent_cookie) = config.encode_with_cookie(message, ip);
+            vprintln!("Sending message to: {:?}", ip);
+            if let Err(e) = socket.send_to(&buf, SocketAddr::new(ip, PORT)) {
+                eprintln!("Errored in send_to: {}", e);
+                break;
+            }
+            let Some(len) = receive_response(socket, &mut recv, ip, message, sent_cookie, config)
+            else {
+                break;
+            };
+            let response = &recv[..len];
+            match error_in_message(message.get_id(), response) {
+                // The name not existing is an answer, which may come with a proof
+                Ok(()) | Err(DnsError::ResponseCodeError(ResponseCodeError::NameError)) => {
+                    return Ok(response.to_vec());
                 }
-                if error_in_message(message.get_id(), &recv[..l]).is_ok() {
-                    return Ok(recv[..l].to_vec());
+                // The response carried a fresh server cookie to retry with (RFC 7873 5.3)
+                Err(DnsError::ResponseCodeError(ResponseCodeError::Other(rcode)))
+                    if sent_cookie && rcode == u16::from(Rcode::BadCookie) =>
+                {
+                    vprintln!("Retrying with the new server cookie of {}", ip);
                 }
-            }
-            Err(e) => {
-                eprintln!("Could not receive from socket: {}", e);
+                Err(_) => break,
             }
         }
     }
@@ -74,6 +237,64 @@ fn send_and_receive(
     Err(DnsError::NoAvailableServers)
 }
 
+/// Waits for the response of `server` to `query`, skipping the packets that can't be it: those
+/// from other addresses, with another ID, without the client cookie that was sent, or with
+/// another client subnet. Giving up at the first such packet would let anyone off-path make us
+/// ignore a server
+///
+/// Returns the length of the response in `recv`, or `None` once the read timeout of `socket`
+/// has passed since the call
+fn receive_response(
+    socket: &UdpSocket,
+    recv: &mut [u8],
+    server: IpAddr,
+    query: &OwnedMessage,
+    sent_cookie: bool,
+    config: &ResolverConfig,
+) -> Option<usize> {
+    let timeout = socket.read_timeout().ok().flatten();
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let len = loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || socket.set_read_timeout(Some(remaining)).is_err() {
+                eprintln!("No response from {}", server);
+                break None;
+            }
+        }
+        let (len, recv_addr) = match socket.recv_from(recv) {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("Could not receive from socket: {}", e);
+                break None;
+            }
+        };
+        let response = &recv[..len];
+        if is_dissect() {
+            eprintln!("{}", dissect(response));
+        }
+        let recv_ip = convert_mapped_addr(recv_addr.ip());
+        if recv_ip != server {
+            eprintln!(
+                "Received ip({}) is not the same as the one sent to({})",
+                recv_ip, server
+            );
+        } else if Message::from_bytes(response).map(|m| m.get_header().get_id())
+            != Ok(query.get_id())
+        {
+            eprintln!("Response from {} has the wrong ID", server);
+        } else if sent_cookie && !config.accept_cookie(server, response) {
+            eprintln!("Response from {} does not echo the client cookie", server);
+        } else if !accept_client_subnet(query, response) {
+            eprintln!("Response from {} does not match the client subnet", server);
+        } else {
+            break Some(len);
+        }
+    };
+    let _ = socket.set_read_timeout(timeout);
+    len
+}
+
 /// Handles the delegation returning the new servers list
 ///
 /// # Errors
@@ -82,27 +303,30 @@ fn send_and_receive(
 ///
 /// # Structure
 ///
-/// if there are additional rrs
+/// if there are glue addresses among the additional rrs
 ///     => next server ips are stored in their rdata
 ///
+/// (the OPT record of EDNS responses is no glue, so referrals carrying only it are glueless)
+///
 /// else if there are no authority rrs
 ///     => Error (should always have either additional or authority rrs)
 ///
 /// else
 ///     => Authority rrs store the domains of servers with better answer
-///     => Query the domains stored in the authority rrs
+///     => Query the domains stored in the authority rrs with `lookup`
 fn handle_delegation(
     response: &Message,
     resp_bytes: &[u8],
-    domain: &[u8],
+    domain: &Name,
+    lookup: impl Fn(&Name, RecordType, RecordClass) -> Result<Vec<Record>, DnsError>,
 ) -> Result<Vec<ServerInfo>, DnsError> {
-    if response.get_arcount() > 0 {
-        let mut servers = response.get_additional_info(resp_bytes);
+    let mut servers = response.get_additional_info(resp_bytes)?;
+    if !servers.is_empty() {
         sort_server_list(&mut servers, domain);
         return Ok(servers);
     }
 
-    let names = response.get_authorities_info(resp_bytes);
+    let names = response.get_authorities_info(resp_bytes)?;
     for name in names.iter() {
         vprintln!("- {}", name);
     }
@@ -110,16 +334,17 @@ fn handle_delegation(
         return Err(DnsError::InvalidFormat);
     }
     for name in names {
-        match query_domain(&name.to_vec()) {
-            Ok(ips) => {
+        match lookup_addresses(&name, AddressFamily::DualStack, &lookup) {
+            Ok(ips) if !ips.is_empty() => {
                 return Ok(ips
                     .iter()
                     .map(|&ip| ServerInfo {
-                        name: CompressedName(Vec::new()),
+                        name: name.clone(),
                         ip: Some(ip),
                     })
                     .collect());
             }
+            Ok(_) => {}
             Err(e) => {
                 eprintln!("Error quering: {}", e);
             }
@@ -129,21 +354,199 @@ fn handle_delegation(
     Err(DnsError::InvalidDelegation)
 }
 
-pub fn query_domain(domain: &[u8]) -> Result<Vec<IpAddr>, DnsError> {
-    let message = Message::new(random::<u16>(), domain, false);
-    vprintln!("Querying domain: {}", print_domain(domain));
+pub fn query_domain(domain: &Name) -> Result<Vec<IpAddr>, DnsError> {
+    query_addresses(domain, AddressFamily::Ipv4)
+}
+
+/// Looks up the addresses of `domain`, using `lookup` for each record type in `family`
+///
+/// For dual stack lookups, a failure of one of the queries is only returned if both fail
+fn lookup_addresses(
+    domain: &Name,
+    family: AddressFamily,
+    lookup: impl Fn(&Name, RecordType, RecordClass) -> Result<Vec<Record>, DnsError>,
+) -> Result<Vec<IpAddr>, DnsError> {
+    let mut ips = Vec::new();
+    let mut error = None;
+    for &rtype in family.record_types() {
+        match lookup(domain, rtype, RecordClass::In) {
+            Ok(records) => ips.extend(records.iter().filter_map(|r| r.rdata.get_ip())),
+            Err(e) => error = Some(e),
+        }
+    }
+
+    match error {
+        Some(e) if ips.is_empty() => Err(e),
+        _ => Ok(ips),
+    }
+}
+
+/// Iteratively resolves the A and/or AAAA records of `domain`
+pub fn query_addresses(domain: &Name, family: AddressFamily) -> Result<Vec<IpAddr>, DnsError> {
+    lookup_addresses(domain, family, query)
+}
+
+/// Asks a recursive resolver for the A and/or AAAA records of `domain`
+pub fn recursive_query_addresses(
+    domain: &Name,
+    family: AddressFamily,
+) -> Result<Vec<IpAddr>, DnsError> {
+    lookup_addresses(domain, family, recursive_query)
+}
+
+/// Looks up the SVCB or HTTPS records of `domain` using `lookup`, following AliasMode records
+///
+/// Returns the ServiceMode records sorted by priority, with `.` targets replaced by the name
+/// the record was found at. Records with mandatory keys that can't be decoded are skipped
+fn lookup_services(
+    domain: &Name,
+    rtype: RecordType,
+    lookup: impl Fn(&Name, RecordType, RecordClass) -> Result<Vec<Record>, DnsError>,
+) -> Result<Vec<ServiceBinding>, DnsError> {
+    let mut name = domain.clone();
+    for _ in 0..MAX_DEPTH {
+        let bindings: Vec<_> = lookup(&name, rtype, RecordClass::In)?
+            .into_iter()
+            .filter_map(|record| match record.rdata {
+                RData::Svcb(binding) | RData::Https(binding) => Some(binding),
+                _ => None,
+            })
+            .collect();
+
+        // ServiceMode records next to an AliasMode record are ignored (RFC 9460 2.4.2)
+        if let Some(alias) = bindings.iter().find(|b| b.is_alias()) {
+            if alias.target.is_root() {
+                // The service is not available
+                return Ok(Vec::new());
+            }
+            vprintln!("Following alias {} -> {}", name, alias.target);
+            name = alias.target.clone();
+            continue;
+        }
+
+        let mut services: Vec<_> = bindings
+            .into_iter()
+            .filter(ServiceBinding::is_supported)
+            .collect();
+        for service in &mut services {
+            if service.target.is_root() {
+                service.target = name.clone();
+            }
+        }
+        services.sort_by_key(|s| s.priority);
+        return Ok(services);
+    }
+
+    Err(DnsError::MaxDepth)
+}
+
+/// Iteratively resolves the endpoints of the service at `domain`, `rtype` being SVCB or HTTPS
+pub fn query_services(domain: &Name, rtype: RecordType) -> Result<Vec<ServiceBinding>, DnsError> {
+    lookup_services(domain, rtype, query)
+}
+
+/// Asks a recursive resolver for the endpoints of the service at `domain`, `rtype` being SVCB
+/// or HTTPS
+pub fn recursive_query_services(
+    domain: &Name,
+    rtype: RecordType,
+) -> Result<Vec<ServiceBinding>, DnsError> {
+    lookup_services(domain, rtype, recursive_query)
+}
+
+/// Iteratively resolves `domain`, starting at the root, following delegations and cnames
+///
+/// Returns the answer records of type `rtype` and class `rclass`, which is empty if the name
+/// exists without any records of that type
+pub fn query(
+    domain: &Name,
+    rtype: RecordType,
+    rclass: RecordClass,
+) -> Result<Vec<Record>, DnsError> {
+    query_with_config(domain, rtype, rclass, &ResolverConfig::default())
+}
+
+/// Same as [`query`], but with the settings in `config`
+///
+/// With a trust anchor in `config` the answers are validated, and bogus ones are an error
+pub fn query_with_config(
+    domain: &Name,
+    rtype: RecordType,
+    rclass: RecordClass,
+    config: &ResolverConfig,
+) -> Result<Vec<Record>, DnsError> {
+    let mut validator = config.trust_anchor.clone().map(Validator::with_system_time);
+    match resolve(domain, rtype, rclass, config, validator.as_mut())? {
+        ValidatedAnswer::Records(records) => {
+            if records.iter().any(|r| r.security == Security::Bogus) {
+                return Err(DnsError::Bogus);
+            }
+            Ok(records.into_iter().map(|r| r.record).collect())
+        }
+        ValidatedAnswer::NxDomain(Security::Bogus) | ValidatedAnswer::NoData(Security::Bogus) => {
+            Err(DnsError::Bogus)
+        }
+        ValidatedAnswer::NxDomain(_) => Err(ResponseCodeError::NameError.into()),
+        ValidatedAnswer::NoData(_) => Ok(Vec::new()),
+    }
+}
+
+/// Iteratively resolves `domain` like [`query`], validating the answers with DNSSEC
+///
+/// The chain of trust starts at the trust anchor of `config`, or at the current root keys if
+/// there is none, and the security of every answer is returned with it, including the proofs
+/// of negative answers
+pub fn query_validated(
+    domain: &Name,
+    rtype: RecordType,
+    rclass: RecordClass,
+    config: &ResolverConfig,
+) -> Result<ValidatedAnswer, DnsError> {
+    let trust_anchor = config
+        .trust_anchor
+        .clone()
+        .unwrap_or_else(root_trust_anchor);
+    let config = ResolverConfig {
+        trust_anchor: Some(trust_anchor.clone()),
+        ..config.clone()
+    };
+    let mut validator = Validator::with_system_time(trust_anchor);
+    resolve(domain, rtype, rclass, &config, Some(&mut validator))
+}
+
+/// Follows delegations from the root down to the answer, feeding the DNSKEY and DS records
+/// found along the way to `validator` if there is one
+///
+/// Without a validator every answer is indeterminate
+fn resolve(
+    domain: &Name,
+    rtype: RecordType,
+    rclass: RecordClass,
+    config: &ResolverConfig,
+    mut validator: Option<&mut Validator>,
+) -> Result<ValidatedAnswer, DnsError> {
+    let mut message = config.build_query(domain, rtype, rclass, false);
+    vprintln!("Querying domain: {} {}", domain, rtype);
     vprintln!("");
 
     let socket = UdpSocket::bind("[::]:0")?;
     socket.set_read_timeout(Some(Duration::new(5, 0)))?;
 
     let mut servers = vec![ServerInfo {
-        name: CompressedName(vec![b".".to_vec()]), // Empty
+        name: Name::root(),
         ip: Some(ROOT_SERVER_IP),
     }];
+    let mut zone = Name::root();
 
     for _ in 0..MAX_DEPTH {
-        let resp_bytes = &send_and_receive(&message, &socket, &servers)?;
+        if let Some(validator) = validator.as_deref_mut()
+            && validator.needs_keys(&zone)
+        {
+            fetch_keys(validator, &zone, &socket, &servers, config)?;
+        }
+
+        let resp_bytes = &send_and_receive(&message, &socket, &servers, config)?;
+        log_response(resp_bytes);
         let response = Message::from_bytes(resp_bytes)?;
         let response_type = response.get_type();
 
@@ -152,23 +555,95 @@ pub fn query_domain(domain: &[u8]) -> Result<Vec<IpAddr>, DnsError> {
                 eprintln!("Invalid format of response");
                 return Err(DnsError::InvalidFormat);
             }
+            ResponseType::NxDomain | ResponseType::NoData => {
+                let nxdomain = matches!(response_type, ResponseType::NxDomain);
+                let security = match validator.as_deref_mut() {
+                    Some(validator) => {
+                        let authorities = response.get_authority_records(resp_bytes)?;
+                        for signer in validator.unknown_signers(&authorities) {
+                            fetch_zone_cut(validator, &signer, &socket, &servers, config)?;
+                        }
+                        validator.validate_denial(domain, rtype, nxdomain, &authorities)
+                    }
+                    None => Security::Indeterminate,
+                };
+                vprintln!("Denial of {} {}: {:?}", domain, rtype, security);
+                return Ok(if nxdomain {
+                    ValidatedAnswer::NxDomain(security)
+                } else {
+                    ValidatedAnswer::NoData(security)
+                });
+            }
             ResponseType::Answer => {
-                let ips = response.get_answer_ips();
-                if !ips.is_empty() {
-                    return Ok(ips);
+                let answers =
+                    response.get_answer_records(resp_bytes, RecordType::Any, RecordClass::Any)?;
+                let validated: Vec<_> = match validator.as_deref_mut() {
+                    Some(validator) => {
+                        // Wildcard answers come with the proof that the name doesn't exist
+                        let mut records = answers.clone();
+                        records.extend(response.get_authority_records(resp_bytes)?);
+                        for signer in validator.unknown_signers(&records) {
+                            fetch_zone_cut(validator, &signer, &socket, &servers, config)?;
+                        }
+                        validator
+                            .validate_records(&records)
+                            .into_iter()
+                            .filter(|v| answers.contains(&v.record))
+                            .collect()
+                    }
+                    None => answers
+                        .into_iter()
+                        .filter(|r| r.rtype != RecordType::Rrsig)
+                        .map(|record| ValidatedRecord {
+                            record,
+                            security: Security::Indeterminate,
+                        })
+                        .collect(),
+                };
+                // Answers reached through CNAME records are only as secure as them
+                let (records, aliases): (Vec<_>, Vec<_>) = validated
+                    .into_iter()
+                    .filter(|v| {
+                        rclass.matches(v.record.rclass)
+                            && (rtype.matches(v.record.rtype)
+                                || v.record.rtype == RecordType::Cname)
+                    })
+                    .partition(|v| rtype.matches(v.record.rtype));
+                if !records.is_empty() {
+                    return Ok(ValidatedAnswer::Records(records).through_aliases(&aliases));
                 }
-                let cnames = response.get_cnames(resp_bytes);
+                let cnames = response.get_cnames(resp_bytes)?;
                 for name in cnames {
-                    match query_domain(&name.to_vec()) {
-                        Ok(ips) => return Ok(ips),
+                    match resolve(&name, rtype, rclass, config, validator.as_deref_mut()) {
+                        Ok(answer) => return Ok(answer.through_aliases(&aliases)),
                         Err(e) => eprintln!("Error when querying cname: {}", e),
                     };
                 }
                 break;
             }
             ResponseType::Delegation => {
-                servers = handle_delegation(&response, resp_bytes, domain)?;
-                message.inc();
+                let authorities = response.get_authority_records(resp_bytes)?;
+                let child = authorities
+                    .iter()
+                    .find(|r| r.rtype == RecordType::Ns)
+                    .map(|r| r.name.clone());
+                if let Some(validator) = validator.as_deref_mut() {
+                    let child = child.clone().ok_or(DnsError::InvalidDelegation)?;
+                    let security = validator.add_delegation(&child, &authorities);
+                    vprintln!("Delegation to {}: {:?}", child, security);
+                }
+                if let Some(child) = child {
+                    zone = child;
+                }
+                // Only the servers below the top level domains answer with addresses
+                if zone.label_count() > 1 {
+                    config.add_client_subnet(&mut message);
+                }
+                let lookup =
+                    |domain: &Name, rtype, rclass| query_with_config(domain, rtype, rclass, config);
+                servers = handle_delegation(&response, resp_bytes, domain, lookup)?;
+                let id = message.get_id().wrapping_add(1);
+                message.get_header_mut().set_id(id);
             }
         };
     }
@@ -176,37 +651,400 @@ pub fn query_domain(domain: &[u8]) -> Result<Vec<IpAddr>, DnsError> {
     Err(DnsError::MaxDepth)
 }
 
-pub fn recursive_query(domain: &[u8]) -> Result<Vec<IpAddr>, DnsError> {
-    let message = Message::new(random::<u16>(), domain, true);
-    vprintln!("Recursivly querying domain: {}", print_domain(domain));
+/// Asks `servers` for the DNSKEY RRset of `zone` and checks it against its DS records
+fn fetch_keys(
+    validator: &mut Validator,
+    zone: &Name,
+    socket: &UdpSocket,
+    servers: &[ServerInfo],
+    config: &ResolverConfig,
+) -> Result<(), DnsError> {
+    let message = config.build_query(zone, RecordType::Dnskey, RecordClass::In, false);
+    let resp_bytes = &send_and_receive(&message, socket, servers, config)?;
+    log_response(resp_bytes);
+    let response = Message::from_bytes(resp_bytes)?;
+    let answers = response.get_answer_records(resp_bytes, RecordType::Any, RecordClass::In)?;
+    let security = validator.add_keys(zone, &answers);
+    vprintln!("Keys of {}: {:?}", zone, security);
+    Ok(())
+}
+
+/// Handles a zone cut that wasn't seen as a referral, because `servers` serve both sides of
+/// it, by asking them for the DS and then the DNSKEY records of `zone`
+fn fetch_zone_cut(
+    validator: &mut Validator,
+    zone: &Name,
+    socket: &UdpSocket,
+    servers: &[ServerInfo],
+    config: &ResolverConfig,
+) -> Result<(), DnsError> {
+    let message = config.build_query(zone, RecordType::Ds, RecordClass::In, false);
+    let resp_bytes = &send_and_receive(&message, socket, servers, config)?;
+    log_response(resp_bytes);
+    let response = Message::from_bytes(resp_bytes)?;
+    let mut records = response.get_answer_records(resp_bytes, RecordType::Any, RecordClass::In)?;
+    records.extend(response.get_authority_records(resp_bytes)?);
+    let security = validator.add_delegation(zone, &records);
+    vprintln!("Zone cut at {}: {:?}", zone, security);
+
+    if validator.needs_keys(zone) {
+        fetch_keys(validator, zone, socket, servers, config)?;
+    }
+    Ok(())
+}
+
+/// Asks a recursive resolver for the records of type `rtype` and class `rclass`
+pub fn recursive_query(
+    domain: &Name,
+    rtype: RecordType,
+    rclass: RecordClass,
+) -> Result<Vec<Record>, DnsError> {
+    recursive_query_with_config(domain, rtype, rclass, &ResolverConfig::default())
+}
+
+/// Same as [`recursive_query`], but with the settings in `config`
+pub fn recursive_query_with_config(
+    domain: &Name,
+    rtype: RecordType,
+    rclass: RecordClass,
+    config: &ResolverConfig,
+) -> Result<Vec<Record>, DnsError> {
+    let message = config.build_query(domain, rtype, rclass, true);
+    vprintln!("Recursivly querying domain: {} {}", domain, rtype);
 
     let socket = UdpSocket::bind("[::]:0")?;
     socket.set_read_timeout(Some(Duration::new(5, 0)))?;
 
     let servers = [ServerInfo {
-        name: CompressedName(vec![b".".to_vec()]), // Empty
+        name: Name::root(),
         ip: Some(GOOGLE_SERVER_IP),
     }];
 
-    let resp_bytes = &send_and_receive(&message, &socket, &servers)?;
+    let resp_bytes = &send_and_receive(&message, &socket, &servers, config)?;
+    log_response(resp_bytes);
     let response = Message::from_bytes(resp_bytes)?;
 
     let response_type = response.get_type();
 
     match response_type {
-        ResponseType::Answer => {
-            let ips = response.get_answer_ips();
-            if !ips.is_empty() {
-                return Ok(ips);
-            }
+        ResponseType::Answer => Ok(response.get_answer_records(resp_bytes, rtype, rclass)?),
+        ResponseType::NxDomain => Err(ResponseCodeError::NameError.into()),
+        ResponseType::NoData => Ok(Vec::new()),
+        _ => {
             eprintln!("Invalid format of response");
 
             Err(DnsError::InvalidFormat)
         }
-        _ => {
-            eprintln!("Invalid format of response");
+    }
+}
 
-            Err(DnsError::InvalidFormat)
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_query_edns() {
+        let domain = "example.com".parse().unwrap();
+        let config = ResolverConfig {
+            udp_payload_size: Some(4096),
+            ..ResolverConfig::default()
+        };
+        let query = config.build_query(&domain, RecordType::A, RecordClass::In, false);
+        assert_eq!(query.get_edns().map(|e| e.udp_payload_size), Some(4096));
+        assert_eq!(config.receive_buffer_size(), 4096);
+
+        let config = ResolverConfig {
+            udp_payload_size: None,
+            ..ResolverConfig::default()
+        };
+        let query = config.build_query(&domain, RecordType::A, RecordClass::In, true);
+        assert_eq!(query.get_edns(), None);
+        assert!(query.get_header().get_rd());
+        assert_eq!(config.receive_buffer_size(), 512);
+
+        // Validation needs the DO bit, and so EDNS even if it wasn't asked for
+        let config = ResolverConfig {
+            udp_payload_size: None,
+            trust_anchor: Some(root_trust_anchor()),
+            ..ResolverConfig::default()
+        };
+        let query = config.build_query(&domain, RecordType::A, RecordClass::In, false);
+        let edns = query.get_edns().unwrap();
+        assert!(edns.dnssec_ok);
+        assert_eq!(edns.udp_payload_size, DEFAULT_UDP_PAYLOAD_SIZE);
+    }
+
+    #[test]
+    fn test_client_subnet() {
+        use crate::edns::{CLIENT_SUBNET, EdnsOption};
+
+        let domain = "example.com".parse().unwrap();
+        let subnet = ClientSubnet::new(IpAddr::from([198, 51, 100, 7]), 24);
+        let config = ResolverConfig {
+            client_subnet: Some(subnet),
+            ..ResolverConfig::default()
+        };
+        let query = config.build_query(&domain, RecordType::A, RecordClass::In, true);
+        assert_eq!(query.get_edns().unwrap().get_client_subnet(), Some(subnet));
+
+        // Iterative queries start without it, the root servers don't need it
+        let mut iterative = config.build_query(&domain, RecordType::A, RecordClass::In, false);
+        assert_eq!(iterative.get_edns().unwrap().get_client_subnet(), None);
+        config.add_client_subnet(&mut iterative);
+        assert_eq!(
+            iterative.get_edns().unwrap().get_client_subnet(),
+            Some(subnet)
+        );
+
+        // Responses may narrow the scope, but not change the subnet
+        let response = |subnet: Option<ClientSubnet>| {
+            let mut edns = Edns::new(DEFAULT_UDP_PAYLOAD_SIZE);
+            edns.set_client_subnet(subnet);
+            let mut buf = Vec::new();
+            MessageBuilder::response(&query)
+                .edns(edns)
+                .build()
+                .encode(&mut buf);
+            buf
+        };
+        let scoped = ClientSubnet::from_option(&EdnsOption {
+            code: CLIENT_SUBNET,
+            data: vec![0, 1, 24, 16, 198, 51, 100],
+        })
+        .unwrap();
+        assert!(accept_client_subnet(&query, &response(Some(scoped))));
+        assert!(accept_client_subnet(&query, &response(None)));
+        let other = ClientSubnet::new(IpAddr::from([203, 0, 113, 7]), 24);
+        assert!(!accept_client_subnet(&query, &response(Some(other))));
+        let without =
+            ResolverConfig::default().build_query(&domain, RecordType::A, RecordClass::In, true);
+        assert!(accept_client_subnet(&without, &response(Some(other))));
+
+        // The option is carried by the OPT record
+        let config = ResolverConfig {
+            udp_payload_size: None,
+            ..config
+        };
+        let mut query = config.build_query(&domain, RecordType::A, RecordClass::In, true);
+        config.add_client_subnet(&mut query);
+        assert_eq!(query.get_edns(), None);
+    }
+
+    #[test]
+    fn test_cookies() {
+        use crate::cookie::Cookie;
+
+        let domain = "example.com".parse().unwrap();
+        let server = IpAddr::from([192, 0, 2, 1]);
+        let config = ResolverConfig::default();
+        let query = config.build_query(&domain, RecordType::A, RecordClass::In, false);
+        let sent_cookie = |config: &ResolverConfig| {
+            let query = config.build_query(&domain, RecordType::A, RecordClass::In, false);
+            let (bytes, sent) = config.encode_with_cookie(&query, server);
+            let edns = OwnedMessage::from_bytes(&bytes).unwrap().get_edns();
+            assert_eq!(sent, edns.as_ref().and_then(Edns::get_cookie).is_some());
+            edns.and_then(|edns| edns.get_cookie())
+        };
+        let cookie = sent_cookie(&config).unwrap();
+        assert!(cookie.server.is_empty());
+
+        // Only responses echoing the client cookie are accepted, and teach the server cookie
+        let response = |cookie: Cookie| {
+            let mut edns = Edns::new(DEFAULT_UDP_PAYLOAD_SIZE);
+            edns.set_cookie(Some(cookie));
+            let mut buf = Vec::new();
+            MessageBuilder::response(&query)
+                .edns(edns)
+                .build()
+                .encode(&mut buf);
+            buf
+        };
+        let forged = response(Cookie {
+            client: [0; 8],
+            server: vec![1; 8],
+        });
+        assert!(!config.accept_cookie(server, &forged));
+        let echoed = Cookie {
+            client: cookie.client,
+            server: vec![2; 8],
+        };
+        assert!(config.accept_cookie(server, &response(echoed.clone())));
+        assert_eq!(sent_cookie(&config), Some(echoed.clone()));
+        assert_eq!(sent_cookie(&config.clone()), Some(echoed));
+
+        // Cookies need EDNS, and can be turned off
+        let config = ResolverConfig {
+            udp_payload_size: None,
+            ..ResolverConfig::default()
+        };
+        assert_eq!(sent_cookie(&config), None);
+        let config = ResolverConfig {
+            cookies: None,
+            ..ResolverConfig::default()
+        };
+        assert_eq!(sent_cookie(&config), None);
+        assert!(config.accept_cookie(server, &forged));
+    }
+
+    #[test]
+    fn test_receive_response_skips_spoofed_packets() {
+        use crate::cookie::Cookie;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        let subnet = ClientSubnet::new(IpAddr::from([198, 51, 100, 7]), 24);
+        let config = ResolverConfig {
+            client_subnet: Some(subnet),
+            ..ResolverConfig::default()
+        };
+        let query = config.build_query(
+            &"example.com".parse().unwrap(),
+            RecordType::A,
+            RecordClass::In,
+            true,
+        );
+        let client = config.cookie_jar().unwrap().cookie_for(ip).client;
+        let response = |id: u16, client: [u8; 8], subnet: ClientSubnet| {
+            let mut edns = Edns::new(DEFAULT_UDP_PAYLOAD_SIZE);
+            edns.set_cookie(Some(Cookie {
+                client,
+                server: vec![1; 8],
+            }));
+            edns.set_client_subnet(Some(subnet));
+            let mut response = MessageBuilder::response(&query).edns(edns).build();
+            response.get_header_mut().set_id(id);
+            let mut buf = Vec::new();
+            response.encode(&mut buf);
+            buf
+        };
+
+        let id = query.get_id();
+        let other = ClientSubnet::new(IpAddr::from([203, 0, 113, 7]), 24);
+        let genuine = response(id, client, subnet);
+        for packet in [
+            response(id.wrapping_add(1), client, subnet),
+            response(id, [0; 8], subnet),
+            response(id, client, other),
+            genuine.clone(),
+        ] {
+            server
+                .send_to(&packet, socket.local_addr().unwrap())
+                .unwrap();
         }
+        let mut recv = vec![0; 512];
+        let len = receive_response(&socket, &mut recv, ip, &query, true, &config);
+        assert_eq!(len.map(|len| &recv[..len]), Some(&genuine[..]));
+
+        // Gives up after the read timeout, which is left as it was
+        assert_eq!(
+            receive_response(&socket, &mut recv, ip, &query, true, &config),
+            None
+        );
+        assert_eq!(
+            socket.read_timeout().unwrap(),
+            Some(Duration::from_millis(200))
+        );
+    }
+
+    #[test]
+    fn test_handle_delegation_glueless() {
+        let domain: Name = "www.example.com".parse().unwrap();
+        let ns = |target: &str| Record {
+            name: "example.com".parse().unwrap(),
+            rtype: RecordType::Ns,
+            rclass: RecordClass::In,
+            ttl: 3600,
+            rdata: RData::Ns(target.parse().unwrap()),
+        };
+        let glue = Record {
+            name: "ns1.example.com".parse().unwrap(),
+            rtype: RecordType::A,
+            rclass: RecordClass::In,
+            ttl: 3600,
+            rdata: RData::A([192, 0, 2, 53].into()),
+        };
+        let referral = |additional: Option<Record>| {
+            let query = MessageBuilder::new(1)
+                .question(domain.clone(), RecordType::A, RecordClass::In)
+                .build();
+            let mut builder = MessageBuilder::response(&query)
+                .authority(ns("ns1.example.com"))
+                .authority(ns("ns.example.net"))
+                .edns(Edns::new(DEFAULT_UDP_PAYLOAD_SIZE));
+            if let Some(record) = additional {
+                builder = builder.additional(record);
+            }
+            let mut buf = Vec::new();
+            builder.build().encode(&mut buf);
+            buf
+        };
+        let lookup = |name: &Name, rtype, _| {
+            Ok(match rtype {
+                RecordType::A if name == &"ns.example.net".parse::<Name>().unwrap() => {
+                    vec![Record {
+                        name: name.clone(),
+                        ..glue.clone()
+                    }]
+                }
+                _ => Vec::new(),
+            })
+        };
+        let servers = |bytes: &[u8]| {
+            let response = Message::from_bytes(bytes).unwrap();
+            handle_delegation(&response, bytes, &domain, lookup)
+                .unwrap()
+                .into_iter()
+                .map(|server| (server.name.to_string(), server.ip))
+                .collect::<Vec<_>>()
+        };
+
+        // Glue addresses are used as they are
+        let ip = Some(IpAddr::from([192, 0, 2, 53]));
+        assert_eq!(
+            servers(&referral(Some(glue.clone()))),
+            [("ns1.example.com.".to_string(), ip)]
+        );
+        // Only the OPT record in the additional section, so the names are looked up
+        assert_eq!(
+            servers(&referral(None)),
+            [("ns.example.net.".to_string(), ip)]
+        );
+    }
+
+    #[test]
+    fn test_lookup_services() {
+        // Zone with an alias at the apex and two endpoints, the first at the owner name
+        let zone: Vec<Record> = [
+            "example.com. 300 IN HTTPS 0 svc.example.net.",
+            "svc.example.net. 300 IN HTTPS 2 . alpn=h2",
+            "svc.example.net. 300 IN HTTPS 1 pool.example.net. alpn=h3 port=8443",
+            "svc.example.net. 300 IN HTTPS 3 old.example.net. mandatory=key9 key9=1",
+            "loop.example.com. 300 IN HTTPS 0 loop.example.com.",
+            "gone.example.com. 300 IN HTTPS 0 .",
+        ]
+        .iter()
+        .map(|text| text.parse().unwrap())
+        .collect();
+        let lookup = |domain: &Name, rtype: RecordType, _: RecordClass| {
+            Ok(zone
+                .iter()
+                .filter(|r| &r.name == domain && r.rtype == rtype)
+                .cloned()
+                .collect())
+        };
+        let name = |domain: &str| domain.parse::<Name>().unwrap();
+
+        let services = lookup_services(&name("example.com"), RecordType::Https, lookup).unwrap();
+        let targets: Vec<_> = services.iter().map(|s| s.target.to_string()).collect();
+        assert_eq!(targets, ["pool.example.net.", "svc.example.net."]);
+
+        let services = lookup_services(&name("gone.example.com"), RecordType::Https, lookup);
+        assert!(services.unwrap().is_empty());
+        let services = lookup_services(&name("loop.example.com"), RecordType::Https, lookup);
+        assert!(matches!(services, Err(DnsError::MaxDepth)));
     }
 }
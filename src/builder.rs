@@ -0,0 +1,632 @@
+use std::fmt;
+
+use crate::{
+    compression::{NameCompressor, decompress},
+    edns::Edns,
+    error::ParseError,
+    message::{Encodable, Header, Message, Opcode, Rcode, ResourceRecord},
+    name::Name,
+    record::{Record, RecordClass, RecordType},
+};
+
+/// Message that owns all its names and records, unlike [`Message`] which borrows from the
+/// received bytes
+///
+/// The header counts are recomputed from the sections when encoding
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OwnedMessage {
+    header: Header,
+    questions: Vec<OwnedQuestion>,
+    answers: Vec<Record>,
+    authorities: Vec<Record>,
+    additionals: Vec<Record>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OwnedQuestion {
+    pub name: Name,
+    pub qtype: RecordType,
+    pub qclass: RecordClass,
+}
+
+/// Records left out by [`OwnedMessage::encode_with_limit`] to stay within the size limit
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Omitted {
+    pub answers: usize,
+    pub authorities: usize,
+    /// Additional records other than the OPT record
+    pub additionals: usize,
+    /// True if the OPT record did not fit either
+    pub edns: bool,
+}
+
+/// Builds an [`OwnedMessage`] one section entry or header flag at a time
+#[derive(Debug)]
+pub struct MessageBuilder {
+    message: OwnedMessage,
+}
+
+impl OwnedMessage {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        Self::from_message(&Message::from_bytes(bytes)?, bytes)
+    }
+
+    /// Copies `message` out of `bytes`, the buffer it was parsed from
+    pub fn from_message(message: &Message, bytes: &[u8]) -> Result<Self, ParseError> {
+        let to_records = |rrs: &[ResourceRecord]| -> Result<Vec<Record>, ParseError> {
+            rrs.iter().map(|rr| rr.to_record(bytes)).collect()
+        };
+        Ok(Self {
+            header: message.header.clone(),
+            questions: message
+                .questions
+                .iter()
+                .map(|q| {
+                    Ok(OwnedQuestion {
+                        name: decompress(q.qname, bytes)?,
+                        qtype: q.qtype.into(),
+                        qclass: q.qclass.into(),
+                    })
+                })
+                .collect::<Result<_, ParseError>>()?,
+            answers: to_records(&message.answers)?,
+            authorities: to_records(&message.authorities)?,
+            additionals: to_records(&message.additionals)?,
+        })
+    }
+
+    pub fn get_id(&self) -> u16 {
+        self.header.id.get()
+    }
+
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn get_header_mut(&mut self) -> &mut Header {
+        &mut self.header
+    }
+
+    pub fn get_questions(&self) -> &[OwnedQuestion] {
+        &self.questions
+    }
+
+    pub fn get_answers(&self) -> &[Record] {
+        &self.answers
+    }
+
+    pub fn get_authorities(&self) -> &[Record] {
+        &self.authorities
+    }
+
+    pub fn get_additionals(&self) -> &[Record] {
+        &self.additionals
+    }
+
+    /// Returns the EDNS information from the OPT record, if there is one
+    pub fn get_edns(&self) -> Option<Edns> {
+        self.additionals.iter().find_map(Edns::from_record)
+    }
+
+    /// Replaces the OPT record, or removes it if `edns` is `None`
+    pub fn set_edns(&mut self, edns: Option<Edns>) {
+        self.additionals.retain(|r| r.rtype != RecordType::Opt);
+        if let Some(edns) = edns {
+            self.additionals.push(edns.to_record());
+        }
+    }
+
+    /// Returns the full rcode, combining the header with the extended rcode of the OPT record
+    pub fn get_rcode(&self) -> Rcode {
+        match self.get_edns() {
+            Some(edns) => edns.get_rcode(self.header.get_rcode()),
+            None => self.header.get_rcode(),
+        }
+    }
+
+    pub fn get_questions_mut(&mut self) -> &mut Vec<OwnedQuestion> {
+        &mut self.questions
+    }
+
+    pub fn get_answers_mut(&mut self) -> &mut Vec<Record> {
+        &mut self.answers
+    }
+
+    pub fn get_authorities_mut(&mut self) -> &mut Vec<Record> {
+        &mut self.authorities
+    }
+
+    pub fn get_additionals_mut(&mut self) -> &mut Vec<Record> {
+        &mut self.additionals
+    }
+
+    /// Largest UDP response this message allows, if it is a request: the EDNS payload size,
+    /// or 512 bytes without EDNS
+    pub fn max_udp_response_size(&self) -> usize {
+        self.get_edns()
+            .map_or(512, |edns| edns.udp_payload_size.max(512) as usize)
+    }
+
+    /// Encodes the message with compressed names in at most `limit` bytes, leaving out
+    /// records from the end: additional records first, then authority and answer records
+    ///
+    /// The TC bit is set if any answer or authority record is left out. Additional records are
+    /// only dropped as whole RRsets, and the OPT record is kept whenever it fits, as RFC 6891
+    /// asks of responses. The header and questions are always written, even past the limit
+    pub fn encode_with_limit(&self, buf: &mut Vec<u8>, limit: usize) -> Omitted {
+        let opt = self.additionals.iter().find(|r| r.rtype == RecordType::Opt);
+        let additionals: Vec<&Record> = self
+            .additionals
+            .iter()
+            .filter(|r| r.rtype != RecordType::Opt)
+            .collect();
+        let records: Vec<&Record> = self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(additionals.iter().copied())
+            .collect();
+
+        // Pointers only go backwards, so every prefix of the records encodes the same way
+        let mut message = Vec::new();
+        let mut compressor = NameCompressor::new();
+        self.header.encode(&mut message);
+        for question in &self.questions {
+            question.encode_compressed(&mut message, &mut compressor);
+        }
+        let mut ends = vec![message.len()];
+        for record in &records {
+            record.encode_compressed(&mut message, &mut compressor);
+            ends.push(message.len());
+        }
+        let mut opt_bytes = Vec::new();
+        if let Some(opt) = opt {
+            opt.encode(&mut opt_bytes);
+        }
+
+        let fits = |extra: usize| (0..ends.len()).rev().find(|&k| ends[k] + extra <= limit);
+        let (mut kept, keep_opt) = match fits(opt_bytes.len()) {
+            Some(kept) => (kept, opt.is_some()),
+            None => (fits(0).unwrap_or(0), false),
+        };
+        let sections = self.answers.len() + self.authorities.len();
+        if kept > sections && kept < records.len() {
+            while kept > sections && same_rrset(records[kept - 1], records[kept]) {
+                kept -= 1;
+            }
+        }
+
+        message.truncate(ends[kept]);
+        if keep_opt {
+            message.extend_from_slice(&opt_bytes);
+        }
+
+        let ancount = kept.min(self.answers.len());
+        let nscount = (kept - ancount).min(self.authorities.len());
+        let arcount = kept - ancount - nscount;
+        let omitted = Omitted {
+            answers: self.answers.len() - ancount,
+            authorities: self.authorities.len() - nscount,
+            additionals: additionals.len() - arcount,
+            edns: opt.is_some() && !keep_opt,
+        };
+
+        let mut header = self.header.clone();
+        header.qdcount = self.questions.len() as u16;
+        header.ancount = ancount as u16;
+        header.nscount = nscount as u16;
+        header.arcount = (arcount + usize::from(keep_opt)) as u16;
+        header.set_tc(omitted.answers > 0 || omitted.authorities > 0);
+        let mut header_bytes = Vec::new();
+        header.encode(&mut header_bytes);
+        message[..header_bytes.len()].copy_from_slice(&header_bytes);
+
+        buf.extend_from_slice(&message);
+        omitted
+    }
+
+    /// Returns the header with its counts set to the number of entries in each section
+    fn counted_header(&self) -> Header {
+        let mut header = self.header.clone();
+        header.qdcount = self.questions.len() as u16;
+        header.ancount = self.answers.len() as u16;
+        header.nscount = self.authorities.len() as u16;
+        header.arcount = self.additionals.len() as u16;
+        header
+    }
+}
+
+fn same_rrset(a: &Record, b: &Record) -> bool {
+    a.name == b.name && a.rtype == b.rtype && a.rclass == b.rclass
+}
+
+impl Encodable for OwnedQuestion {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.name.as_wire());
+        buf.extend_from_slice(&u16::from(self.qtype).to_be_bytes());
+        buf.extend_from_slice(&u16::from(self.qclass).to_be_bytes());
+    }
+
+    fn encode_compressed(&self, buf: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        compressor.write_name(self.name.as_wire(), buf);
+        buf.extend_from_slice(&u16::from(self.qtype).to_be_bytes());
+        buf.extend_from_slice(&u16::from(self.qclass).to_be_bytes());
+    }
+}
+
+impl Encodable for OwnedMessage {
+    /// Encodes the message with compressed names
+    fn encode(&self, buf: &mut Vec<u8>) {
+        // Pointers are offsets from the start of the message, so encode it separately
+        let mut message = Vec::new();
+        self.encode_compressed(&mut message, &mut NameCompressor::new());
+        buf.extend_from_slice(&message);
+    }
+
+    fn encode_compressed(&self, buf: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        self.counted_header().encode(buf);
+
+        for question in &self.questions {
+            question.encode_compressed(buf, compressor);
+        }
+        for rr in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            rr.encode_compressed(buf, compressor);
+        }
+    }
+}
+
+impl fmt::Display for OwnedQuestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ";{}\t\t{}\t{}", self.name, self.qclass, self.qtype)
+    }
+}
+
+impl fmt::Display for OwnedMessage {
+    /// Writes the message in the presentation format used by dig, the OPT record is shown as a
+    /// pseudo-section instead of an additional record
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = self.counted_header();
+        header.write_presentation(f, self.get_rcode())?;
+        writeln!(f)?;
+
+        if let Some(edns) = self.get_edns() {
+            write!(f, "\n;; OPT PSEUDOSECTION:\n{}\n", edns)?;
+        }
+        if !self.questions.is_empty() {
+            writeln!(f, "\n;; QUESTION SECTION:")?;
+            for question in &self.questions {
+                writeln!(f, "{}", question)?;
+            }
+        }
+
+        let additionals: Vec<&Record> = self
+            .additionals
+            .iter()
+            .filter(|r| r.rtype != RecordType::Opt)
+            .collect();
+        let sections = [
+            ("ANSWER", self.answers.iter().collect()),
+            ("AUTHORITY", self.authorities.iter().collect()),
+            ("ADDITIONAL", additionals),
+        ];
+        for (name, records) in sections {
+            if records.is_empty() {
+                continue;
+            }
+            writeln!(f, "\n;; {} SECTION:", name)?;
+            for record in records {
+                writeln!(f, "{}", record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MessageBuilder {
+    pub fn new(id: u16) -> Self {
+        let mut header = Header::new(id, false, false, false, false, 0);
+        header.qdcount = 0;
+        Self {
+            message: OwnedMessage {
+                header,
+                questions: Vec::new(),
+                answers: Vec::new(),
+                authorities: Vec::new(),
+                additionals: Vec::new(),
+            },
+        }
+    }
+
+    /// Starts a response to `request`, copying its id, opcode, RD flag and questions
+    pub fn response(request: &OwnedMessage) -> Self {
+        let mut builder = Self::new(request.get_id())
+            .qr(true)
+            .opcode(request.header.get_opcode())
+            .rd(request.header.get_rd());
+        builder.message.questions = request.questions.clone();
+        builder
+    }
+
+    pub fn question(mut self, name: Name, qtype: RecordType, qclass: RecordClass) -> Self {
+        self.message.questions.push(OwnedQuestion {
+            name,
+            qtype,
+            qclass,
+        });
+        self
+    }
+
+    pub fn answer(mut self, record: Record) -> Self {
+        self.message.answers.push(record);
+        self
+    }
+
+    pub fn authority(mut self, record: Record) -> Self {
+        self.message.authorities.push(record);
+        self
+    }
+
+    pub fn additional(mut self, record: Record) -> Self {
+        self.message.additionals.push(record);
+        self
+    }
+
+    /// Adds an OPT record, replacing any previous one
+    pub fn edns(mut self, edns: Edns) -> Self {
+        self.message.set_edns(Some(edns));
+        self
+    }
+
+    pub fn qr(mut self, on: bool) -> Self {
+        self.message.header.set_qr(on);
+        self
+    }
+
+    pub fn opcode(mut self, opcode: Opcode) -> Self {
+        self.message.header.set_opcode(opcode);
+        self
+    }
+
+    pub fn aa(mut self, on: bool) -> Self {
+        self.message.header.set_aa(on);
+        self
+    }
+
+    pub fn tc(mut self, on: bool) -> Self {
+        self.message.header.set_tc(on);
+        self
+    }
+
+    pub fn rd(mut self, on: bool) -> Self {
+        self.message.header.set_rd(on);
+        self
+    }
+
+    pub fn ra(mut self, on: bool) -> Self {
+        self.message.header.set_ra(on);
+        self
+    }
+
+    pub fn ad(mut self, on: bool) -> Self {
+        self.message.header.set_ad(on);
+        self
+    }
+
+    pub fn cd(mut self, on: bool) -> Self {
+        self.message.header.set_cd(on);
+        self
+    }
+
+    pub fn rcode(mut self, rcode: Rcode) -> Self {
+        self.message.header.set_rcode(rcode);
+        self
+    }
+
+    pub fn build(mut self) -> OwnedMessage {
+        self.message.header = self.message.counted_header();
+        self.message
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        rdata::RData,
+        test_util::{name, record},
+    };
+
+    #[test]
+    fn test_builder_encode_from_bytes_eq() {
+        let message = MessageBuilder::new(0x1234)
+            .question(name("example.com"), RecordType::A, RecordClass::In)
+            .qr(true)
+            .aa(true)
+            .rd(true)
+            .ra(true)
+            .answer(record("example.com", RData::A([192, 0, 2, 1].into())))
+            .answer(record("example.com", RData::A([192, 0, 2, 2].into())))
+            .authority(record("example.com", RData::Ns(name("ns1.example.com"))))
+            .additional(record("ns1.example.com", RData::A([192, 0, 2, 53].into())))
+            .build();
+
+        let mut buf = Vec::new();
+        message.encode(&mut buf);
+
+        let parsed = Message::from_bytes(&buf).unwrap();
+        assert_eq!(parsed.get_id(), 0x1234);
+        assert_eq!(parsed.get_ancount(), 2);
+        assert_eq!(parsed.get_nscount(), 1);
+        assert_eq!(parsed.get_arcount(), 1);
+        assert_eq!(OwnedMessage::from_bytes(&buf).unwrap(), message);
+    }
+
+    #[test]
+    fn test_counts_follow_sections() {
+        let mut message = MessageBuilder::new(1)
+            .question(name("example.com"), RecordType::Mx, RecordClass::In)
+            .build();
+        message.get_answers_mut().push(record(
+            "example.com",
+            RData::Mx {
+                preference: 10,
+                exchange: name("mail.example.com"),
+            },
+        ));
+
+        let mut buf = Vec::new();
+        message.encode(&mut buf);
+        assert_eq!(Message::from_bytes(&buf).unwrap().get_ancount(), 1);
+    }
+
+    #[test]
+    fn test_builder_edns() {
+        let mut edns = Edns::new(1232);
+        edns.set_rcode(Rcode::BadCookie);
+        let message = MessageBuilder::new(1)
+            .edns(Edns::new(512))
+            .edns(edns.clone())
+            .rcode(Rcode::BadCookie)
+            .build();
+
+        assert_eq!(message.get_additionals().len(), 1);
+        assert_eq!(message.get_edns(), Some(edns));
+        assert_eq!(message.get_rcode(), Rcode::BadCookie);
+    }
+
+    #[test]
+    fn test_message_display() {
+        let mut edns = Edns::new(1232);
+        edns.dnssec_ok = true;
+        let message = MessageBuilder::new(0x1234)
+            .question(name("example.com"), RecordType::A, RecordClass::In)
+            .qr(true)
+            .rd(true)
+            .ra(true)
+            .answer(record("example.com", RData::A([192, 0, 2, 1].into())))
+            .edns(edns)
+            .build();
+
+        let expected = "\
+;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4660
+;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1
+
+;; OPT PSEUDOSECTION:
+; EDNS: version: 0, flags: do; udp: 1232
+
+;; QUESTION SECTION:
+;example.com.\t\tIN\tA
+
+;; ANSWER SECTION:
+example.com.\t3600\tIN\tA\t192.0.2.1
+";
+        assert_eq!(message.to_string(), expected);
+    }
+
+    fn large_response() -> OwnedMessage {
+        let mut builder = MessageBuilder::new(1)
+            .question(name("example.com"), RecordType::Txt, RecordClass::In)
+            .qr(true);
+        for i in 0..4u8 {
+            builder = builder.answer(record("example.com", RData::Txt(vec![vec![i; 100]])));
+        }
+        builder = builder
+            .authority(record("example.com", RData::Ns(name("ns1.example.com"))))
+            .authority(record("example.com", RData::Ns(name("ns2.example.com"))));
+        for i in 0..3u8 {
+            builder =
+                builder.additional(record("ns1.example.com", RData::A([192, 0, 2, i].into())));
+        }
+        builder.edns(Edns::new(1232)).build()
+    }
+
+    #[test]
+    fn test_encode_with_limit_fits() {
+        let message = large_response();
+        let mut buf = Vec::new();
+        let omitted = message.encode_with_limit(&mut buf, 1232);
+        assert_eq!(omitted, Omitted::default());
+
+        let mut expected = Vec::new();
+        message.encode(&mut expected);
+        assert_eq!(buf.len(), expected.len());
+        assert_eq!(OwnedMessage::from_bytes(&buf).unwrap(), message);
+    }
+
+    #[test]
+    fn test_encode_with_limit_drops_additionals_first() {
+        let message = large_response();
+        let mut full = Vec::new();
+        message.encode(&mut full);
+
+        // Room for one more A record, but not the whole RRset
+        let mut buf = Vec::new();
+        let omitted = message.encode_with_limit(&mut buf, full.len() - 16);
+        assert_eq!(
+            omitted,
+            Omitted {
+                additionals: 3,
+                ..Omitted::default()
+            }
+        );
+        let output = OwnedMessage::from_bytes(&buf).unwrap();
+        assert!(!output.get_header().get_tc());
+        assert_eq!(output.get_authorities().len(), 2);
+        assert_eq!(output.get_additionals().len(), 1);
+        assert_eq!(output.get_edns(), message.get_edns());
+    }
+
+    #[test]
+    fn test_encode_with_limit_sets_tc() {
+        let message = large_response();
+        let mut buf = Vec::new();
+        let omitted = message.encode_with_limit(&mut buf, 480);
+        assert!(buf.len() <= 480);
+        assert_eq!(omitted.answers, 1);
+        assert_eq!(omitted.authorities, 2);
+        assert!(!omitted.edns);
+
+        let output = OwnedMessage::from_bytes(&buf).unwrap();
+        assert!(output.get_header().get_tc());
+        assert_eq!(output.get_answers(), &message.get_answers()[..3]);
+        assert!(output.get_edns().is_some());
+
+        // Only the header and question fit
+        let mut buf = Vec::new();
+        let omitted = message.encode_with_limit(&mut buf, 35);
+        assert!(omitted.edns);
+        let output = OwnedMessage::from_bytes(&buf).unwrap();
+        assert_eq!(output.get_questions(), message.get_questions());
+        assert!(output.get_answers().is_empty() && output.get_additionals().is_empty());
+    }
+
+    #[test]
+    fn test_max_udp_response_size() {
+        let request = MessageBuilder::new(1).build();
+        assert_eq!(request.max_udp_response_size(), 512);
+        let request = MessageBuilder::new(1).edns(Edns::new(4096)).build();
+        assert_eq!(request.max_udp_response_size(), 4096);
+        let request = MessageBuilder::new(1).edns(Edns::new(100)).build();
+        assert_eq!(request.max_udp_response_size(), 512);
+    }
+
+    #[test]
+    fn test_response_copies_request() {
+        let request = MessageBuilder::new(7)
+            .question(name("example.com"), RecordType::A, RecordClass::In)
+            .opcode(Opcode::Status)
+            .rd(true)
+            .build();
+        let response = MessageBuilder::response(&request).build();
+
+        assert_eq!(response.get_id(), 7);
+        assert_eq!(response.get_questions(), request.get_questions());
+        assert!(response.header.get_qr() && response.header.get_rd());
+        assert_eq!(response.header.get_opcode(), Opcode::Status);
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use crate::edns::{CLIENT_SUBNET, EdnsOption};
+
+/// Address family numbers used by the option (RFC 7871 6)
+const FAMILY_IPV4: u16 = 1;
+const FAMILY_IPV6: u16 = 2;
+
+/// Content of the EDNS Client Subnet option: the network of the client on whose behalf a
+/// query is sent, so authoritative servers can tailor their answer to it (RFC 7871 6)
+///
+/// Only built by [`ClientSubnet::new`] and [`ClientSubnet::from_option`], so the prefixes
+/// always fit the address and no bits past the source prefix are ever sent
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ClientSubnet {
+    /// Address with every bit past `source_prefix` cleared
+    address: IpAddr,
+    /// Leftmost bits of the address that are sent
+    source_prefix: u8,
+    /// Leftmost bits of the address the answer covers, 0 in queries
+    scope_prefix: u8,
+}
+
+impl ClientSubnet {
+    /// Subnet of `address` keeping `source_prefix` bits, capped to the address length
+    pub fn new(address: IpAddr, source_prefix: u8) -> Self {
+        let source_prefix = source_prefix.min(max_prefix(address));
+        Self {
+            address: truncate(address, source_prefix),
+            source_prefix,
+            scope_prefix: 0,
+        }
+    }
+
+    /// Reads a CLIENT-SUBNET option, returns `None` for other options or malformed subnets
+    ///
+    /// The address must be sent in exactly as many bytes as the source prefix needs, with the
+    /// bits past the prefix cleared (RFC 7871 6)
+    pub fn from_option(option: &EdnsOption) -> Option<Self> {
+        if option.code != CLIENT_SUBNET {
+            return None;
+        }
+        let (header, bytes) = option.data.split_first_chunk::<4>()?;
+        let [family_hi, family_lo, source_prefix, scope_prefix] = *header;
+        let address = match u16::from_be_bytes([family_hi, family_lo]) {
+            FAMILY_IPV4 => {
+                let mut octets = [0; 4];
+                octets.get_mut(..bytes.len())?.copy_from_slice(bytes);
+                IpAddr::from(octets)
+            }
+            FAMILY_IPV6 => {
+                let mut octets = [0; 16];
+                octets.get_mut(..bytes.len())?.copy_from_slice(bytes);
+                IpAddr::from(octets)
+            }
+            _ => return None,
+        };
+        let max = max_prefix(address);
+        if source_prefix > max || scope_prefix > max {
+            return None;
+        }
+        if bytes.len() != prefix_bytes(source_prefix) || truncate(address, source_prefix) != address
+        {
+            return None;
+        }
+        Some(Self {
+            address,
+            source_prefix,
+            scope_prefix,
+        })
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn source_prefix(&self) -> u8 {
+        self.source_prefix
+    }
+
+    pub fn scope_prefix(&self) -> u8 {
+        self.scope_prefix
+    }
+
+    pub fn to_option(&self) -> EdnsOption {
+        let family = match self.address {
+            IpAddr::V4(_) => FAMILY_IPV4,
+            IpAddr::V6(_) => FAMILY_IPV6,
+        };
+        let mut data = family.to_be_bytes().to_vec();
+        data.extend_from_slice(&[self.source_prefix, self.scope_prefix]);
+        data.extend_from_slice(&octets(self.address)[..prefix_bytes(self.source_prefix)]);
+        EdnsOption {
+            code: CLIENT_SUBNET,
+            data,
+        }
+    }
+
+    /// Tells whether `response` is the option of a response to a query sent with this one,
+    /// which must repeat its family, source prefix and address (RFC 7871 7.3)
+    pub fn is_answered_by(&self, response: &Self) -> bool {
+        self.address == response.address && self.source_prefix == response.source_prefix
+    }
+}
+
+impl fmt::Display for ClientSubnet {
+    /// Writes the subnet the way dig does, as address/source/scope
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}",
+            self.address, self.source_prefix, self.scope_prefix
+        )
+    }
+}
+
+fn max_prefix(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn prefix_bytes(prefix: u8) -> usize {
+    usize::from(prefix).div_ceil(8)
+}
+
+fn octets(address: IpAddr) -> Vec<u8> {
+    match address {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
+
+/// Clears the bits of `address` past the first `prefix` ones
+fn truncate(address: IpAddr, prefix: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            Ipv4Addr::from(u32::from(ip) & mask).into()
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            Ipv6Addr::from(u128::from(ip) & mask).into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn option(data: &[u8]) -> EdnsOption {
+        EdnsOption {
+            code: CLIENT_SUBNET,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_client_subnet_truncation() {
+        let subnet = ClientSubnet::new(IpAddr::from([192, 0, 2, 77]), 24);
+        assert_eq!(subnet.address, IpAddr::from([192, 0, 2, 0]));
+        assert_eq!(subnet.to_option().data, [0, 1, 24, 0, 192, 0, 2]);
+        assert_eq!(subnet.to_string(), "192.0.2.0/24/0");
+
+        // Partial bytes keep only their leading bits
+        let subnet = ClientSubnet::new(IpAddr::from([192, 0, 2, 255]), 25);
+        assert_eq!(subnet.address, IpAddr::from([192, 0, 2, 128]));
+        assert_eq!(subnet.to_option().data, [0, 1, 25, 0, 192, 0, 2, 128]);
+
+        let address: IpAddr = "2001:db8:1234:5678::1".parse().unwrap();
+        let subnet = ClientSubnet::new(address, 56);
+        assert_eq!(
+            subnet.address,
+            "2001:db8:1234:5600::".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            subnet.to_option().data,
+            [0, 2, 56, 0, 0x20, 0x01, 0x0d, 0xb8, 0x12, 0x34, 0x56]
+        );
+
+        // A zero prefix sends no address, prefixes are capped to the address length
+        let subnet = ClientSubnet::new(address, 0);
+        assert_eq!(subnet.to_option().data, [0, 2, 0, 0]);
+        let subnet = ClientSubnet::new(IpAddr::from([192, 0, 2, 77]), 40);
+        assert_eq!(subnet.source_prefix(), 32);
+        assert_eq!(subnet.address(), IpAddr::from([192, 0, 2, 77]));
+        assert_eq!(subnet.to_option().data, [0, 1, 32, 0, 192, 0, 2, 77]);
+        let subnet = ClientSubnet::new(address, 200);
+        assert_eq!(subnet.source_prefix(), 128);
+        assert_eq!(subnet.to_option().data.len(), 20);
+    }
+
+    #[test]
+    fn test_client_subnet_option() {
+        for subnet in [
+            ClientSubnet::new(IpAddr::from([198, 51, 100, 1]), 20),
+            ClientSubnet::new("2001:db8::1".parse().unwrap(), 48),
+            ClientSubnet::new("2001:db8::1".parse().unwrap(), 0),
+        ] {
+            assert_eq!(ClientSubnet::from_option(&subnet.to_option()), Some(subnet));
+        }
+
+        let response = ClientSubnet::from_option(&option(&[0, 1, 24, 16, 192, 0, 2])).unwrap();
+        assert_eq!(response.scope_prefix(), 16);
+        assert!(ClientSubnet::new(IpAddr::from([192, 0, 2, 1]), 24).is_answered_by(&response));
+        assert!(!ClientSubnet::new(IpAddr::from([192, 0, 3, 1]), 24).is_answered_by(&response));
+        assert!(!ClientSubnet::new(IpAddr::from([192, 0, 2, 1]), 23).is_answered_by(&response));
+
+        for data in [
+            // Unknown family, or too short for the header
+            &[0, 3, 0, 0][..],
+            &[0, 1, 0],
+            // Prefixes longer than the address
+            &[0, 1, 33, 0, 192, 0, 2, 1, 0],
+            &[0, 1, 24, 33, 192, 0, 2],
+            // Address not in exactly the bytes the prefix needs
+            &[0, 1, 24, 0, 192, 0],
+            &[0, 1, 24, 0, 192, 0, 2, 0],
+            // Bits past the prefix set
+            &[0, 1, 23, 0, 192, 0, 3],
+        ] {
+            assert_eq!(ClientSubnet::from_option(&option(data)), None, "{:?}", data);
+        }
+        let other = EdnsOption {
+            code: 10,
+            data: vec![0, 1, 24, 0, 192, 0, 2],
+        };
+        assert_eq!(ClientSubnet::from_option(&other), None);
+    }
+}
@@ -1,137 +1,336 @@
-use std::fmt;
+use std::collections::HashMap;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub struct CompressedName(pub Vec<Vec<u8>>);
+use crate::{error::ParseError, name::Name};
 
-impl CompressedName {
-    pub fn to_vec(&self) -> Vec<u8> {
-        self.0.iter().flat_map(|v| v.iter()).cloned().collect()
-    }
+pub const MAX_LABEL_LEN: u8 = 63;
+pub const MAX_NAME_LEN: usize = 255;
+
+pub fn is_pointer(p: u8) -> bool {
+    p & 0b11000000 == 0b11000000
 }
 
-pub fn compress_domain(domain: &str) -> Vec<u8> {
-    let mut buf: Vec<u8> = Vec::new();
-    for label in domain.split(".") {
-        let len = label.len();
-        if len > 63 {
-            panic!("Label too long");
+pub(crate) fn pointer_to_offset(a: u8, b: u8) -> usize {
+    (u16::from_be_bytes([a, b]) & 0x3FFF) as usize
+}
+
+/// Finds the end of the name starting at `offset`, including the terminating zero or pointer
+pub fn name_end(data: &[u8], offset: usize) -> Option<usize> {
+    let mut end = offset;
+    loop {
+        let len = *data.get(end)?;
+        if is_pointer(len) {
+            return (end + 2 <= data.len()).then_some(end + 2);
         }
-        buf.push(len as u8);
-        buf.extend_from_slice(label.as_bytes());
+        if len == 0 {
+            return Some(end + 1);
+        }
+        end += 1 + len as usize;
     }
-    buf.push(0);
-    buf
 }
 
-impl fmt::Display for CompressedName {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut first_label = true;
-
-        for part in self.0.iter() {
-            let mut i = 0usize;
-            while i < part.len() {
-                let len = part[i];
-                if len == 0 {
-                    break;
-                }
+/// Expands the name in `data`, following pointers into `message`
+///
+/// Every pointer has to point before the start of the labels containing it, which rules out
+/// loops, and the expanded name has to respect the 63 octet label and 255 octet name limits
+///
+/// # Errors
+///
+/// This function will return an error if the name is truncated, malformed or too long
+pub fn decompress(data: &[u8], message: &[u8]) -> Result<Name, ParseError> {
+    let mut wire = Vec::new();
+    walk_name(data, None, message, |segment| {
+        wire.extend_from_slice(segment)
+    })?;
+    Ok(Name::from_wire_unchecked(wire))
+}
 
-                if len & 0xC0 == 0xC0 {
-                    break;
-                }
+/// Checks the name at `offset` in `message` without allocating, returning the end of it
+///
+/// This applies the same rules as [`decompress`], and since the position of the name is
+/// known, its first pointer also has to point backwards
+pub fn check_name(message: &[u8], offset: usize) -> Result<usize, ParseError> {
+    let data = message.get(offset..).ok_or(ParseError::TruncatedName)?;
+    walk_name(data, Some(offset), message, |_| {})?;
+    name_end(message, offset).ok_or(ParseError::TruncatedName)
+}
 
-                let label_len = len as usize;
-                i += 1;
+/// Follows the name in `data`, calling `on_segment` with the labels between pointers, the
+/// last one including the terminating zero
+///
+/// `start` is the offset of `data` in `message` if it is known
+fn walk_name<'a>(
+    data: &'a [u8],
+    start: Option<usize>,
+    message: &'a [u8],
+    mut on_segment: impl FnMut(&'a [u8]),
+) -> Result<(), ParseError> {
+    let mut segment = data;
+    let mut segment_start = start;
+    let mut name_len = 0;
 
-                if i + label_len > part.len() {
-                    break;
+    loop {
+        let mut i = 0;
+        loop {
+            let len = *segment.get(i).ok_or(ParseError::TruncatedName)?;
+            if is_pointer(len) {
+                let next = *segment.get(i + 1).ok_or(ParseError::TruncatedName)?;
+                let target = pointer_to_offset(len, next);
+                if segment_start.is_some_and(|start| target >= start) {
+                    return Err(ParseError::CompressionLoop);
                 }
-
-                if !first_label {
-                    write!(f, ".")?;
+                if target >= message.len() {
+                    return Err(ParseError::InvalidPointer);
                 }
-
-                for &b in &part[i..i + label_len] {
-                    write!(f, "{}", b as char)?;
+                if i > 0 {
+                    on_segment(&segment[..i]);
                 }
+                segment = &message[target..];
+                segment_start = Some(target);
+                break;
+            }
+            if len > MAX_LABEL_LEN {
+                return Err(ParseError::LabelTooLong);
+            }
 
-                first_label = false;
-                i += label_len;
+            name_len += 1 + len as usize;
+            if name_len > MAX_NAME_LEN {
+                return Err(ParseError::NameTooLong);
+            }
+            if len == 0 {
+                on_segment(&segment[..=i]);
+                return Ok(());
             }
+            i += 1 + len as usize;
         }
+    }
+}
 
-        if first_label {
-            write!(f, ".")?;
-        }
+/// Iterates over the labels of a name in `message`, following pointers without allocating
+///
+/// The name should have been checked by [`check_name`], iteration stops early on anything
+/// malformed rather than failing
+#[derive(Debug, Clone)]
+pub struct Labels<'a> {
+    message: &'a [u8],
+    offset: usize,
+    jumps: usize,
+}
 
-        Ok(())
+impl<'a> Labels<'a> {
+    pub fn new(message: &'a [u8], offset: usize) -> Self {
+        Self {
+            message,
+            offset,
+            jumps: 0,
+        }
     }
 }
 
-pub fn is_pointer(p: u8) -> bool {
-    p & 0b11000000 == 0b11000000
+impl<'a> Iterator for Labels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let len = *self.message.get(self.offset)?;
+            if is_pointer(len) {
+                // A checked name can't have more pointers than labels
+                if self.jumps > MAX_NAME_LEN / 2 {
+                    return None;
+                }
+                let next = *self.message.get(self.offset + 1)?;
+                self.offset = pointer_to_offset(len, next);
+                self.jumps += 1;
+                continue;
+            }
+            if len == 0 || len > MAX_LABEL_LEN {
+                return None;
+            }
+            let start = self.offset + 1;
+            let label = self.message.get(start..start + len as usize)?;
+            self.offset = start + len as usize;
+            return Some(label);
+        }
+    }
 }
 
-fn pointer_to_offset(a: u8, b: u8) -> usize {
-    (u16::from_be_bytes([a, b]) & 0x3FF) as usize
+/// Remembers where names were written in a message, so later names sharing a suffix can be
+/// replaced by a pointer (RFC 1035 4.1.4)
+///
+/// Offsets are relative to the start of the buffer, which must be the start of the message
+#[derive(Debug, Default)]
+pub struct NameCompressor {
+    offsets: HashMap<Vec<u8>, u16>,
 }
 
-pub fn decompress(data: &[u8], message: &[u8]) -> CompressedName {
-    let mut current: Vec<Vec<u8>> = Vec::new();
-    let len = data.len();
-    if len < 2 || !is_pointer(data[len - 2]) {
-        current.push(data.to_vec());
-    } else {
-        // contains pointer
-        if len > 2 {
-            current.push(data[..len - 2].to_vec());
+impl NameCompressor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the uncompressed name `name` to `buf`, replacing the longest suffix already
+    /// written with a pointer
+    ///
+    /// Names that are already compressed or malformed are written as is
+    pub fn write_name(&mut self, name: &[u8], buf: &mut Vec<u8>) {
+        let mut starts = Vec::new();
+        let mut i = 0;
+        while i < name.len() && name[i] != 0 {
+            if is_pointer(name[i]) {
+                buf.extend_from_slice(name);
+                return;
+            }
+            starts.push(i);
+            i += 1 + name[i] as usize;
         }
-        let offset = pointer_to_offset(data[len - 2], data[len - 1]);
-        let mut end = offset;
-        while end < message.len() && message[end] != 0 {
-            if is_pointer(message[end]) {
-                end += 1;
-                break;
+        if i + 1 != name.len() {
+            buf.extend_from_slice(name);
+            return;
+        }
+
+        let base = buf.len();
+        for (n, &start) in starts.iter().enumerate() {
+            let key = name[start..].to_ascii_lowercase();
+            if let Some(&offset) = self.offsets.get(&key) {
+                buf.extend_from_slice(&name[..start]);
+                buf.extend_from_slice(&(offset | 0xC000).to_be_bytes());
+                self.remember(&starts[..n], name, base);
+                return;
             }
-            let label_len = message[end] as usize;
-            end += 1 + label_len;
         }
-        let pointer_value = decompress(&message[offset..=end], message);
-        current.extend_from_slice(&pointer_value.0);
+        buf.extend_from_slice(name);
+        self.remember(&starts, name, base);
     }
 
-    CompressedName(current)
+    fn remember(&mut self, starts: &[usize], name: &[u8], base: usize) {
+        for &start in starts {
+            // Pointers can only address the first 16K of a message
+            if base + start >= 0x4000 {
+                break;
+            }
+            self.offsets
+                .entry(name[start..].to_ascii_lowercase())
+                .or_insert((base + start) as u16);
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn wire(domain: &str) -> Vec<u8> {
+        domain.parse::<Name>().unwrap().as_wire().to_vec()
+    }
+
+    #[test]
+    fn test_name_compressor() {
+        let mut compressor = NameCompressor::new();
+        let mut buf = vec![0; 12];
+        compressor.write_name(&wire("www.example.com"), &mut buf);
+        compressor.write_name(&wire("mail.EXAMPLE.com"), &mut buf);
+        compressor.write_name(&wire("www.example.com"), &mut buf);
+        compressor.write_name(&wire("org"), &mut buf);
+
+        let mut expected = vec![0; 12];
+        expected.extend_from_slice(&wire("www.example.com"));
+        expected.extend_from_slice(&[4, b'm', b'a', b'i', b'l', 0b11000000, 16]);
+        expected.extend_from_slice(&[0b11000000, 12]);
+        expected.extend_from_slice(&wire("org"));
+        assert_eq!(buf, expected);
+
+        let name = decompress(&buf[29..36], &buf).unwrap();
+        assert_eq!(name.as_wire(), wire("mail.example.com"));
+    }
+
     #[test]
-    fn test_compress_domain_google_dns() {
-        let output = compress_domain("dns.google.com");
-        let expected = [
-            3, b'd', b'n', b's', 6, b'g', b'o', b'o', b'g', b'l', b'e', 3, b'c', b'o', b'm', 0,
+    fn test_decompress_pointer_loop() {
+        // Pointer to itself
+        let message = [0b11000000, 0];
+        assert!(matches!(
+            decompress(&message, &message),
+            Err(ParseError::CompressionLoop)
+        ));
+
+        // Two names pointing to each other
+        let message = [1, b'a', 0b11000000, 4, 1, b'b', 0b11000000, 0];
+        assert!(matches!(
+            decompress(&message[4..], &message),
+            Err(ParseError::CompressionLoop)
+        ));
+
+        // Forward pointer
+        let message = [1, b'a', 0b11000000, 4, 1, b'b', 0];
+        assert!(matches!(
+            decompress(&message[..4], &message[..4]),
+            Err(ParseError::InvalidPointer)
+        ));
+        assert!(matches!(
+            decompress(&[0b11000000, 2], &message[..4]),
+            Err(ParseError::CompressionLoop)
+        ));
+    }
+
+    #[test]
+    fn test_decompress_limits() {
+        let mut message = vec![64];
+        message.extend_from_slice(&[b'a'; 64]);
+        message.push(0);
+        assert!(matches!(
+            decompress(&message, &message),
+            Err(ParseError::LabelTooLong)
+        ));
+
+        let mut message = Vec::new();
+        for _ in 0..64 {
+            message.extend_from_slice(&[3, b'a', b'b', b'c']);
+        }
+        message.push(0);
+        assert!(matches!(
+            decompress(&message, &message),
+            Err(ParseError::NameTooLong)
+        ));
+
+        assert!(matches!(
+            decompress(&[3, b'a', b'b'], &[]),
+            Err(ParseError::TruncatedName)
+        ));
+        assert!(matches!(
+            decompress(&[0b11000000, 40], &[0; 12]),
+            Err(ParseError::InvalidPointer)
+        ));
+    }
+
+    #[test]
+    fn test_check_name_and_labels() {
+        let message = [
+            1, b'f', 3, b'i', b's', b'i', 4, b'a', b'r', b'p', b'a', 0, 3, b'f', b'o', b'o',
+            0b11000000, 0, 0b11000000, 18,
         ];
-        assert_eq!(output.as_slice(), expected);
+        assert_eq!(check_name(&message, 12), Ok(18));
+        let labels: Vec<&[u8]> = Labels::new(&message, 12).collect();
+        assert_eq!(labels, [&b"foo"[..], b"f", b"isi", b"arpa"]);
+
+        // The first pointer of a name at a known offset can't point forwards either
+        assert_eq!(check_name(&message, 18), Err(ParseError::CompressionLoop));
+        assert_eq!(check_name(&message, 30), Err(ParseError::TruncatedName));
     }
 
     #[test]
-    fn test_compress_domain_nrk_no() {
-        let output = compress_domain("nrk.no");
-        let expected = [3, b'n', b'r', b'k', 2, b'n', b'o', 0];
-        assert_eq!(output.as_slice(), expected);
+    fn test_name_end() {
+        let data = [3, b'f', b'o', b'o', 0b11000000, 0, 1, 2];
+        assert_eq!(name_end(&data, 0), Some(6));
+        assert_eq!(name_end(&[3, b'f', b'o', b'o', 0], 0), Some(5));
+        assert_eq!(name_end(&[3, b'f', b'o'], 0), None);
     }
 
     #[test]
-    fn test_decompress_domain() {
+    fn test_dewire() {
         let message = [
             1, b'f', 3, b'i', b's', b'i', 4, b'a', b'r', b'p', b'a', 0, 3, b'f', b'o', b'o',
             0b11000000, 0, 0,
         ];
         let pointer = &message[12..18];
-        let output = decompress(pointer, &message);
-        let expected = CompressedName(vec![message[12..12 + 4].to_vec(), message[..12].to_vec()]);
-
-        assert_eq!(output, expected);
+        let output = decompress(pointer, &message).unwrap();
+        assert_eq!(output.to_string(), "foo.f.isi.arpa.");
     }
 }
@@ -0,0 +1,193 @@
+use std::{collections::HashMap, fmt, net::IpAddr};
+
+use rand::random;
+use ring::hmac;
+
+use crate::edns::{COOKIE, Edns, EdnsOption};
+
+const CLIENT_COOKIE_LEN: usize = 8;
+const MIN_SERVER_COOKIE_LEN: usize = 8;
+const MAX_SERVER_COOKIE_LEN: usize = 32;
+
+/// Content of the COOKIE option: the client cookie, followed by the server cookie once the
+/// server has sent one (RFC 7873 4)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cookie {
+    pub client: [u8; CLIENT_COOKIE_LEN],
+    /// Empty, or 8 to 32 bytes
+    pub server: Vec<u8>,
+}
+
+/// Client secret and the server cookies learned from each server, which protect queries
+/// against off-path spoofing (RFC 7873 5)
+///
+/// The client cookie sent to a server is derived from the secret and the server address, so
+/// servers can't link the queries of a client to each other
+pub struct CookieJar {
+    secret: [u8; 16],
+    servers: HashMap<IpAddr, Vec<u8>>,
+}
+
+impl Cookie {
+    /// Reads a COOKIE option, returns `None` for other options or malformed cookies
+    pub fn from_option(option: &EdnsOption) -> Option<Self> {
+        if option.code != COOKIE {
+            return None;
+        }
+        let (client, server) = option.data.split_first_chunk::<CLIENT_COOKIE_LEN>()?;
+        if !server.is_empty()
+            && !(MIN_SERVER_COOKIE_LEN..=MAX_SERVER_COOKIE_LEN).contains(&server.len())
+        {
+            return None;
+        }
+        Some(Self {
+            client: *client,
+            server: server.to_vec(),
+        })
+    }
+
+    pub fn to_option(&self) -> EdnsOption {
+        let mut data = self.client.to_vec();
+        data.extend_from_slice(&self.server);
+        EdnsOption { code: COOKIE, data }
+    }
+}
+
+impl CookieJar {
+    /// Starts with a random client secret and no server cookies
+    pub fn new() -> Self {
+        Self {
+            secret: random(),
+            servers: HashMap::new(),
+        }
+    }
+
+    /// Returns the cookie to send to `server`, with the last server cookie it gave us
+    pub fn cookie_for(&self, server: IpAddr) -> Cookie {
+        Cookie {
+            client: self.client_cookie(server),
+            server: self.servers.get(&server).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Checks the cookie in the OPT record of a response from `server`, remembering its server
+    /// cookie, and returns false if the response must be discarded (RFC 7873 5.3)
+    ///
+    /// Responses must echo the client cookie sent to the server. Servers that never sent a
+    /// cookie may not support them, but once one did, its responses without a cookie are
+    /// discarded too
+    pub fn accept(&mut self, server: IpAddr, edns: Option<&Edns>) -> bool {
+        let Some(option) = edns.and_then(|edns| edns.get_option(COOKIE)) else {
+            return !self.servers.contains_key(&server);
+        };
+        match Cookie::from_option(option) {
+            Some(cookie) if cookie.client == self.client_cookie(server) => {
+                if !cookie.server.is_empty() {
+                    self.servers.insert(server, cookie.server);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Client cookie for `server`, HMAC-SHA256 of its address truncated to 64 bits
+    /// (RFC 7873 appendix A.2)
+    fn client_cookie(&self, server: IpAddr) -> [u8; CLIENT_COOKIE_LEN] {
+        let address = match server {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &self.secret);
+        let tag = hmac::sign(&key, &address);
+        let mut cookie = [0; CLIENT_COOKIE_LEN];
+        cookie.copy_from_slice(&tag.as_ref()[..CLIENT_COOKIE_LEN]);
+        cookie
+    }
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CookieJar {
+    /// Leaves out the secret
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CookieJar")
+            .field("servers", &self.servers)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn edns(cookie: &Cookie) -> Edns {
+        let mut edns = Edns::new(1232);
+        edns.set_cookie(Some(cookie.clone()));
+        edns
+    }
+
+    #[test]
+    fn test_cookie_option() {
+        let cookie = Cookie {
+            client: [1; 8],
+            server: vec![2; 16],
+        };
+        let option = cookie.to_option();
+        assert_eq!(option.data.len(), 24);
+        assert_eq!(Cookie::from_option(&option), Some(cookie));
+
+        // Server cookies are 8 to 32 bytes long
+        for (len, valid) in [(0, true), (7, false), (8, true), (32, true), (33, false)] {
+            let option = EdnsOption {
+                code: COOKIE,
+                data: vec![0; 8 + len],
+            };
+            assert_eq!(Cookie::from_option(&option).is_some(), valid);
+        }
+        let option = EdnsOption {
+            code: COOKIE,
+            data: vec![0; 7],
+        };
+        assert_eq!(Cookie::from_option(&option), None);
+    }
+
+    #[test]
+    fn test_cookie_jar() {
+        let mut jar = CookieJar::new();
+        let server = IpAddr::from([192, 0, 2, 1]);
+        let other = IpAddr::from([192, 0, 2, 2]);
+
+        let cookie = jar.cookie_for(server);
+        assert!(cookie.server.is_empty());
+        assert_eq!(jar.cookie_for(server), cookie);
+        assert_ne!(jar.cookie_for(other).client, cookie.client);
+        assert_ne!(CookieJar::new().cookie_for(server).client, cookie.client);
+
+        // Servers without cookie support are accepted until they send one
+        assert!(jar.accept(server, None));
+        assert!(jar.accept(server, Some(&Edns::new(1232))));
+
+        let answered = Cookie {
+            client: cookie.client,
+            server: vec![7; 8],
+        };
+        assert!(jar.accept(server, Some(&edns(&answered))));
+        assert_eq!(jar.cookie_for(server), answered);
+        assert!(!jar.accept(server, None));
+        assert!(jar.accept(other, None));
+
+        // Forged responses don't know the client cookie
+        let forged = Cookie {
+            client: [0; 8],
+            server: vec![8; 8],
+        };
+        assert!(!jar.accept(server, Some(&edns(&forged))));
+        assert!(!jar.accept(other, Some(&edns(&answered))));
+        assert_eq!(jar.cookie_for(server), answered);
+    }
+}
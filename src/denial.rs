@@ -0,0 +1,602 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use ring::digest;
+
+use crate::{
+    dnssec::{Nsec, Nsec3},
+    encoding::from_base32hex,
+    name::Name,
+    rdata::RData,
+    record::{Record, RecordType},
+};
+
+/// SHA-1, the only hash algorithm defined for NSEC3
+const NSEC3_SHA1: u8 = 1;
+
+/// NSEC3 records with more iterations are treated as insecure (RFC 9276 3.2)
+pub const MAX_NSEC3_ITERATIONS: u16 = 150;
+
+/// Outcome of checking a denial of existence against the NSEC or NSEC3 records of a zone
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Proof {
+    /// The records prove the denial
+    Proven,
+    /// The records can't rule out an unsigned delegation, because it could hide in an
+    /// opt-out span, or the NSEC3 hashes are too expensive to check
+    Insecure,
+    /// The records don't prove the denial
+    Failed,
+}
+
+/// Hashes `name` the way the owner names of NSEC3 records are (RFC 5155 5)
+pub fn nsec3_hash(name: &Name, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let mut hash = name.to_lowercase().as_wire().to_vec();
+    for _ in 0..=iterations {
+        hash.extend_from_slice(salt);
+        hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &hash)
+            .as_ref()
+            .to_vec();
+    }
+    hash
+}
+
+/// Proves that `qname` doesn't exist in `zone`, and that no wildcard could have answered
+/// instead (RFC 4035 5.4, RFC 5155 8.4)
+pub fn prove_nxdomain(qname: &Name, zone: &Name, records: &[Record]) -> Proof {
+    match Chain::new(zone, records) {
+        Chain::Nsec(nsecs) => {
+            let Some(covering) = nsecs.iter().find(|n| n.covers(qname)) else {
+                return Proof::Failed;
+            };
+            let encloser = covering.closest_encloser(qname);
+            match wildcard(&encloser) {
+                Some(wildcard) if nsecs.iter().any(|n| n.covers(&wildcard)) => Proof::Proven,
+                _ => Proof::Failed,
+            }
+        }
+        Chain::Nsec3(nsec3s) => {
+            let Some((encloser, next_closer)) = nsec3s.closest_encloser(qname, zone) else {
+                return Proof::Failed;
+            };
+            match wildcard(&encloser) {
+                Some(wildcard) if nsec3s.covering(&wildcard).is_some() => {
+                    opt_out_proof(next_closer)
+                }
+                _ => Proof::Failed,
+            }
+        }
+        Chain::Expensive => Proof::Insecure,
+        Chain::Inconsistent => Proof::Failed,
+    }
+}
+
+/// Proves that `qname` exists in `zone` without records of type `qtype`, directly or through
+/// a wildcard (RFC 4035 5.4, RFC 5155 8.5 to 8.7)
+pub fn prove_nodata(qname: &Name, qtype: RecordType, zone: &Name, records: &[Record]) -> Proof {
+    match Chain::new(zone, records) {
+        Chain::Nsec(nsecs) => {
+            if let Some(matching) = nsecs.iter().find(|n| n.owner == qname) {
+                return proven(denies_type(&matching.nsec.types, qtype));
+            }
+            let Some(covering) = nsecs.iter().find(|n| n.covers(qname)) else {
+                return Proof::Failed;
+            };
+            // Empty non-terminal, the next name in the zone is below it
+            if covering.nsec.next.is_subdomain_of(qname) {
+                return Proof::Proven;
+            }
+            let encloser = covering.closest_encloser(qname);
+            let matching = wildcard(&encloser)
+                .and_then(|wildcard| nsecs.iter().find(|n| n.owner == &wildcard));
+            proven(matching.is_some_and(|n| denies_type(&n.nsec.types, qtype)))
+        }
+        Chain::Nsec3(nsec3s) => {
+            if let Some(matching) = nsec3s.matching(qname) {
+                return proven(denies_type(&matching.nsec3.types, qtype));
+            }
+            let Some((encloser, next_closer)) = nsec3s.closest_encloser(qname, zone) else {
+                return Proof::Failed;
+            };
+            // Delegations in opt-out spans have no NSEC3 record to deny the DS with
+            if qtype == RecordType::Ds && next_closer.nsec3.is_opt_out() {
+                return Proof::Insecure;
+            }
+            let matching = wildcard(&encloser).and_then(|wildcard| nsec3s.matching(&wildcard));
+            proven(matching.is_some_and(|n| denies_type(&n.nsec3.types, qtype)))
+        }
+        Chain::Expensive => Proof::Insecure,
+        Chain::Inconsistent => Proof::Failed,
+    }
+}
+
+/// Proves that the delegation from `zone` to `child` has no DS records (RFC 4035 5.2,
+/// RFC 5155 8.9)
+///
+/// Either outcome other than [`Proof::Failed`] makes the child zone insecure
+pub fn prove_unsigned_delegation(child: &Name, zone: &Name, records: &[Record]) -> Proof {
+    let is_unsigned_cut = |types: &[RecordType]| {
+        types.contains(&RecordType::Ns)
+            && !types.contains(&RecordType::Ds)
+            && !types.contains(&RecordType::Soa)
+    };
+    match Chain::new(zone, records) {
+        Chain::Nsec(nsecs) => proven(
+            nsecs
+                .iter()
+                .any(|n| n.owner == child && is_unsigned_cut(&n.nsec.types)),
+        ),
+        Chain::Nsec3(nsec3s) => {
+            if let Some(matching) = nsec3s.matching(child) {
+                return proven(is_unsigned_cut(&matching.nsec3.types));
+            }
+            match nsec3s.closest_encloser(child, zone) {
+                Some((_, next_closer)) if next_closer.nsec3.is_opt_out() => Proof::Insecure,
+                _ => Proof::Failed,
+            }
+        }
+        Chain::Expensive => Proof::Insecure,
+        Chain::Inconsistent => Proof::Failed,
+    }
+}
+
+/// Proves that `qname` doesn't exist, so an answer could be expanded from the wildcard below
+/// `encloser` (RFC 4035 5.3.4, RFC 5155 8.8)
+pub fn prove_wildcard_expansion(
+    qname: &Name,
+    encloser: &Name,
+    zone: &Name,
+    records: &[Record],
+) -> Proof {
+    match Chain::new(zone, records) {
+        Chain::Nsec(nsecs) => proven(
+            nsecs
+                .iter()
+                .any(|n| n.covers(qname) && &n.closest_encloser(qname) == encloser),
+        ),
+        Chain::Nsec3(nsec3s) => {
+            let next_closer = qname.suffix(encloser.label_count() + 1);
+            proven(nsec3s.covering(&next_closer).is_some())
+        }
+        Chain::Expensive => Proof::Insecure,
+        Chain::Inconsistent => Proof::Failed,
+    }
+}
+
+/// Denial records of a zone, NSEC3 taking precedence when both are present
+enum Chain<'a> {
+    Nsec(Vec<NsecRecord<'a>>),
+    Nsec3(Nsec3Chain<'a>),
+    /// NSEC3 records with more than [`MAX_NSEC3_ITERATIONS`]
+    Expensive,
+    /// NSEC3 records with different salts or iterations, which can't be from the same chain
+    Inconsistent,
+}
+
+struct NsecRecord<'a> {
+    owner: &'a Name,
+    nsec: &'a Nsec,
+}
+
+struct Nsec3Record<'a> {
+    /// Hash decoded from the first label of the owner name
+    hash: Vec<u8>,
+    nsec3: &'a Nsec3,
+}
+
+/// NSEC3 records sharing the salt and iterations, so every name looked up is only hashed
+/// once, whatever the number of records
+struct Nsec3Chain<'a> {
+    salt: &'a [u8],
+    iterations: u16,
+    records: Vec<Nsec3Record<'a>>,
+    hashes: RefCell<HashMap<Name, Vec<u8>>>,
+}
+
+impl<'a> Chain<'a> {
+    fn new(zone: &Name, records: &'a [Record]) -> Self {
+        let mut nsecs = Vec::new();
+        let mut nsec3s = Vec::new();
+        for record in records.iter().filter(|r| r.name.is_subdomain_of(zone)) {
+            match &record.rdata {
+                RData::Nsec(nsec) => nsecs.push(NsecRecord {
+                    owner: &record.name,
+                    nsec,
+                }),
+                // Records with unknown hash algorithms are ignored (RFC 5155 8.1)
+                RData::Nsec3(nsec3) if nsec3.hash_algorithm == NSEC3_SHA1 => {
+                    if record.name.parent().as_ref() != Some(zone) {
+                        continue;
+                    }
+                    let Some(label) = record.name.labels().next() else {
+                        continue;
+                    };
+                    let hash = std::str::from_utf8(label).ok().and_then(from_base32hex);
+                    if let Some(hash) = hash {
+                        nsec3s.push(Nsec3Record { hash, nsec3 });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let Some(first) = nsec3s.first().map(|n| n.nsec3) else {
+            return Chain::Nsec(nsecs);
+        };
+        if nsec3s
+            .iter()
+            .any(|n| n.nsec3.iterations > MAX_NSEC3_ITERATIONS)
+        {
+            Chain::Expensive
+        } else if nsec3s
+            .iter()
+            .any(|n| n.nsec3.salt != first.salt || n.nsec3.iterations != first.iterations)
+        {
+            Chain::Inconsistent
+        } else {
+            Chain::Nsec3(Nsec3Chain {
+                salt: &first.salt,
+                iterations: first.iterations,
+                records: nsec3s,
+                hashes: RefCell::new(HashMap::new()),
+            })
+        }
+    }
+}
+
+impl NsecRecord<'_> {
+    /// Returns true if `name` sorts between the owner and the next name, so it doesn't exist
+    fn covers(&self, name: &Name) -> bool {
+        let (owner, next) = (self.owner, &self.nsec.next);
+        // The last NSEC of a zone points back to the apex
+        let between = if owner < next {
+            owner < name && name < next
+        } else {
+            owner < name || name < next
+        };
+        // Records at zone cuts don't deny anything below them (RFC 6840 4.1)
+        between && !(name.is_subdomain_of(owner) && is_cut(&self.nsec.types))
+    }
+
+    /// Returns the deepest existing ancestor of `name`, which it covers
+    fn closest_encloser(&self, name: &Name) -> Name {
+        let owner = name.common_ancestor(self.owner);
+        let next = name.common_ancestor(&self.nsec.next);
+        if owner.label_count() > next.label_count() {
+            owner
+        } else {
+            next
+        }
+    }
+}
+
+impl Nsec3Record<'_> {
+    /// Returns true if `hash` sorts between the owner and the next hash
+    fn covers(&self, hash: &[u8]) -> bool {
+        let (owner, next) = (&self.hash[..], &self.nsec3.next_hashed[..]);
+        if owner < next {
+            owner < hash && hash < next
+        } else {
+            owner < hash || hash < next
+        }
+    }
+}
+
+impl<'a> Nsec3Chain<'a> {
+    fn hash(&self, name: &Name) -> Vec<u8> {
+        self.hashes
+            .borrow_mut()
+            .entry(name.clone())
+            .or_insert_with(|| nsec3_hash(name, self.salt, self.iterations))
+            .clone()
+    }
+
+    /// Returns the record whose owner is the hash of `name`
+    fn matching(&self, name: &Name) -> Option<&Nsec3Record<'a>> {
+        let hash = self.hash(name);
+        self.records.iter().find(|n| n.hash == hash)
+    }
+
+    /// Returns the record whose span covers the hash of `name`, so it doesn't exist
+    fn covering(&self, name: &Name) -> Option<&Nsec3Record<'a>> {
+        let hash = self.hash(name);
+        self.records.iter().find(|n| n.covers(&hash))
+    }
+
+    /// Finds the deepest ancestor of `qname` with a matching record, and the record covering
+    /// the next closer name, the child of that ancestor on the way to `qname` (RFC 5155 8.3)
+    fn closest_encloser(&self, qname: &Name, zone: &Name) -> Option<(Name, &Nsec3Record<'a>)> {
+        let mut next_closer = qname.clone();
+        while next_closer != *zone && next_closer.is_subdomain_of(zone) {
+            let encloser = next_closer.parent()?;
+            if let Some(matching) = self.matching(&encloser) {
+                // The parent side of a zone cut can't prove anything below it
+                if is_cut(&matching.nsec3.types) {
+                    return None;
+                }
+                let covering = self.covering(&next_closer)?;
+                return Some((encloser, covering));
+            }
+            next_closer = encloser;
+        }
+        None
+    }
+}
+
+/// Returns the wildcard directly below `encloser`
+fn wildcard(encloser: &Name) -> Option<Name> {
+    Name::from_labels(std::iter::once(&b"*"[..]).chain(encloser.labels())).ok()
+}
+
+/// Returns true for the type bitmap of a delegation or DNAME
+fn is_cut(types: &[RecordType]) -> bool {
+    types.contains(&RecordType::Dname)
+        || (types.contains(&RecordType::Ns) && !types.contains(&RecordType::Soa))
+}
+
+/// Returns true if a type bitmap proves that there are no records of type `qtype`, nor a
+/// CNAME that should have been followed instead
+fn denies_type(types: &[RecordType], qtype: RecordType) -> bool {
+    if types.contains(&qtype) || types.contains(&RecordType::Cname) {
+        return false;
+    }
+    if qtype == RecordType::Ds {
+        // DS records are denied by the parent, not at the apex of the child
+        !types.contains(&RecordType::Soa)
+    } else {
+        // The parent side of a delegation only knows about the DS records
+        !is_cut(types)
+    }
+}
+
+/// Names covered by an opt-out span may be unsigned delegations
+fn opt_out_proof(covering: &Nsec3Record) -> Proof {
+    if covering.nsec3.is_opt_out() {
+        Proof::Insecure
+    } else {
+        Proof::Proven
+    }
+}
+
+fn proven(condition: bool) -> Proof {
+    if condition {
+        Proof::Proven
+    } else {
+        Proof::Failed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        encoding::to_base32hex,
+        test_util::{name, record},
+    };
+
+    /// Names of `example.` with their types, sorted in canonical order: a signed delegation,
+    /// an unsigned one, and a wildcard below an empty non-terminal
+    fn zone() -> Vec<(Name, Vec<RecordType>)> {
+        use RecordType::*;
+        [
+            ("example", vec![Soa, Ns, Dnskey]),
+            ("a.example", vec![Ns, Ds]),
+            ("c.example", vec![Ns]),
+            ("mail.example", vec![A]),
+            ("*.w.example", vec![Mx]),
+            ("x.w.example", vec![A]),
+        ]
+        .into_iter()
+        .map(|(domain, types)| (name(domain), types))
+        .collect()
+    }
+
+    fn nsec_chain() -> Vec<Record> {
+        let zone = zone();
+        zone.iter()
+            .enumerate()
+            .map(|(i, (owner, types))| {
+                let next = zone[(i + 1) % zone.len()].0.clone();
+                let mut types = types.clone();
+                types.extend([RecordType::Rrsig, RecordType::Nsec]);
+                record(&owner.to_string(), RData::Nsec(Nsec { next, types }))
+            })
+            .collect()
+    }
+
+    /// Opt-out chains leave out the unsigned delegation, and every chain has the empty
+    /// non-terminal `w.example.`
+    fn nsec3_chain(iterations: u16, opt_out: bool) -> Vec<Record> {
+        let salt = vec![0xaa, 0xbb, 0xcc, 0xdd];
+        let mut hashed: Vec<_> = zone()
+            .into_iter()
+            .chain([(name("w.example"), vec![])])
+            .filter(|(owner, _)| !opt_out || owner != &name("c.example"))
+            .map(|(owner, types)| (nsec3_hash(&owner, &salt, iterations), types))
+            .collect();
+        hashed.sort_by(|a, b| a.0.cmp(&b.0));
+        (0..hashed.len())
+            .map(|i| {
+                let (hash, types) = &hashed[i];
+                let owner = format!("{}.example", to_base32hex(hash));
+                let nsec3 = Nsec3 {
+                    hash_algorithm: NSEC3_SHA1,
+                    flags: u8::from(opt_out),
+                    iterations,
+                    salt: salt.clone(),
+                    next_hashed: hashed[(i + 1) % hashed.len()].0.clone(),
+                    types: types.clone(),
+                };
+                record(&owner, RData::Nsec3(nsec3))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nsec3_hash() {
+        // RFC 5155 appendix A
+        let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+        for (domain, hash) in [
+            ("example", "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom"),
+            ("a.example", "35mthgpgcu1qg68fab165klnsnk3dpvl"),
+            ("*.w.example", "r53bq7cc2uvmubfu5ocmm6pers9tk9en"),
+            ("X.W.EXAMPLE", "b4um86eghhds6nea196smvmlo4ors995"),
+        ] {
+            assert_eq!(to_base32hex(&nsec3_hash(&name(domain), &salt, 12)), hash);
+        }
+    }
+
+    #[test]
+    fn test_nsec_proofs() {
+        let chain = nsec_chain();
+        let zone = name("example");
+        let nxdomain = |domain: &str| prove_nxdomain(&name(domain), &zone, &chain);
+        let nodata = |domain: &str, rtype| prove_nodata(&name(domain), rtype, &zone, &chain);
+
+        assert_eq!(nxdomain("nope.example"), Proof::Proven);
+        assert_eq!(nxdomain("x.example"), Proof::Proven);
+        assert_eq!(nxdomain("mail.example"), Proof::Failed);
+        // Covered by the NSEC of a delegation, which the parent knows nothing below of
+        assert_eq!(nxdomain("www.c.example"), Proof::Failed);
+        // The record covering the wildcard is missing
+        assert_eq!(
+            prove_nxdomain(&name("nope.example"), &zone, &chain[3..4]),
+            Proof::Failed
+        );
+
+        assert_eq!(nodata("mail.example", RecordType::Aaaa), Proof::Proven);
+        assert_eq!(nodata("mail.example", RecordType::A), Proof::Failed);
+        assert_eq!(nodata("w.example", RecordType::A), Proof::Proven);
+        assert_eq!(nodata("y.w.example", RecordType::Aaaa), Proof::Proven);
+        assert_eq!(nodata("y.w.example", RecordType::Mx), Proof::Failed);
+        assert_eq!(nodata("c.example", RecordType::Ds), Proof::Proven);
+        assert_eq!(nodata("c.example", RecordType::A), Proof::Failed);
+        assert_eq!(nodata("example", RecordType::Ds), Proof::Failed);
+
+        let delegation = |domain: &str| prove_unsigned_delegation(&name(domain), &zone, &chain);
+        assert_eq!(delegation("c.example"), Proof::Proven);
+        assert_eq!(delegation("a.example"), Proof::Failed);
+
+        let expansion = |encloser: &str| {
+            prove_wildcard_expansion(&name("z.w.example"), &name(encloser), &zone, &chain)
+        };
+        assert_eq!(expansion("w.example"), Proof::Proven);
+        assert_eq!(expansion("example"), Proof::Failed);
+    }
+
+    #[test]
+    fn test_nsec3_proofs() {
+        let zone = name("example");
+        let chain = nsec3_chain(1, false);
+        let nxdomain = |domain: &str| prove_nxdomain(&name(domain), &zone, &chain);
+        let nodata = |domain: &str, rtype| prove_nodata(&name(domain), rtype, &zone, &chain);
+
+        assert_eq!(nxdomain("nope.example"), Proof::Proven);
+        assert_eq!(nxdomain("a.b.c.nope.example"), Proof::Proven);
+        assert_eq!(nxdomain("mail.example"), Proof::Failed);
+        assert_eq!(nxdomain("www.c.example"), Proof::Failed);
+
+        assert_eq!(nodata("mail.example", RecordType::Aaaa), Proof::Proven);
+        assert_eq!(nodata("mail.example", RecordType::A), Proof::Failed);
+        assert_eq!(nodata("w.example", RecordType::A), Proof::Proven);
+        assert_eq!(nodata("y.w.example", RecordType::Aaaa), Proof::Proven);
+        assert_eq!(nodata("y.w.example", RecordType::Mx), Proof::Failed);
+
+        assert_eq!(
+            prove_unsigned_delegation(&name("c.example"), &zone, &chain),
+            Proof::Proven
+        );
+        assert_eq!(
+            prove_wildcard_expansion(&name("z.w.example"), &name("w.example"), &zone, &chain),
+            Proof::Proven
+        );
+        assert_eq!(
+            prove_wildcard_expansion(&name("x.w.example"), &name("w.example"), &zone, &chain),
+            Proof::Failed
+        );
+
+        // Records of other zones are ignored
+        assert_eq!(
+            prove_nxdomain(&name("nope.example.org"), &name("example.org"), &chain),
+            Proof::Failed
+        );
+    }
+
+    #[test]
+    fn test_nsec3_opt_out() {
+        let zone = name("example");
+        let chain = nsec3_chain(1, true);
+
+        // The unsigned delegation is only covered by an opt-out span
+        assert_eq!(
+            prove_unsigned_delegation(&name("c.example"), &zone, &chain),
+            Proof::Insecure
+        );
+        assert_eq!(
+            prove_nodata(&name("c.example"), RecordType::Ds, &zone, &chain),
+            Proof::Insecure
+        );
+        assert_eq!(
+            prove_nxdomain(&name("nope.example"), &zone, &chain),
+            Proof::Insecure
+        );
+        assert_eq!(
+            prove_unsigned_delegation(&name("a.example"), &zone, &chain),
+            Proof::Failed
+        );
+    }
+
+    #[test]
+    fn test_nsec3_iterations() {
+        let zone = name("example");
+        let chain = nsec3_chain(MAX_NSEC3_ITERATIONS, false);
+        assert_eq!(
+            prove_nxdomain(&name("nope.example"), &zone, &chain),
+            Proof::Proven
+        );
+
+        let chain = nsec3_chain(MAX_NSEC3_ITERATIONS + 1, false);
+        assert_eq!(
+            prove_nxdomain(&name("nope.example"), &zone, &chain),
+            Proof::Insecure
+        );
+        assert_eq!(
+            prove_nodata(&name("mail.example"), RecordType::A, &zone, &chain),
+            Proof::Insecure
+        );
+    }
+
+    #[test]
+    fn test_nsec3_parameters() {
+        let zone = name("example");
+        let chain = nsec3_chain(1, false);
+        let Chain::Nsec3(nsec3s) = Chain::new(&zone, &chain) else {
+            panic!("Expected an NSEC3 chain");
+        };
+        // Each name is hashed once, however many records it is compared with: the next closer
+        // name nope.example was already hashed as a candidate encloser
+        assert!(
+            nsec3s
+                .closest_encloser(&name("a.b.nope.example"), &zone)
+                .is_some()
+        );
+        assert_eq!(nsec3s.hashes.borrow().len(), 3);
+
+        // Records of different chains don't prove anything together
+        let mut mixed = chain.clone();
+        if let RData::Nsec3(nsec3) = &mut mixed[0].rdata {
+            nsec3.salt = vec![0xee];
+        }
+        assert_eq!(
+            prove_nxdomain(&name("nope.example"), &zone, &mixed),
+            Proof::Failed
+        );
+        let mut mixed = chain;
+        if let RData::Nsec3(nsec3) = &mut mixed[0].rdata {
+            nsec3.iterations = 2;
+        }
+        assert_eq!(
+            prove_nodata(&name("mail.example"), RecordType::Aaaa, &zone, &mixed),
+            Proof::Failed
+        );
+    }
+}
@@ -0,0 +1,480 @@
+use std::fmt;
+
+use crate::{
+    encoding::{to_base32hex, to_base64, to_hex},
+    error::ParseError,
+    message::Encodable,
+    name::Name,
+    record::RecordType,
+};
+
+const ZONE_KEY: u16 = 0x0100;
+const REVOKED: u16 = 0x0080;
+const SECURE_ENTRY_POINT: u16 = 0x0001;
+const OPT_OUT: u8 = 0x01;
+const RSA_MD5: u8 = 1;
+
+/// Public key of a zone (RFC 4034 2)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Dnskey {
+    pub flags: u16,
+    /// Always 3
+    pub protocol: u8,
+    pub algorithm: u8,
+    pub public_key: Vec<u8>,
+}
+
+/// Signature over an RRset (RFC 4034 3)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Rrsig {
+    pub type_covered: RecordType,
+    pub algorithm: u8,
+    /// Number of labels in the owner name, without the root and a leading wildcard
+    pub labels: u8,
+    pub original_ttl: u32,
+    /// Validity period in seconds since the epoch, compared with serial number arithmetic
+    pub expiration: u32,
+    pub inception: u32,
+    pub key_tag: u16,
+    pub signer: Name,
+    pub signature: Vec<u8>,
+}
+
+/// Digest of a DNSKEY of the child zone, stored in the parent (RFC 4034 5)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Ds {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    pub digest_type: u8,
+    pub digest: Vec<u8>,
+}
+
+/// Next owner name in canonical order and the types at the owner (RFC 4034 4)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Nsec {
+    pub next: Name,
+    pub types: Vec<RecordType>,
+}
+
+/// Hashed version of NSEC (RFC 5155 3)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Nsec3 {
+    pub hash_algorithm: u8,
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+    /// Hash of the next owner name, without the zone
+    pub next_hashed: Vec<u8>,
+    pub types: Vec<RecordType>,
+}
+
+/// Parameters used to compute the NSEC3 hashes of a zone (RFC 5155 4)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Nsec3Param {
+    pub hash_algorithm: u8,
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+}
+
+impl Dnskey {
+    pub fn is_zone_key(&self) -> bool {
+        self.flags & ZONE_KEY != 0
+    }
+
+    pub fn is_secure_entry_point(&self) -> bool {
+        self.flags & SECURE_ENTRY_POINT != 0
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.flags & REVOKED != 0
+    }
+
+    /// Computes the tag RRSIG and DS records use to refer to the key (RFC 4034 B)
+    pub fn key_tag(&self) -> u16 {
+        if self.algorithm == RSA_MD5 {
+            let len = self.public_key.len();
+            if len < 3 {
+                return 0;
+            }
+            return u16::from_be_bytes([self.public_key[len - 3], self.public_key[len - 2]]);
+        }
+
+        let mut rdata = Vec::new();
+        self.encode(&mut rdata);
+        let mut ac: u32 = 0;
+        for (i, &b) in rdata.iter().enumerate() {
+            ac += if i.is_multiple_of(2) {
+                u32::from(b) << 8
+            } else {
+                u32::from(b)
+            };
+        }
+        ac += (ac >> 16) & 0xffff;
+        (ac & 0xffff) as u16
+    }
+}
+
+impl Nsec3 {
+    /// Returns true if the span may contain insecure delegations (RFC 5155 6)
+    pub fn is_opt_out(&self) -> bool {
+        self.flags & OPT_OUT != 0
+    }
+}
+
+impl Encodable for Dnskey {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.flags.to_be_bytes());
+        buf.push(self.protocol);
+        buf.push(self.algorithm);
+        buf.extend_from_slice(&self.public_key);
+    }
+}
+
+impl Rrsig {
+    /// Writes every field before the signature, the part of the rdata that is signed
+    pub fn encode_without_signature(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&u16::from(self.type_covered).to_be_bytes());
+        buf.push(self.algorithm);
+        buf.push(self.labels);
+        for value in [self.original_ttl, self.expiration, self.inception] {
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+        buf.extend_from_slice(&self.key_tag.to_be_bytes());
+        buf.extend_from_slice(self.signer.as_wire());
+    }
+}
+
+impl Encodable for Rrsig {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.encode_without_signature(buf);
+        buf.extend_from_slice(&self.signature);
+    }
+}
+
+impl Encodable for Ds {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.key_tag.to_be_bytes());
+        buf.push(self.algorithm);
+        buf.push(self.digest_type);
+        buf.extend_from_slice(&self.digest);
+    }
+}
+
+impl Encodable for Nsec {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.next.as_wire());
+        encode_type_bitmap(&self.types, buf);
+    }
+}
+
+impl Encodable for Nsec3 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.hash_algorithm);
+        buf.push(self.flags);
+        buf.extend_from_slice(&self.iterations.to_be_bytes());
+        buf.push(self.salt.len() as u8);
+        buf.extend_from_slice(&self.salt);
+        buf.push(self.next_hashed.len() as u8);
+        buf.extend_from_slice(&self.next_hashed);
+        encode_type_bitmap(&self.types, buf);
+    }
+}
+
+impl Encodable for Nsec3Param {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.hash_algorithm);
+        buf.push(self.flags);
+        buf.extend_from_slice(&self.iterations.to_be_bytes());
+        buf.push(self.salt.len() as u8);
+        buf.extend_from_slice(&self.salt);
+    }
+}
+
+impl fmt::Display for Dnskey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.flags,
+            self.protocol,
+            self.algorithm,
+            to_base64(&self.public_key)
+        )
+    }
+}
+
+impl fmt::Display for Rrsig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {}",
+            self.type_covered,
+            self.algorithm,
+            self.labels,
+            self.original_ttl,
+            Timestamp(self.expiration),
+            Timestamp(self.inception),
+            self.key_tag,
+            self.signer,
+            to_base64(&self.signature)
+        )
+    }
+}
+
+impl fmt::Display for Ds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.key_tag,
+            self.algorithm,
+            self.digest_type,
+            to_hex(&self.digest).to_ascii_uppercase()
+        )
+    }
+}
+
+impl fmt::Display for Nsec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.next)?;
+        write_types(f, &self.types)
+    }
+}
+
+impl fmt::Display for Nsec3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.hash_algorithm,
+            self.flags,
+            self.iterations,
+            Salt(&self.salt),
+            to_base32hex(&self.next_hashed).to_ascii_uppercase()
+        )?;
+        write_types(f, &self.types)
+    }
+}
+
+impl fmt::Display for Nsec3Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.hash_algorithm,
+            self.flags,
+            self.iterations,
+            Salt(&self.salt)
+        )
+    }
+}
+
+fn write_types(f: &mut fmt::Formatter<'_>, types: &[RecordType]) -> fmt::Result {
+    for rtype in types {
+        write!(f, " {}", rtype)?;
+    }
+    Ok(())
+}
+
+/// NSEC3 salt in hex, `-` when empty
+struct Salt<'a>(&'a [u8]);
+
+impl fmt::Display for Salt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            f.write_str("-")
+        } else {
+            f.write_str(&to_hex(self.0).to_ascii_uppercase())
+        }
+    }
+}
+
+/// RRSIG time written as `YYYYMMDDHHmmSS` in UTC (RFC 4034 3.2)
+struct Timestamp(u32);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = i64::from(self.0 / 86400);
+        let seconds = self.0 % 86400;
+        let (year, month, day) = civil_from_days(days);
+        write!(
+            f,
+            "{:04}{:02}{:02}{:02}{:02}{:02}",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// Parses an RRSIG time, either `YYYYMMDDHHmmSS` or the number of seconds since the epoch
+pub(crate) fn parse_timestamp(s: &str) -> Result<u32, ParseError> {
+    let invalid = || ParseError::InvalidPresentation;
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    if s.len() != 14 {
+        return s.parse().map_err(|_| invalid());
+    }
+
+    let field = |range: std::ops::Range<usize>| s[range].parse::<i64>().unwrap();
+    let (year, month, day) = (field(0..4), field(4..6), field(6..8));
+    let (hour, minute, second) = (field(8..10), field(10..12), field(12..14));
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    // Times past 2106 wrap around, which serial number arithmetic allows for
+    Ok(seconds.rem_euclid(1 << 32) as u32)
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of [`days_from_civil`]
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Writes the types as windows of bits (RFC 4034 4.1.2), the order of `types` doesn't matter
+pub fn encode_type_bitmap(types: &[RecordType], buf: &mut Vec<u8>) {
+    let mut values: Vec<u16> = types.iter().map(|&t| u16::from(t)).collect();
+    values.sort_unstable();
+    values.dedup();
+
+    for window in values.chunk_by(|a, b| a >> 8 == b >> 8) {
+        let mut bitmap = [0u8; 32];
+        for &value in window {
+            let low = (value & 0xff) as usize;
+            bitmap[low / 8] |= 0x80 >> (low % 8);
+        }
+        let len = (window[window.len() - 1] & 0xff) as usize / 8 + 1;
+        buf.push((window[0] >> 8) as u8);
+        buf.push(len as u8);
+        buf.extend_from_slice(&bitmap[..len]);
+    }
+}
+
+/// Reads a type bitmap, whose windows have to be in increasing order
+///
+/// # Errors
+///
+/// This function will return an error if the bitmap is truncated or malformed
+pub fn decode_type_bitmap(mut data: &[u8]) -> Result<Vec<RecordType>, ParseError> {
+    let mut types = Vec::new();
+    let mut last_window = None;
+    while let [window, len, rest @ ..] = data {
+        let len = *len as usize;
+        if last_window.is_some_and(|last| *window <= last) || !(1..=32).contains(&len) {
+            return Err(ParseError::InvalidRData);
+        }
+        let bitmap = rest.get(..len).ok_or(ParseError::InvalidRData)?;
+        for (i, &byte) in bitmap.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    let value = u16::from(*window) << 8 | (i * 8 + bit) as u16;
+                    types.push(RecordType::from(value));
+                }
+            }
+        }
+        last_window = Some(*window);
+        data = &rest[len..];
+    }
+    if !data.is_empty() {
+        return Err(ParseError::InvalidRData);
+    }
+    Ok(types)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::encoding::from_base64;
+
+    #[test]
+    fn test_key_tag() {
+        // KSK of the root zone, whose tag is 20326
+        let key = Dnskey {
+            flags: 257,
+            protocol: 3,
+            algorithm: 8,
+            public_key: from_base64(
+                "AwEAAaz/tAm8yTn4Mfeh5eyI96WSVexTBAvkMgJzkKTOiW1vkIbzxeF3+/4RgWOq7HrxRixHlFlExOLAJr5emLvN7SWXgnLh4+B5xQlNVz8Og8kvArMtNROxVQuCaSnIDdD5LKyWbRd2n9WGe2R8PzgCmr3EgVLrjyBxWezF0jLHwVN8efS3rCj/EWgvIWgb9tarpVUDK/b58Da+sqqls3eNbuv7pr+eoZG+SrDK6nWeL3c6H5Apxz7LjVc1uTIdsIXxuOLYA4/ilBmSVIzuDWfdRUfhHdY6+cn8HFRm+2hM8AnXGXws9555KrUB5qihylGa8subX2Nn6UwNR1AkUTV74bU=",
+            )
+            .unwrap(),
+        };
+        assert_eq!(key.key_tag(), 20326);
+        assert!(key.is_zone_key());
+        assert!(key.is_secure_entry_point());
+        assert!(!key.is_revoked());
+    }
+
+    #[test]
+    fn test_type_bitmap() {
+        // Example of RFC 4034 4.3
+        let types = [
+            RecordType::A,
+            RecordType::Mx,
+            RecordType::Rrsig,
+            RecordType::Nsec,
+            RecordType::Unknown(1234),
+        ];
+        let mut buf = Vec::new();
+        encode_type_bitmap(&types, &mut buf);
+        assert_eq!(
+            buf,
+            [
+                0x00, 0x06, 0x40, 0x01, 0x00, 0x00, 0x00, 0x03, 0x04, 0x1b, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20
+            ]
+        );
+        assert_eq!(decode_type_bitmap(&buf), Ok(types.to_vec()));
+
+        assert_eq!(decode_type_bitmap(&[0, 0]), Err(ParseError::InvalidRData));
+        assert_eq!(
+            decode_type_bitmap(&[4, 1, 0x20, 0, 1, 0x40]),
+            Err(ParseError::InvalidRData)
+        );
+        assert_eq!(
+            decode_type_bitmap(&[0, 2, 0x40]),
+            Err(ParseError::InvalidRData)
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(parse_timestamp("20240229123456"), Ok(1709210096));
+        assert_eq!(Timestamp(1709210096).to_string(), "20240229123456");
+        assert_eq!(Timestamp(0).to_string(), "19700101000000");
+        assert_eq!(parse_timestamp("1709210096"), Ok(1709210096));
+        assert!(parse_timestamp("20241301000000").is_err());
+        assert!(parse_timestamp("2024-02-29").is_err());
+    }
+}
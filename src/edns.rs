@@ -0,0 +1,199 @@
+use std::fmt;
+
+use crate::{
+    client_subnet::ClientSubnet,
+    cookie::Cookie,
+    encoding::to_hex,
+    message::Rcode,
+    name::Name,
+    rdata::RData,
+    record::{Record, RecordClass, RecordType},
+};
+
+/// Payload size recommended by DNS flag day 2020, which avoids IP fragmentation
+pub const DEFAULT_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+const DNSSEC_OK: u32 = 1 << 15;
+
+/// Option code of EDNS Client Subnet (RFC 7871 6)
+pub const CLIENT_SUBNET: u16 = 8;
+
+/// Option code of DNS Cookies (RFC 7873 4)
+pub const COOKIE: u16 = 10;
+
+/// EDNS(0) information carried by the OPT pseudo-record (RFC 6891)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    /// Upper 8 bits of the 12 bit rcode
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+impl Edns {
+    pub fn new(udp_payload_size: u16) -> Self {
+        Self {
+            udp_payload_size,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+
+    /// Reads the OPT pseudo-record, returns `None` for any other record
+    pub fn from_record(record: &Record) -> Option<Self> {
+        let RData::Opt(options) = &record.rdata else {
+            return None;
+        };
+        Some(Self {
+            udp_payload_size: record.rclass.into(),
+            extended_rcode: (record.ttl >> 24) as u8,
+            version: (record.ttl >> 16) as u8,
+            dnssec_ok: record.ttl & DNSSEC_OK != 0,
+            options: options.clone(),
+        })
+    }
+
+    pub fn to_record(&self) -> Record {
+        let mut ttl = (u32::from(self.extended_rcode) << 24) | (u32::from(self.version) << 16);
+        if self.dnssec_ok {
+            ttl |= DNSSEC_OK;
+        }
+        Record {
+            name: Name::root(),
+            rtype: RecordType::Opt,
+            rclass: RecordClass::from(self.udp_payload_size),
+            ttl,
+            rdata: RData::Opt(self.options.clone()),
+        }
+    }
+
+    /// Combines the 4 bit rcode from the header with the extended rcode
+    pub fn get_rcode(&self, header_rcode: Rcode) -> Rcode {
+        Rcode::from((u16::from(self.extended_rcode) << 4) | u16::from(header_rcode))
+    }
+
+    /// Stores the upper 8 bits of `rcode`, the lower 4 bits go in the header
+    pub fn set_rcode(&mut self, rcode: Rcode) {
+        self.extended_rcode = (u16::from(rcode) >> 4) as u8;
+    }
+
+    pub fn get_option(&self, code: u16) -> Option<&EdnsOption> {
+        self.options.iter().find(|o| o.code == code)
+    }
+
+    /// Returns the COOKIE option, if there is a well-formed one
+    pub fn get_cookie(&self) -> Option<Cookie> {
+        self.get_option(COOKIE).and_then(Cookie::from_option)
+    }
+
+    /// Replaces the COOKIE option, or removes it if `cookie` is `None`
+    pub fn set_cookie(&mut self, cookie: Option<Cookie>) {
+        self.options.retain(|o| o.code != COOKIE);
+        if let Some(cookie) = cookie {
+            self.options.push(cookie.to_option());
+        }
+    }
+
+    /// Returns the CLIENT-SUBNET option, if there is a well-formed one
+    pub fn get_client_subnet(&self) -> Option<ClientSubnet> {
+        self.get_option(CLIENT_SUBNET)
+            .and_then(ClientSubnet::from_option)
+    }
+
+    /// Replaces the CLIENT-SUBNET option, or removes it if `subnet` is `None`
+    pub fn set_client_subnet(&mut self, subnet: Option<ClientSubnet>) {
+        self.options.retain(|o| o.code != CLIENT_SUBNET);
+        if let Some(subnet) = subnet {
+            self.options.push(subnet.to_option());
+        }
+    }
+}
+
+impl fmt::Display for Edns {
+    /// Writes the OPT pseudo-section the way dig does, with the option data in hex
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "; EDNS: version: {}, flags:", self.version)?;
+        if self.dnssec_ok {
+            write!(f, " do")?;
+        }
+        write!(f, "; udp: {}", self.udp_payload_size)?;
+        for option in &self.options {
+            match option.code {
+                CLIENT_SUBNET if let Some(subnet) = ClientSubnet::from_option(option) => {
+                    write!(f, "\n; CLIENT-SUBNET: {}", subnet)?
+                }
+                COOKIE => write!(f, "\n; COOKIE: {}", to_hex(&option.data))?,
+                code => write!(f, "\n; OPT={}: {}", code, to_hex(&option.data))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::Encodable;
+
+    #[test]
+    fn test_edns_record_round_trip() {
+        let mut edns = Edns::new(4096);
+        edns.dnssec_ok = true;
+        edns.set_rcode(Rcode::BadVers);
+        edns.options.push(EdnsOption {
+            code: 10,
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        });
+
+        let record = edns.to_record();
+        let mut buf = Vec::new();
+        record.encode(&mut buf);
+        // root name, type 41, class 4096, ttl with extended rcode 1 and DO
+        assert_eq!(buf[..11], [0, 0, 41, 16, 0, 1, 0, 0x80, 0, 0, 12]);
+
+        let (rr, _) = crate::message::ResourceRecord::from_bytes(&buf, 0).unwrap();
+        let output = Edns::from_record(&rr.to_record(&buf).unwrap()).unwrap();
+        assert_eq!(output, edns);
+        assert_eq!(output.get_rcode(Rcode::NoError), Rcode::BadVers);
+    }
+
+    #[test]
+    fn test_edns_client_subnet() {
+        let mut edns = Edns::new(1232);
+        let subnet = ClientSubnet::new([192, 0, 2, 77].into(), 24);
+        edns.set_client_subnet(Some(subnet));
+        edns.set_client_subnet(Some(subnet));
+        assert_eq!(edns.options.len(), 1);
+        assert_eq!(edns.get_client_subnet(), Some(subnet));
+        assert_eq!(
+            edns.to_string(),
+            "; EDNS: version: 0, flags:; udp: 1232\n; CLIENT-SUBNET: 192.0.2.0/24/0"
+        );
+
+        edns.set_client_subnet(None);
+        assert_eq!(edns.get_client_subnet(), None);
+        assert!(edns.options.is_empty());
+    }
+
+    #[test]
+    fn test_edns_from_other_record() {
+        let record = Record {
+            name: Name::root(),
+            rtype: RecordType::A,
+            rclass: RecordClass::In,
+            ttl: 0,
+            rdata: RData::A([127, 0, 0, 1].into()),
+        };
+        assert_eq!(Edns::from_record(&record), None);
+    }
+}
@@ -0,0 +1,156 @@
+/// Formats `data` as lowercase hex, without separators
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parses hex in either case, returning `None` for odd lengths or other characters
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high << 4 | low) as u8)
+        })
+        .collect()
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Formats `data` as padded base64 with the standard alphabet (RFC 4648 4)
+pub fn to_base64(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+/// Parses padded base64 with the standard alphabet, returning `None` for anything malformed
+pub fn from_base64(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut data = Vec::with_capacity(s.len() / 4 * 3);
+    let chunks = s.as_bytes().chunks(4);
+    let last = chunks.len().saturating_sub(1);
+    for (n, chunk) in chunks.enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && n != last) {
+            return None;
+        }
+        let mut bits = 0u32;
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+            bits |= value << (18 - 6 * i);
+        }
+        data.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Some(data)
+}
+
+const BASE32HEX_ALPHABET: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+
+/// Formats `data` as lowercase base32 with the extended hex alphabet and no padding, as used
+/// for NSEC3 hashes (RFC 5155 3.3)
+pub fn to_base32hex(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len().div_ceil(5) * 8);
+    for chunk in data.chunks(5) {
+        let mut block = [0u8; 8];
+        block[3..3 + chunk.len()].copy_from_slice(chunk);
+        let bits = u64::from_be_bytes(block);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            s.push(BASE32HEX_ALPHABET[(bits >> (35 - 5 * i) & 0x1f) as usize] as char);
+        }
+    }
+    s
+}
+
+/// Parses unpadded base32 with the extended hex alphabet in either case
+pub fn from_base32hex(s: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(s.len() * 5 / 8);
+    for chunk in s.as_bytes().chunks(8) {
+        // Lengths that can't come from whole bytes
+        if matches!(chunk.len(), 1 | 3 | 6) {
+            return None;
+        }
+        let mut bits = 0u64;
+        for (i, &c) in chunk.iter().enumerate() {
+            let c = c.to_ascii_lowercase();
+            let value = BASE32HEX_ALPHABET.iter().position(|&a| a == c)? as u64;
+            bits |= value << (35 - 5 * i);
+        }
+        data.extend_from_slice(&bits.to_be_bytes()[3..3 + chunk.len() * 5 / 8]);
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hex_round_trip() {
+        assert_eq!(to_hex(&[0xc0, 0x00, 0x02, 0x01]), "c0000201");
+        assert_eq!(from_hex("C0000201"), Some(vec![0xc0, 0x00, 0x02, 0x01]));
+        assert_eq!(from_hex(""), Some(Vec::new()));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+
+    #[test]
+    fn test_base64_round_trip() {
+        // Test vectors of RFC 4648 10
+        for (data, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(to_base64(data.as_bytes()), encoded);
+            assert_eq!(from_base64(encoded), Some(data.as_bytes().to_vec()));
+        }
+        assert_eq!(from_base64("Zm9"), None);
+        assert_eq!(from_base64("Zg==Zg=="), None);
+        assert_eq!(from_base64("Z==="), None);
+        assert_eq!(from_base64("Zm9*"), None);
+    }
+
+    #[test]
+    fn test_base32hex_round_trip() {
+        // Test vectors of RFC 4648 10, without padding
+        for (data, encoded) in [
+            ("", ""),
+            ("f", "co"),
+            ("fo", "cpng"),
+            ("foo", "cpnmu"),
+            ("foob", "cpnmuog"),
+            ("fooba", "cpnmuoj1"),
+            ("foobar", "cpnmuoj1e8"),
+        ] {
+            assert_eq!(to_base32hex(data.as_bytes()), encoded);
+            assert_eq!(from_base32hex(encoded), Some(data.as_bytes().to_vec()));
+        }
+        assert_eq!(from_base32hex("CPNMUOJ1"), Some(b"fooba".to_vec()));
+        assert_eq!(from_base32hex("cpn"), None);
+        assert_eq!(from_base32hex("cw"), None);
+    }
+}
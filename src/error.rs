@@ -1,15 +1,48 @@
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
 pub enum ParseError {
     #[error("Header is not large enough")]
     InvalidHeader,
 
-    #[error("Question is not large enough")]
-    InvalidQuestion,
+    #[error("Question at offset {0} is not large enough")]
+    InvalidQuestion(usize),
+
+    #[error("ResourceRecord at offset {0} is not large enough")]
+    InvalidResourcRecord(usize),
+
+    #[error("RDATA does not match its record type")]
+    InvalidRData,
+
+    #[error("Name runs past the end of the message")]
+    TruncatedName,
+
+    #[error("Compression pointer points outside the message")]
+    InvalidPointer,
+
+    #[error("Compression pointer does not point backwards")]
+    CompressionLoop,
+
+    #[error("Label is longer than 63 octets")]
+    LabelTooLong,
+
+    #[error("Name is longer than 255 octets")]
+    NameTooLong,
+
+    #[error("Name has an empty label")]
+    EmptyLabel,
 
-    #[error("ResourceRecord is not large enough")]
-    InvalidResourcRecord,
+    #[error("Name has an invalid escape sequence")]
+    InvalidEscape,
+
+    #[error("Name is not a valid internationalized domain name")]
+    InvalidIdna,
+
+    #[error("Invalid presentation format")]
+    InvalidPresentation,
+
+    #[error("JSON member {0} is missing or invalid")]
+    InvalidJson(String),
 }
 #[derive(Debug, Error)]
 pub enum ResponseCodeError {
@@ -29,6 +62,9 @@ pub enum ResponseCodeError {
 
     #[error("The name server refuses to perform the specified operation for policy reasons")]
     Refused,
+
+    #[error("The name server responded with RCODE {0}")]
+    Other(u16),
 }
 
 #[derive(Debug, Error)]
@@ -59,4 +95,16 @@ pub enum DnsError {
 
     #[error("Error in delegation")]
     InvalidDelegation,
+
+    #[error("DNSSEC validation of the answer failed")]
+    Bogus,
+
+    #[error("TSIG signature of the message is missing or invalid")]
+    BadSig,
+
+    #[error("TSIG key is unknown")]
+    BadKey,
+
+    #[error("TSIG signing time is outside of the allowed fudge")]
+    BadTime,
 }
@@ -0,0 +1,353 @@
+//! JSON representation of messages from RFC 8427
+//!
+//! Every record carries its rdata as `RDATAHEX` so that the conversion is lossless, along with
+//! the presentation format in an `rdata<TYPE>` member for readers
+
+use serde_json::{Map, Value, json};
+
+use crate::{
+    builder::{MessageBuilder, OwnedMessage, OwnedQuestion},
+    encoding::{from_hex, to_hex},
+    error::ParseError,
+    message::{Encodable, Opcode, Rcode},
+    name::Name,
+    rdata::RData,
+    record::{Record, RecordClass, RecordType},
+};
+
+const SECTIONS: [&str; 3] = ["answerRRs", "authorityRRs", "additionalRRs"];
+
+/// Converts `message` to an RFC 8427 JSON object
+///
+/// A received [`Message`](crate::message::Message) can be converted with
+/// [`OwnedMessage::from_message`] first
+pub fn to_json(message: &OwnedMessage) -> Value {
+    let header = message.get_header();
+    let mut object = json!({
+        "ID": header.get_id(),
+        "QR": u8::from(header.get_qr()),
+        "Opcode": u8::from(header.get_opcode()),
+        "AA": u8::from(header.get_aa()),
+        "TC": u8::from(header.get_tc()),
+        "RD": u8::from(header.get_rd()),
+        "RA": u8::from(header.get_ra()),
+        "AD": u8::from(header.get_ad()),
+        "CD": u8::from(header.get_cd()),
+        "RCODE": u16::from(header.get_rcode()),
+        "QDCOUNT": message.get_questions().len(),
+        "ANCOUNT": message.get_answers().len(),
+        "NSCOUNT": message.get_authorities().len(),
+        "ARCOUNT": message.get_additionals().len(),
+    });
+
+    let questions: Vec<Value> = message
+        .get_questions()
+        .iter()
+        .map(|q| {
+            json!({
+                "NAME": q.name.to_string(),
+                "TYPE": u16::from(q.qtype),
+                "TYPEname": q.qtype.to_string(),
+                "CLASS": u16::from(q.qclass),
+                "CLASSname": q.qclass.to_string(),
+            })
+        })
+        .collect();
+    let sections = [
+        message.get_answers(),
+        message.get_authorities(),
+        message.get_additionals(),
+    ];
+
+    object["questionRRs"] = questions.into();
+    for (key, records) in SECTIONS.into_iter().zip(sections) {
+        if !records.is_empty() {
+            object[key] = records.iter().map(record_to_json).collect();
+        }
+    }
+    object
+}
+
+/// Converts an RFC 8427 JSON object to a message
+///
+/// Missing header members are taken as 0, and the counts are ignored since they follow from
+/// the sections. A single question may also be given with `QNAME`, `QTYPE` and `QCLASS`
+///
+/// # Errors
+///
+/// This function will return an error if a member has the wrong type, or a record has neither
+/// `RDATAHEX` nor an `rdata` member that can be read
+pub fn from_json(value: &Value) -> Result<OwnedMessage, ParseError> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| ParseError::InvalidJson("message".to_string()))?;
+
+    let mut builder = MessageBuilder::new(get_u16(object, "ID")?.unwrap_or(0))
+        .qr(get_flag(object, "QR")?)
+        .opcode(Opcode::from(get_u16(object, "Opcode")?.unwrap_or(0) as u8))
+        .aa(get_flag(object, "AA")?)
+        .tc(get_flag(object, "TC")?)
+        .rd(get_flag(object, "RD")?)
+        .ra(get_flag(object, "RA")?)
+        .ad(get_flag(object, "AD")?)
+        .cd(get_flag(object, "CD")?)
+        .rcode(Rcode::from(get_u16(object, "RCODE")?.unwrap_or(0)));
+
+    match get_array(object, "questionRRs")? {
+        Some(questions) => {
+            for question in questions {
+                let question = question_from_json(question, "NAME", "TYPE", "CLASS")?;
+                builder = builder.question(question.name, question.qtype, question.qclass);
+            }
+        }
+        None if object.contains_key("QNAME") => {
+            let question = question_from_json(value, "QNAME", "QTYPE", "QCLASS")?;
+            builder = builder.question(question.name, question.qtype, question.qclass);
+        }
+        None => {}
+    }
+
+    for record in read_records(object, "answerRRs")? {
+        builder = builder.answer(record);
+    }
+    for record in read_records(object, "authorityRRs")? {
+        builder = builder.authority(record);
+    }
+    for record in read_records(object, "additionalRRs")? {
+        builder = builder.additional(record);
+    }
+    Ok(builder.build())
+}
+
+fn read_records(object: &Map<String, Value>, key: &str) -> Result<Vec<Record>, ParseError> {
+    let records = get_array(object, key)?.unwrap_or(&[]);
+    records.iter().map(record_from_json).collect()
+}
+
+fn record_to_json(record: &Record) -> Value {
+    let mut rdata = Vec::new();
+    record.rdata.encode(&mut rdata);
+    let mut object = json!({
+        "NAME": record.name.to_string(),
+        "TYPE": u16::from(record.rtype),
+        "TYPEname": record.rtype.to_string(),
+        "CLASS": u16::from(record.rclass),
+        "CLASSname": record.rclass.to_string(),
+        "TTL": record.ttl,
+        "RDLENGTH": rdata.len(),
+        "RDATAHEX": to_hex(&rdata),
+    });
+    // Types without a presentation format of their own only have the hex
+    if !matches!(record.rdata, RData::Opt(_) | RData::Unknown { .. }) {
+        object[format!("rdata{}", record.rtype)] = record.rdata.to_string().into();
+    }
+    object
+}
+
+fn record_from_json(value: &Value) -> Result<Record, ParseError> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| ParseError::InvalidJson("record".to_string()))?;
+    let rtype = RecordType::from(require(get_u16(object, "TYPE")?, "TYPE")?);
+    let rdata = match get_str(object, "RDATAHEX")? {
+        Some(hex) => {
+            let bytes =
+                from_hex(hex).ok_or_else(|| ParseError::InvalidJson("RDATAHEX".to_string()))?;
+            RData::from_bytes(rtype, &bytes, &bytes)?
+        }
+        None => rdata_from_presentation(object, rtype)?,
+    };
+
+    Ok(Record {
+        name: get_name(object, "NAME")?,
+        rtype,
+        rclass: RecordClass::from(get_u16(object, "CLASS")?.unwrap_or(1)),
+        ttl: require(get_u32(object, "TTL")?, "TTL")?,
+        rdata,
+    })
+}
+
+/// Reads the `rdata<TYPE>` member, which holds the rdata in presentation format
+fn rdata_from_presentation(
+    object: &Map<String, Value>,
+    rtype: RecordType,
+) -> Result<RData, ParseError> {
+    let key = format!("rdata{}", rtype);
+    let text = require(get_str(object, &key)?, &key)?;
+    RData::from_presentation(rtype, text).map_err(|_| ParseError::InvalidJson(key))
+}
+
+fn question_from_json(
+    value: &Value,
+    name: &str,
+    qtype: &str,
+    qclass: &str,
+) -> Result<OwnedQuestion, ParseError> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| ParseError::InvalidJson("question".to_string()))?;
+    Ok(OwnedQuestion {
+        name: get_name(object, name)?,
+        qtype: RecordType::from(require(get_u16(object, qtype)?, qtype)?),
+        qclass: RecordClass::from(get_u16(object, qclass)?.unwrap_or(1)),
+    })
+}
+
+fn require<T>(value: Option<T>, key: &str) -> Result<T, ParseError> {
+    value.ok_or_else(|| ParseError::InvalidJson(key.to_string()))
+}
+
+fn get_u32(object: &Map<String, Value>, key: &str) -> Result<Option<u32>, ParseError> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .and_then(|n| u32::try_from(n).ok())
+            .map(Some)
+            .ok_or_else(|| ParseError::InvalidJson(key.to_string())),
+    }
+}
+
+fn get_u16(object: &Map<String, Value>, key: &str) -> Result<Option<u16>, ParseError> {
+    match get_u32(object, key)? {
+        None => Ok(None),
+        Some(n) => u16::try_from(n)
+            .map(Some)
+            .map_err(|_| ParseError::InvalidJson(key.to_string())),
+    }
+}
+
+/// Reads a flag given either as a JSON boolean or as 0 or 1
+fn get_flag(object: &Map<String, Value>, key: &str) -> Result<bool, ParseError> {
+    match object.get(key) {
+        None => Ok(false),
+        Some(Value::Bool(on)) => Ok(*on),
+        Some(value) => match value.as_u64() {
+            Some(0) => Ok(false),
+            Some(1) => Ok(true),
+            _ => Err(ParseError::InvalidJson(key.to_string())),
+        },
+    }
+}
+
+fn get_str<'a>(object: &'a Map<String, Value>, key: &str) -> Result<Option<&'a str>, ParseError> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| ParseError::InvalidJson(key.to_string())),
+    }
+}
+
+fn get_array<'a>(
+    object: &'a Map<String, Value>,
+    key: &str,
+) -> Result<Option<&'a [Value]>, ParseError> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_array()
+            .map(|a| Some(a.as_slice()))
+            .ok_or_else(|| ParseError::InvalidJson(key.to_string())),
+    }
+}
+
+fn get_name(object: &Map<String, Value>, key: &str) -> Result<Name, ParseError> {
+    require(get_str(object, key)?, key)?.parse()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        edns::Edns,
+        test_util::{name, record},
+    };
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut edns = Edns::new(1232);
+        edns.dnssec_ok = true;
+        let message = MessageBuilder::new(0x1234)
+            .question(name("example.com"), RecordType::Mx, RecordClass::In)
+            .qr(true)
+            .rd(true)
+            .answer(record(
+                "example.com",
+                RData::Mx {
+                    preference: 10,
+                    exchange: name("mail.example.com"),
+                },
+            ))
+            .authority(record("example.com", RData::Txt(vec![b"\"hi\"".to_vec()])))
+            .additional(record(
+                "mail.example.com",
+                RData::Unknown {
+                    rtype: 65280,
+                    data: vec![1, 2, 3],
+                },
+            ))
+            .edns(edns)
+            .build();
+
+        let value = to_json(&message);
+        assert_eq!(value["ID"], 0x1234);
+        assert_eq!(value["QR"], 1);
+        assert_eq!(value["ARCOUNT"], 2);
+        assert_eq!(value["answerRRs"][0]["rdataMX"], "10 mail.example.com.");
+        assert_eq!(value["additionalRRs"][0]["RDATAHEX"], "010203");
+
+        let text = value.to_string();
+        let output = from_json(&serde_json::from_str(&text).unwrap()).unwrap();
+        assert_eq!(output, message);
+    }
+
+    #[test]
+    fn test_json_rfc_8427_example() {
+        // Query from RFC 8427 section 7.1, and a response with presentation format rdata
+        let query = json!({
+            "ID": 19678, "QR": 0, "Opcode": 0,
+            "AA": 0, "TC": 0, "RD": 0, "RA": 0, "AD": 0, "CD": 0, "RCODE": 0,
+            "QDCOUNT": 1, "ANCOUNT": 0, "NSCOUNT": 0, "ARCOUNT": 0,
+            "QNAME": "example.com", "QTYPE": 1, "QCLASS": 1
+        });
+        let message = from_json(&query).unwrap();
+        assert_eq!(message.get_id(), 19678);
+        assert_eq!(message.get_questions()[0].name, name("example.com"));
+
+        let response = json!({
+            "ID": 1, "QR": true, "RCODE": 3,
+            "answerRRs": [
+                { "NAME": "example.com.", "TYPE": 1, "CLASS": 1, "TTL": 60,
+                  "rdataA": "192.0.2.1" }
+            ]
+        });
+        let message = from_json(&response).unwrap();
+        assert!(message.get_header().get_qr());
+        assert_eq!(message.get_rcode(), Rcode::NxDomain);
+        assert_eq!(
+            message.get_answers()[0].rdata,
+            RData::A([192, 0, 2, 1].into())
+        );
+    }
+
+    #[test]
+    fn test_json_invalid_members() {
+        let missing_ttl = json!({
+            "answerRRs": [{ "NAME": "example.com", "TYPE": 1, "RDATAHEX": "c0000201" }]
+        });
+        assert_eq!(
+            from_json(&missing_ttl),
+            Err(ParseError::InvalidJson("TTL".to_string()))
+        );
+        assert_eq!(
+            from_json(&json!({ "ID": 70000 })),
+            Err(ParseError::InvalidJson("ID".to_string()))
+        );
+        assert_eq!(
+            from_json(&json!({ "QR": 2 })),
+            Err(ParseError::InvalidJson("QR".to_string()))
+        );
+    }
+}
@@ -1,7 +1,24 @@
 pub mod algorithm;
+pub mod builder;
+pub mod client_subnet;
 pub mod compression;
+pub mod cookie;
+pub mod denial;
+pub mod dnssec;
+pub mod edns;
+pub mod encoding;
 pub mod error;
+pub mod json;
 pub mod log;
 pub mod message;
+pub mod name;
 pub mod net;
+pub mod rdata;
+pub mod record;
 pub mod server_info;
+pub mod svcb;
+#[cfg(test)]
+mod test_util;
+pub mod tsig;
+pub mod validator;
+pub mod view;
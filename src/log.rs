@@ -1,6 +1,7 @@
 use std::sync::atomic::{AtomicBool, Ordering};
 
 static VERBOSE: AtomicBool = AtomicBool::new(false);
+static DISSECT: AtomicBool = AtomicBool::new(false);
 
 pub fn set_verbose(on: bool) {
     VERBOSE.store(on, Ordering::Relaxed);
@@ -10,12 +11,21 @@ pub fn is_verbose() -> bool {
     VERBOSE.load(Ordering::Relaxed)
 }
 
-// macro that only prints when VERBOSE is true
+/// Prints a hex dump of every received packet to stderr
+pub fn set_dissect(on: bool) {
+    DISSECT.store(on, Ordering::Relaxed);
+}
+
+pub fn is_dissect() -> bool {
+    DISSECT.load(Ordering::Relaxed)
+}
+
+// macro that only prints when VERBOSE is true, to stderr so it never mixes with the output
 #[macro_export]
 macro_rules! vprintln {
     ($($arg:tt)*) => {
         if $crate::log::is_verbose() {
-            println!($($arg)*);
+            eprintln!($($arg)*);
         }
     };
 }
@@ -1,8 +1,13 @@
 use rust_dns::{
-    algorithm::{query_domain, recursive_query},
-    compression::compress_domain,
+    algorithm::{
+        AddressFamily, query, query_addresses, recursive_query, recursive_query_addresses,
+    },
+    builder::MessageBuilder,
     error::DnsError,
-    log::set_verbose,
+    json::to_json,
+    log::{set_dissect, set_verbose},
+    name::Name,
+    record::RecordClass,
     vprintln,
 };
 use std::env;
@@ -11,17 +16,26 @@ use std::env;
 struct Options<'a> {
     verbose: bool,
     recursive: bool,
+    json: bool,
+    dissect: bool,
+    family: AddressFamily,
     domain: &'a String,
 }
 
 fn print_usage(program: &String) {
-    println!("{} [--verbose | -v] [--recursive | -r] domain", program);
+    println!(
+        "{} [--verbose | -v] [--recursive | -r] [--json | -j] [--dissect | -x] [--ipv6 | -6 | --dual-stack | -d] domain",
+        program
+    );
 }
 
-fn parse_args(args: &[String]) -> Result<Options, DnsError> {
+fn parse_args(args: &[String]) -> Result<Options<'_>, DnsError> {
     let res = {
         let mut verbose = false;
         let mut recursive = false;
+        let mut json = false;
+        let mut dissect = false;
+        let mut family = None;
         let mut domain = None;
         let mut error = false;
         for arg in args[1..].iter() {
@@ -37,6 +51,27 @@ fn parse_args(args: &[String]) -> Result<Options, DnsError> {
                     break;
                 }
                 recursive = true;
+            } else if arg == "-j" || arg == "--json" {
+                if json {
+                    error = true;
+                    break;
+                }
+                json = true;
+            } else if arg == "-x" || arg == "--dissect" {
+                if dissect {
+                    error = true;
+                    break;
+                }
+                dissect = true;
+            } else if arg == "-6" || arg == "--ipv6" || arg == "-d" || arg == "--dual-stack" {
+                if family.is_some() {
+                    error = true;
+                    break;
+                }
+                family = Some(match arg.as_str() {
+                    "-6" | "--ipv6" => AddressFamily::Ipv6,
+                    _ => AddressFamily::DualStack,
+                });
             } else {
                 if domain.is_some() {
                     error = true;
@@ -52,6 +87,9 @@ fn parse_args(args: &[String]) -> Result<Options, DnsError> {
             let options = Options {
                 verbose,
                 recursive,
+                json,
+                dissect,
+                family: family.unwrap_or(AddressFamily::Ipv4),
                 domain,
             };
             Ok(options)
@@ -79,17 +117,43 @@ fn main() -> Result<(), DnsError> {
     let options = parse_args(&args)?;
 
     set_verbose(options.verbose);
-    let compressed_domain = compress_domain(options.domain);
+    set_dissect(options.dissect);
+    let domain = Name::from_unicode(options.domain)?;
+    vprintln!("Resolving {} ({})", domain.to_unicode(), domain);
+
+    if options.json {
+        // Answers as an RFC 8427 response, with a question for each record type looked up.
+        // Lookups failing with an rcode, like a name that doesn't exist, are reported with it
+        let mut builder = MessageBuilder::new(0).qr(true).rd(options.recursive);
+        for &rtype in options.family.record_types() {
+            builder = builder.question(domain.clone(), rtype, RecordClass::In);
+            let records = match options.recursive {
+                false => query(&domain, rtype, RecordClass::In),
+                true => recursive_query(&domain, rtype, RecordClass::In),
+            };
+            match records {
+                Ok(records) => {
+                    for record in records {
+                        builder = builder.answer(record);
+                    }
+                }
+                Err(DnsError::ResponseCodeError(error)) => builder = builder.rcode(error.into()),
+                Err(e) => return Err(e),
+            }
+        }
+        println!("{}", to_json(&builder.build()));
+        return Ok(());
+    }
 
     let ips = match options.recursive {
-        false => query_domain(&compressed_domain)?,
-        true => recursive_query(&compress_domain("www.nrk.no"))?,
+        false => query_addresses(&domain, options.family)?,
+        true => recursive_query_addresses(&domain, options.family)?,
     };
 
     vprintln!();
     println!("The ips for {} is:", options.domain);
     for &ip in ips.iter() {
-        println!("{:?}", ip);
+        println!("{}", ip);
     }
 
     Ok(())
@@ -121,11 +185,50 @@ mod test {
 
         let output = parse_args(&args);
         match output {
-            Ok(o) if o.domain == "domain" && !o.verbose => {}
+            Ok(o) if o.domain == "domain" && !o.verbose && !o.json => {}
             o => panic!("Test failed, got {:?}, expected Ok((\"domain\", None))", o),
         }
     }
 
+    #[test]
+    fn test_parse_args_family() {
+        let args: Vec<String> = vec!["prog".to_string(), "-6".to_string(), "domain".to_string()];
+        let output = parse_args(&args);
+        match output {
+            Ok(o) if o.family == AddressFamily::Ipv6 => {}
+            o => panic!("Test failed, got {:?}, expected Ipv6", o),
+        }
+
+        let args: Vec<String> = vec!["prog".to_string(), "domain".to_string()];
+        let output = parse_args(&args);
+        match output {
+            Ok(o) if o.family == AddressFamily::Ipv4 => {}
+            o => panic!("Test failed, got {:?}, expected Ipv4", o),
+        }
+
+        let args: Vec<String> = vec![
+            "prog".to_string(),
+            "-6".to_string(),
+            "--dual-stack".to_string(),
+            "domain".to_string(),
+        ];
+        let output = parse_args(&args);
+        match output {
+            Err(DnsError::WrongArgs) => {}
+            o => panic!("Test failed, got {:?}, expected Err(WrongArgs)", o),
+        }
+    }
+
+    #[test]
+    fn test_parse_args_json() {
+        let args: Vec<String> = vec!["prog".to_string(), "-j".to_string(), "domain".to_string()];
+        let output = parse_args(&args);
+        match output {
+            Ok(o) if o.json && !o.dissect => {}
+            o => panic!("Test failed, got {:?}, expected json", o),
+        }
+    }
+
     #[test]
     fn test_parse_args_two_domains() {
         let args: Vec<String> = vec![
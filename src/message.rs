@@ -1,42 +1,67 @@
-use std::{cell::Cell, net::IpAddr};
+use std::{
+    cell::Cell,
+    fmt::{self, Write},
+    net::IpAddr,
+};
 
 use rand::random;
 
 use crate::{
-    compression::{CompressedName, decompress, is_pointer},
+    compression::{
+        MAX_LABEL_LEN, NameCompressor, decompress, is_pointer, name_end, pointer_to_offset,
+    },
+    edns::Edns,
     error::{DnsError, ParseError, ResponseCodeError},
+    name::Name,
     net::bytes_to_ip,
+    rdata::RData,
+    record::{Record, RecordClass, RecordType},
     server_info::ServerInfo,
 };
 
+const QR: u16 = 1 << 15;
+const AA: u16 = 1 << 10;
+const TC: u16 = 1 << 9;
+const RD: u16 = 1 << 8;
+const RA: u16 = 1 << 7;
+const AD: u16 = 1 << 5;
+const CD: u16 = 1 << 4;
+const OPCODE_SHIFT: u16 = 11;
+/// Root name, type and class
+const MIN_QUESTION_LEN: usize = 5;
+/// Root name, type, class, ttl and rdlength
+const MIN_RECORD_LEN: usize = 11;
+const OPCODE_MASK: u16 = 0xf << OPCODE_SHIFT;
+const RCODE_MASK: u16 = 0xf;
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Message<'a> {
-    header: Header,
-    questions: Vec<Question<'a>>,
-    answers: Vec<ResourceRecord<'a>>,
-    authorities: Vec<ResourceRecord<'a>>,
-    additionals: Vec<ResourceRecord<'a>>,
+    pub(crate) header: Header,
+    pub(crate) questions: Vec<Question<'a>>,
+    pub(crate) answers: Vec<ResourceRecord<'a>>,
+    pub(crate) authorities: Vec<ResourceRecord<'a>>,
+    pub(crate) additionals: Vec<ResourceRecord<'a>>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-struct Header {
-    id: Cell<u16>,
-    flags: u16,
-    qdcount: u16,
-    ancount: u16,
-    nscount: u16,
-    arcount: u16,
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Header {
+    pub(crate) id: Cell<u16>,
+    pub(crate) flags: u16,
+    pub(crate) qdcount: u16,
+    pub(crate) ancount: u16,
+    pub(crate) nscount: u16,
+    pub(crate) arcount: u16,
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct Question<'a> {
-    qname: &'a [u8],
-    qtype: u16,
-    qclass: u16,
+pub(crate) struct Question<'a> {
+    pub(crate) qname: &'a [u8],
+    pub(crate) qtype: u16,
+    pub(crate) qclass: u16,
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct ResourceRecord<'a> {
+pub struct ResourceRecord<'a> {
     rname: &'a [u8],
     rtype: u16,
     rclass: u16,
@@ -45,21 +70,66 @@ struct ResourceRecord<'a> {
     rdata: &'a [u8],
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Opcode {
+    Query,
+    IQuery,
+    Status,
+    Notify,
+    Update,
+    Dso,
+    Unknown(u8),
+}
+
+/// Response code, including the extended codes only reachable through EDNS and TSIG
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Rcode {
+    NoError,
+    FormErr,
+    ServFail,
+    NxDomain,
+    NotImp,
+    Refused,
+    YxDomain,
+    YxRrset,
+    NxRrset,
+    NotAuth,
+    NotZone,
+    DsoTypeNi,
+    BadVers,
+    BadKey,
+    BadTime,
+    BadMode,
+    BadName,
+    BadAlg,
+    BadTrunc,
+    BadCookie,
+    Unknown(u16),
+}
+
 #[derive(Debug)]
 pub enum ResponseType {
     Answer,
     Delegation,
+    NxDomain,
+    NoData,
     Error,
 }
 
 impl<'a> Message<'a> {
-    pub fn new(id: u16, domain: &'a [u8], recursion: bool) -> Self {
+    pub fn new(
+        id: u16,
+        domain: &'a [u8],
+        qtype: RecordType,
+        qclass: RecordClass,
+        recursion: bool,
+    ) -> Self {
         Self {
             header: Header::new(id, false, false, false, recursion, 0),
             questions: vec![Question::<'a> {
                 qname: domain,
-                qtype: 1,
-                qclass: 1,
+                qtype: qtype.into(),
+                qclass: qclass.into(),
             }],
             answers: Vec::new(),
             authorities: Vec::new(),
@@ -71,6 +141,14 @@ impl<'a> Message<'a> {
         self.header.id.get()
     }
 
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn get_rcode(&self) -> Rcode {
+        self.header.get_rcode()
+    }
+
     pub fn get_ancount(&self) -> u16 {
         self.header.ancount
     }
@@ -83,6 +161,18 @@ impl<'a> Message<'a> {
         self.header.arcount
     }
 
+    pub fn get_answers(&self) -> &[ResourceRecord<'a>] {
+        &self.answers
+    }
+
+    pub fn get_authorities(&self) -> &[ResourceRecord<'a>] {
+        &self.authorities
+    }
+
+    pub fn get_additionals(&self) -> &[ResourceRecord<'a>] {
+        &self.additionals
+    }
+
     pub fn check_error_response(&self, request: &Message) -> Result<(), DnsError> {
         self.header.check_error()?;
         (self.header.id == request.header.id)
@@ -102,6 +192,14 @@ impl<'a> Message<'a> {
         if self.header.ancount > 0 {
             return ResponseType::Answer;
         }
+        if self.get_rcode() == Rcode::NxDomain {
+            return ResponseType::NxDomain;
+        }
+        let is_ns = |rr: &ResourceRecord| rr.get_type() == RecordType::Ns;
+        let is_soa = |rr: &ResourceRecord| rr.get_type() == RecordType::Soa;
+        if self.authorities.iter().any(is_soa) && !self.authorities.iter().any(is_ns) {
+            return ResponseType::NoData;
+        }
         if self.header.nscount > 0 {
             return ResponseType::Delegation;
         }
@@ -109,45 +207,89 @@ impl<'a> Message<'a> {
     }
 
     pub fn is_cname(&self) -> bool {
-        self.answers.iter().any(|a| a.rtype == 5)
+        self.answers
+            .iter()
+            .any(|a| a.get_type() == RecordType::Cname)
     }
 
-    pub fn get_cnames(&self, bytes: &[u8]) -> Vec<CompressedName> {
+    pub fn get_cnames(&self, bytes: &[u8]) -> Result<Vec<Name>, ParseError> {
         let mut cnames = Vec::new();
         for answer in self.answers.iter() {
-            if answer.rtype != 5 {
+            if answer.get_type() != RecordType::Cname {
                 continue;
             }
-            cnames.push(decompress(answer.rdata, bytes));
+            cnames.push(decompress(answer.rdata, bytes)?);
         }
-        cnames
+        Ok(cnames)
     }
 
-    pub fn get_additional_info(&self, bytes: &[u8]) -> Vec<ServerInfo> {
+    pub fn get_additional_info(&self, bytes: &[u8]) -> Result<Vec<ServerInfo>, ParseError> {
         let mut servers = Vec::with_capacity(self.header.arcount as usize);
         for additional in &self.additionals {
+            // Only glue addresses, not OPT or anything else a server might add
+            if !matches!(additional.get_type(), RecordType::A | RecordType::Aaaa) {
+                continue;
+            }
             servers.push(ServerInfo {
-                name: decompress(additional.rname, bytes),
+                name: decompress(additional.rname, bytes)?,
                 ip: bytes_to_ip(additional.rdata),
             });
         }
 
-        servers
+        Ok(servers)
+    }
+
+    /// Returns the EDNS information from the OPT record, if there is one
+    pub fn get_edns(&self, bytes: &[u8]) -> Result<Option<Edns>, ParseError> {
+        for additional in &self.additionals {
+            if additional.get_type() == RecordType::Opt {
+                return Ok(Edns::from_record(&additional.to_record(bytes)?));
+            }
+        }
+        Ok(None)
     }
 
-    pub fn get_authorities_info(&self, bytes: &[u8]) -> Vec<CompressedName> {
+    pub fn get_authorities_info(&self, bytes: &[u8]) -> Result<Vec<Name>, ParseError> {
         let mut names = Vec::with_capacity(self.header.nscount as usize);
-        for additional in &self.authorities {
-            names.push(decompress(additional.rdata, bytes));
+        for authority in &self.authorities {
+            if authority.get_type() != RecordType::Ns {
+                continue;
+            }
+            names.push(decompress(authority.rdata, bytes)?);
         }
 
-        names
+        Ok(names)
+    }
+
+    /// Returns the answers of type `rtype` and class `rclass`
+    pub fn get_answer_records(
+        &self,
+        bytes: &[u8],
+        rtype: RecordType,
+        rclass: RecordClass,
+    ) -> Result<Vec<Record>, ParseError> {
+        let mut records = Vec::new();
+        for answer in &self.answers {
+            if rtype.matches(answer.get_type()) && rclass.matches(answer.get_class()) {
+                records.push(answer.to_record(bytes)?);
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Returns every record of the authority section
+    pub fn get_authority_records(&self, bytes: &[u8]) -> Result<Vec<Record>, ParseError> {
+        self.authorities
+            .iter()
+            .map(|authority| authority.to_record(bytes))
+            .collect()
     }
 
     pub fn get_answer_ips(&self) -> Vec<IpAddr> {
         let mut ips = Vec::with_capacity(self.header.ancount as usize);
         for answer in &self.answers {
-            if answer.rtype != 1 {
+            if !matches!(answer.get_type(), RecordType::A | RecordType::Aaaa) {
                 continue;
             }
             if let Some(ip) = bytes_to_ip(answer.rdata) {
@@ -160,10 +302,12 @@ impl<'a> Message<'a> {
 
     pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, ParseError> {
         let header = Header::from_bytes(bytes)?;
-        let mut questions = Vec::with_capacity(header.qdcount as usize);
-        let mut answers = Vec::with_capacity(header.ancount as usize);
-        let mut authorities = Vec::with_capacity(header.nscount as usize);
-        let mut additionals = Vec::with_capacity(header.arcount as usize);
+        // The counts are untrusted, so don't reserve more entries than the bytes could hold
+        let capacity = |count: u16, min_size: usize| (count as usize).min(bytes.len() / min_size);
+        let mut questions = Vec::with_capacity(capacity(header.qdcount, MIN_QUESTION_LEN));
+        let mut answers = Vec::with_capacity(capacity(header.ancount, MIN_RECORD_LEN));
+        let mut authorities = Vec::with_capacity(capacity(header.nscount, MIN_RECORD_LEN));
+        let mut additionals = Vec::with_capacity(capacity(header.arcount, MIN_RECORD_LEN));
 
         let mut offset = 12;
         for _ in 0..header.qdcount {
@@ -198,19 +342,19 @@ impl<'a> Message<'a> {
 }
 
 impl Header {
-    fn new(id: u16, qr: bool, aa: bool, tc: bool, rd: bool, rcode: u8) -> Self {
+    pub(crate) fn new(id: u16, qr: bool, aa: bool, tc: bool, rd: bool, rcode: u8) -> Self {
         let mut flags: u16 = (rcode & 15).into();
         if qr {
-            flags |= 1 << 15;
+            flags |= QR;
         }
         if aa {
-            flags |= 1 << 10;
+            flags |= AA;
         }
         if tc {
-            flags |= 1 << 9;
+            flags |= TC;
         }
         if rd {
-            flags |= 1 << 8;
+            flags |= RD;
         }
         Self {
             id: id.into(),
@@ -222,7 +366,114 @@ impl Header {
         }
     }
 
-    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+    pub fn get_id(&self) -> u16 {
+        self.id.get()
+    }
+
+    pub fn set_id(&mut self, id: u16) {
+        self.id.set(id);
+    }
+
+    pub fn get_qr(&self) -> bool {
+        self.flags & QR != 0
+    }
+
+    pub fn set_qr(&mut self, on: bool) {
+        self.set_flag(QR, on);
+    }
+
+    pub fn get_opcode(&self) -> Opcode {
+        Opcode::from(((self.flags & OPCODE_MASK) >> OPCODE_SHIFT) as u8)
+    }
+
+    pub fn set_opcode(&mut self, opcode: Opcode) {
+        let opcode = u16::from(u8::from(opcode)) << OPCODE_SHIFT;
+        self.flags = (self.flags & !OPCODE_MASK) | (opcode & OPCODE_MASK);
+    }
+
+    pub fn get_aa(&self) -> bool {
+        self.flags & AA != 0
+    }
+
+    pub fn set_aa(&mut self, on: bool) {
+        self.set_flag(AA, on);
+    }
+
+    pub fn get_tc(&self) -> bool {
+        self.flags & TC != 0
+    }
+
+    pub fn set_tc(&mut self, on: bool) {
+        self.set_flag(TC, on);
+    }
+
+    pub fn get_rd(&self) -> bool {
+        self.flags & RD != 0
+    }
+
+    pub fn set_rd(&mut self, on: bool) {
+        self.set_flag(RD, on);
+    }
+
+    pub fn get_ra(&self) -> bool {
+        self.flags & RA != 0
+    }
+
+    pub fn set_ra(&mut self, on: bool) {
+        self.set_flag(RA, on);
+    }
+
+    pub fn get_ad(&self) -> bool {
+        self.flags & AD != 0
+    }
+
+    pub fn set_ad(&mut self, on: bool) {
+        self.set_flag(AD, on);
+    }
+
+    pub fn get_cd(&self) -> bool {
+        self.flags & CD != 0
+    }
+
+    pub fn set_cd(&mut self, on: bool) {
+        self.set_flag(CD, on);
+    }
+
+    /// Returns the 4 bit rcode of the header, extended rcodes also need the OPT record
+    pub fn get_rcode(&self) -> Rcode {
+        Rcode::from(self.flags & RCODE_MASK)
+    }
+
+    /// Sets the lower 4 bits of `rcode`, the upper bits belong in the OPT record
+    pub fn set_rcode(&mut self, rcode: Rcode) {
+        self.flags = (self.flags & !RCODE_MASK) | (u16::from(rcode) & RCODE_MASK);
+    }
+
+    pub fn get_qdcount(&self) -> u16 {
+        self.qdcount
+    }
+
+    pub fn get_ancount(&self) -> u16 {
+        self.ancount
+    }
+
+    pub fn get_nscount(&self) -> u16 {
+        self.nscount
+    }
+
+    pub fn get_arcount(&self) -> u16 {
+        self.arcount
+    }
+
+    fn set_flag(&mut self, flag: u16, on: bool) {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
         if bytes.len() < 12 {
             return Err(ParseError::InvalidHeader);
         }
@@ -237,99 +488,304 @@ impl Header {
     }
 
     fn check_error(&self) -> Result<(), ResponseCodeError> {
-        match self.flags & 0xf {
-            0 => Ok(()),
-            1 => Err(ResponseCodeError::FormatError),
-            2 => Err(ResponseCodeError::ServerFailure),
-            3 => Err(ResponseCodeError::NameError),
-            4 => Err(ResponseCodeError::NotImplemented),
-            5 => Err(ResponseCodeError::Refused),
-            _ => Ok(()),
-        }
+        self.get_rcode().check()
     }
 }
 
-impl<'a> Question<'a> {
-    fn from_bytes(bytes: &'a [u8], offset: usize) -> Result<(Self, usize), ParseError> {
-        let mut end = offset;
-        while end < bytes.len() && bytes[end] != 0 {
-            if is_pointer(bytes[end]) {
-                end += 1;
-                break;
+impl Header {
+    /// Writes the header the way dig does, with `rcode` as the status since the full rcode
+    /// may need the OPT record
+    pub(crate) fn write_presentation(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        rcode: Rcode,
+    ) -> fmt::Result {
+        writeln!(
+            f,
+            ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+            self.get_opcode(),
+            rcode,
+            self.get_id()
+        )?;
+        write!(f, ";; flags:")?;
+        let flags = [
+            ("qr", QR),
+            ("aa", AA),
+            ("tc", TC),
+            ("rd", RD),
+            ("ra", RA),
+            ("ad", AD),
+            ("cd", CD),
+        ];
+        for (name, flag) in flags {
+            if self.flags & flag != 0 {
+                write!(f, " {}", name)?;
             }
-            let label_len = bytes[end] as usize;
-            end += 1 + label_len;
         }
-        end += 1;
-        if end + 4 > bytes.len() {
-            return Err(ParseError::InvalidQuestion);
+        write!(
+            f,
+            "; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            self.qdcount, self.ancount, self.nscount, self.arcount
+        )
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_presentation(f, self.get_rcode())
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self {
+            Opcode::Query => "QUERY",
+            Opcode::IQuery => "IQUERY",
+            Opcode::Status => "STATUS",
+            Opcode::Notify => "NOTIFY",
+            Opcode::Update => "UPDATE",
+            Opcode::Dso => "DSO",
+            Opcode::Unknown(other) => return write!(f, "OPCODE{}", other),
+        };
+        f.write_str(mnemonic)
+    }
+}
+
+impl fmt::Display for Rcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self {
+            Rcode::NoError => "NOERROR",
+            Rcode::FormErr => "FORMERR",
+            Rcode::ServFail => "SERVFAIL",
+            Rcode::NxDomain => "NXDOMAIN",
+            Rcode::NotImp => "NOTIMP",
+            Rcode::Refused => "REFUSED",
+            Rcode::YxDomain => "YXDOMAIN",
+            Rcode::YxRrset => "YXRRSET",
+            Rcode::NxRrset => "NXRRSET",
+            Rcode::NotAuth => "NOTAUTH",
+            Rcode::NotZone => "NOTZONE",
+            Rcode::DsoTypeNi => "DSOTYPENI",
+            Rcode::BadVers => "BADVERS",
+            Rcode::BadKey => "BADKEY",
+            Rcode::BadTime => "BADTIME",
+            Rcode::BadMode => "BADMODE",
+            Rcode::BadName => "BADNAME",
+            Rcode::BadAlg => "BADALG",
+            Rcode::BadTrunc => "BADTRUNC",
+            Rcode::BadCookie => "BADCOOKIE",
+            Rcode::Unknown(other) => return write!(f, "RCODE{}", other),
+        };
+        f.write_str(mnemonic)
+    }
+}
+
+impl From<u8> for Opcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Query,
+            1 => Self::IQuery,
+            2 => Self::Status,
+            4 => Self::Notify,
+            5 => Self::Update,
+            6 => Self::Dso,
+            other => Self::Unknown(other),
         }
-        let qname = &bytes[offset..end];
-        let qtype = u16::from_be_bytes([bytes[end], bytes[end + 1]]);
-        let qclass = u16::from_be_bytes([bytes[end + 2], bytes[end + 3]]);
-        end += 4;
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(value: Opcode) -> Self {
+        match value {
+            Opcode::Query => 0,
+            Opcode::IQuery => 1,
+            Opcode::Status => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::Dso => 6,
+            Opcode::Unknown(other) => other,
+        }
+    }
+}
+
+impl From<u16> for Rcode {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Self::NoError,
+            1 => Self::FormErr,
+            2 => Self::ServFail,
+            3 => Self::NxDomain,
+            4 => Self::NotImp,
+            5 => Self::Refused,
+            6 => Self::YxDomain,
+            7 => Self::YxRrset,
+            8 => Self::NxRrset,
+            9 => Self::NotAuth,
+            10 => Self::NotZone,
+            11 => Self::DsoTypeNi,
+            16 => Self::BadVers,
+            17 => Self::BadKey,
+            18 => Self::BadTime,
+            19 => Self::BadMode,
+            20 => Self::BadName,
+            21 => Self::BadAlg,
+            22 => Self::BadTrunc,
+            23 => Self::BadCookie,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+impl From<Rcode> for u16 {
+    fn from(value: Rcode) -> Self {
+        match value {
+            Rcode::NoError => 0,
+            Rcode::FormErr => 1,
+            Rcode::ServFail => 2,
+            Rcode::NxDomain => 3,
+            Rcode::NotImp => 4,
+            Rcode::Refused => 5,
+            Rcode::YxDomain => 6,
+            Rcode::YxRrset => 7,
+            Rcode::NxRrset => 8,
+            Rcode::NotAuth => 9,
+            Rcode::NotZone => 10,
+            Rcode::DsoTypeNi => 11,
+            Rcode::BadVers => 16,
+            Rcode::BadKey => 17,
+            Rcode::BadTime => 18,
+            Rcode::BadMode => 19,
+            Rcode::BadName => 20,
+            Rcode::BadAlg => 21,
+            Rcode::BadTrunc => 22,
+            Rcode::BadCookie => 23,
+            Rcode::Unknown(other) => other,
+        }
+    }
+}
+
+impl From<ResponseCodeError> for Rcode {
+    fn from(value: ResponseCodeError) -> Self {
+        match value {
+            ResponseCodeError::FormatError => Rcode::FormErr,
+            ResponseCodeError::ServerFailure => Rcode::ServFail,
+            ResponseCodeError::NameError => Rcode::NxDomain,
+            ResponseCodeError::NotImplemented => Rcode::NotImp,
+            ResponseCodeError::Refused => Rcode::Refused,
+            ResponseCodeError::Other(rcode) => Rcode::from(rcode),
+        }
+    }
+}
+
+impl Rcode {
+    /// Returns the error for every rcode except NOERROR
+    pub fn check(self) -> Result<(), ResponseCodeError> {
+        match self {
+            Rcode::NoError => Ok(()),
+            Rcode::FormErr => Err(ResponseCodeError::FormatError),
+            Rcode::ServFail => Err(ResponseCodeError::ServerFailure),
+            Rcode::NxDomain => Err(ResponseCodeError::NameError),
+            Rcode::NotImp => Err(ResponseCodeError::NotImplemented),
+            Rcode::Refused => Err(ResponseCodeError::Refused),
+            other => Err(ResponseCodeError::Other(other.into())),
+        }
+    }
+}
+
+impl<'a> Question<'a> {
+    pub(crate) fn from_bytes(bytes: &'a [u8], offset: usize) -> Result<(Self, usize), ParseError> {
+        let error = ParseError::InvalidQuestion(offset);
+        let end = name_end(bytes, offset).ok_or(error.clone())?;
+        let fields = bytes.get(end..end + 4).ok_or(error)?;
 
         Ok((
             Self {
-                qname,
-                qtype,
-                qclass,
+                qname: &bytes[offset..end],
+                qtype: u16::from_be_bytes([fields[0], fields[1]]),
+                qclass: u16::from_be_bytes([fields[2], fields[3]]),
             },
-            end,
+            end + 4,
         ))
     }
 }
 
 impl<'a> ResourceRecord<'a> {
-    fn from_bytes(bytes: &'a [u8], offset: usize) -> Result<(Self, usize), ParseError> {
-        let mut end = offset;
-        while end < bytes.len() && bytes[end] != 0 {
-            if is_pointer(bytes[end]) {
-                end += 1;
-                break;
-            }
-            let label_len = bytes[end] as usize;
-            end += 1 + label_len;
-        }
-        end += 1;
-        if end + 10 > bytes.len() {
-            return Err(ParseError::InvalidResourcRecord);
-        }
-        let rname = &bytes[offset..end];
-        let rtype = u16::from_be_bytes([bytes[end], bytes[end + 1]]);
-        let rclass = u16::from_be_bytes([bytes[end + 2], bytes[end + 3]]);
-        let ttl = u32::from_be_bytes([
-            bytes[end + 4],
-            bytes[end + 5],
-            bytes[end + 6],
-            bytes[end + 7],
-        ]);
-        let rdlength = u16::from_be_bytes([bytes[end + 8], bytes[end + 9]]);
-        end += 10;
-        if end + (rdlength as usize) > bytes.len() {
-            return Err(ParseError::InvalidResourcRecord);
-        }
-        let rdata = &bytes[end..end + rdlength as usize];
-        end += rdlength as usize;
+    pub fn get_name(&self, bytes: &[u8]) -> Result<Name, ParseError> {
+        decompress(self.rname, bytes)
+    }
+
+    pub fn get_type(&self) -> RecordType {
+        self.rtype.into()
+    }
+
+    pub fn get_class(&self) -> RecordClass {
+        self.rclass.into()
+    }
+
+    pub fn get_ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    pub fn get_raw_rdata(&self) -> &'a [u8] {
+        self.rdata
+    }
+
+    /// Decodes the rdata, `bytes` is the whole message the record was parsed from
+    pub fn get_rdata(&self, bytes: &[u8]) -> Result<RData, ParseError> {
+        RData::from_bytes(self.get_type(), self.rdata, bytes)
+    }
+
+    pub fn to_record(&self, bytes: &[u8]) -> Result<Record, ParseError> {
+        Ok(Record {
+            name: self.get_name(bytes)?,
+            rtype: self.get_type(),
+            rclass: self.get_class(),
+            ttl: self.ttl,
+            rdata: self.get_rdata(bytes)?,
+        })
+    }
+
+    pub fn from_bytes(bytes: &'a [u8], offset: usize) -> Result<(Self, usize), ParseError> {
+        let error = ParseError::InvalidResourcRecord(offset);
+        let end = name_end(bytes, offset).ok_or(error.clone())?;
+        let fields = bytes.get(end..end + 10).ok_or(error.clone())?;
+        let rdlength = u16::from_be_bytes([fields[8], fields[9]]);
+        let rdata_start = end + 10;
+        let rdata_end = rdata_start + rdlength as usize;
+        let rdata = bytes.get(rdata_start..rdata_end).ok_or(error)?;
+
         Ok((
             Self {
-                rname,
-                rtype,
-                rclass,
-                ttl,
+                rname: &bytes[offset..end],
+                rtype: u16::from_be_bytes([fields[0], fields[1]]),
+                rclass: u16::from_be_bytes([fields[2], fields[3]]),
+                ttl: u32::from_be_bytes([fields[4], fields[5], fields[6], fields[7]]),
                 rdlength,
                 rdata,
             },
-            end,
+            rdata_end,
         ))
     }
 }
 
 pub trait Encodable {
     fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Encodes `self`, replacing names by pointers to names already written where allowed
+    ///
+    /// `buf` must start at the beginning of the message
+    fn encode_compressed(&self, buf: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        let _ = compressor;
+        self.encode(buf);
+    }
 }
 
 impl Encodable for Message<'_> {
+    /// Encodes the message in the layout it was read in
+    ///
+    /// Names and rdata may hold pointers to offsets of the original message, which only stay
+    /// valid if nothing before them moves. [`OwnedMessage`](crate::builder::OwnedMessage)
+    /// decompresses everything, and so can compress the names again
     fn encode(&self, buf: &mut Vec<u8>) {
         self.header.encode(buf);
 
@@ -382,37 +838,198 @@ impl Encodable for ResourceRecord<'_> {
 }
 
 pub fn error_in_message(id: u16, bytes: &[u8]) -> Result<(), DnsError> {
-    if bytes.len() < 12 {
-        return Err(DnsError::ParsingError(ParseError::InvalidHeader));
-    }
-    let resp_id = u16::from_be_bytes([bytes[0], bytes[1]]);
-    let flags = u16::from_be_bytes([bytes[2], bytes[3]]);
-    let ancount = u16::from_be_bytes([bytes[6], bytes[7]]);
-    let nscount = u16::from_be_bytes([bytes[8], bytes[9]]);
+    let header = Header::from_bytes(bytes)?;
 
-    if id != resp_id {
+    if id != header.get_id() {
         return Err(DnsError::InvalidResponseID);
     }
-    match flags & 0xf {
-        0 => Ok(()),
-        1 => Err(ResponseCodeError::FormatError),
-        2 => Err(ResponseCodeError::ServerFailure),
-        3 => Err(ResponseCodeError::NameError),
-        4 => Err(ResponseCodeError::NotImplemented),
-        5 => Err(ResponseCodeError::Refused),
-        _ => Ok(()),
-    }?;
-
-    if ancount == 0 && nscount == 0 {
+    // Extended rcodes like BADCOOKIE only make sense with the upper bits from the OPT record
+    let mut rcode = header.get_rcode();
+    if header.arcount > 0 {
+        let message = Message::from_bytes(bytes)?;
+        if let Some(edns) = message.get_edns(bytes)? {
+            rcode = edns.get_rcode(rcode);
+        }
+    }
+    rcode.check()?;
+
+    if header.ancount == 0 && header.nscount == 0 {
         return Err(DnsError::InvalidFormat);
     }
 
     Ok(())
 }
 
+/// Bytes shown on each line of [`dissect`]
+const DISSECT_ROW_LEN: usize = 8;
+
+/// Renders `bytes` as an annotated hex dump, with a line per field giving its offset, bytes,
+/// name and decoded value, in the spirit of the DNS tree in Wireshark
+///
+/// Malformed packets are dissected up to the first error, which ends the dump
+pub fn dissect(bytes: &[u8]) -> String {
+    let mut dissector = Dissector {
+        bytes,
+        out: String::new(),
+    };
+    if let Err(e) = dissector.message() {
+        let _ = writeln!(dissector.out, "error: {}", e);
+    }
+    dissector.out
+}
+
+struct Dissector<'a> {
+    bytes: &'a [u8],
+    out: String,
+}
+
+impl Dissector<'_> {
+    fn message(&mut self) -> Result<(), ParseError> {
+        let header = Header::from_bytes(self.bytes)?;
+        self.field(0, 2, "ID", header.get_id());
+        let mut flags = String::new();
+        for (name, on) in [
+            ("qr", header.get_qr()),
+            ("aa", header.get_aa()),
+            ("tc", header.get_tc()),
+            ("rd", header.get_rd()),
+            ("ra", header.get_ra()),
+            ("ad", header.get_ad()),
+            ("cd", header.get_cd()),
+        ] {
+            if on {
+                flags.push_str(name);
+                flags.push(' ');
+            }
+        }
+        let flags = format!(
+            "{}opcode {}, rcode {}",
+            flags,
+            header.get_opcode(),
+            header.get_rcode()
+        );
+        self.field(2, 2, "Flags", flags);
+        self.field(4, 2, "QDCOUNT", header.qdcount);
+        self.field(6, 2, "ANCOUNT", header.ancount);
+        self.field(8, 2, "NSCOUNT", header.nscount);
+        self.field(10, 2, "ARCOUNT", header.arcount);
+
+        let mut offset = 12;
+        for i in 1..=header.qdcount {
+            let _ = writeln!(self.out, ";; Question {}", i);
+            let error = ParseError::InvalidQuestion(offset);
+            offset = self.name(offset)?;
+            let fields = self.bytes.get(offset..offset + 4).ok_or(error)?;
+            let qtype = RecordType::from(u16::from_be_bytes([fields[0], fields[1]]));
+            let qclass = RecordClass::from(u16::from_be_bytes([fields[2], fields[3]]));
+            self.field(offset, 2, "QTYPE", qtype);
+            self.field(offset + 2, 2, "QCLASS", qclass);
+            offset += 4;
+        }
+
+        let sections = [
+            ("Answer", header.ancount),
+            ("Authority", header.nscount),
+            ("Additional", header.arcount),
+        ];
+        for (section, count) in sections {
+            for i in 1..=count {
+                let _ = writeln!(self.out, ";; {} {}", section, i);
+                offset = self.record(offset)?;
+            }
+        }
+
+        if offset < self.bytes.len() {
+            self.field(offset, self.bytes.len() - offset, "Trailing", "");
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, start: usize) -> Result<usize, ParseError> {
+        let (rr, end) = ResourceRecord::from_bytes(self.bytes, start)?;
+        let offset = self.name(start)?;
+        self.field(offset, 2, "TYPE", rr.get_type());
+        self.field(offset + 2, 2, "CLASS", rr.get_class());
+        self.field(offset + 4, 4, "TTL", rr.ttl);
+        self.field(offset + 8, 2, "RDLENGTH", rr.rdlength);
+        let rdata = match rr.get_rdata(self.bytes) {
+            Ok(rdata) => rdata.to_string(),
+            Err(e) => format!("error: {}", e),
+        };
+        self.field(offset + 10, rr.rdata.len(), "RDATA", rdata);
+        Ok(end)
+    }
+
+    /// Writes a line for each label of the name at `offset`, returning the end of the name
+    fn name(&mut self, offset: usize) -> Result<usize, ParseError> {
+        let mut i = offset;
+        loop {
+            let len = *self.bytes.get(i).ok_or(ParseError::TruncatedName)?;
+            if is_pointer(len) {
+                if i + 2 > self.bytes.len() {
+                    return Err(ParseError::TruncatedName);
+                }
+                let target = pointer_to_offset(len, self.bytes[i + 1]);
+                let value = match decompress(&self.bytes[i..i + 2], self.bytes) {
+                    Ok(name) => format!("-> {:#06x} {}", target, name),
+                    Err(e) => format!("-> {:#06x} error: {}", target, e),
+                };
+                self.field(i, 2, "Pointer", value);
+                return Ok(i + 2);
+            }
+            if len == 0 {
+                self.field(i, 1, "Root", "");
+                return Ok(i + 1);
+            }
+            if len > MAX_LABEL_LEN {
+                return Err(ParseError::LabelTooLong);
+            }
+            let label = self
+                .bytes
+                .get(i + 1..i + 1 + len as usize)
+                .ok_or(ParseError::TruncatedName)?;
+            let value = format!("{:?}", String::from_utf8_lossy(label));
+            self.field(i, 1 + len as usize, "Label", value);
+            i += 1 + len as usize;
+        }
+    }
+
+    /// Writes the `len` bytes at `offset`, wrapping long fields over several lines
+    fn field(&mut self, offset: usize, len: usize, name: &str, value: impl fmt::Display) {
+        let end = (offset + len).min(self.bytes.len());
+        let mut rows = self.bytes[offset..end].chunks(DISSECT_ROW_LEN);
+        let hex = |row: &[u8]| {
+            row.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let first = rows.next().unwrap_or(&[]);
+        let _ = writeln!(
+            self.out,
+            "{:04x}  {:<width$}  {:<9} {}",
+            offset,
+            hex(first),
+            name,
+            value,
+            width = DISSECT_ROW_LEN * 3 - 1
+        );
+        for (i, row) in rows.enumerate() {
+            let row_offset = offset + (i + 1) * DISSECT_ROW_LEN;
+            let _ = writeln!(self.out, "{:04x}  {}", row_offset, hex(row));
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::builder::OwnedMessage;
+
+    fn wire(domain: &str) -> Vec<u8> {
+        domain.parse::<Name>().unwrap().as_wire().to_vec()
+    }
 
     #[test]
     fn test_header_encode_from_bytes_eq() {
@@ -453,7 +1070,7 @@ mod test {
         ];
         let output = Question::from_bytes(&buf, 0);
         match output {
-            Err(ParseError::InvalidQuestion) => {}
+            Err(ParseError::InvalidQuestion(0)) => {}
             _ => panic!("Bytes are not long enough"),
         }
     }
@@ -489,7 +1106,7 @@ mod test {
         let domains: [u8; 12] = [
             6, b'g', b'o', b'o', b'g', b'l', b'e', 3, b'c', b'o', b'm', 0,
         ];
-        let message = Message::new(1, &domains);
+        let message = Message::new(1, &domains, RecordType::A, RecordClass::In, false);
         let mut buf = Vec::new();
         message.encode(&mut buf);
         let output = Message::from_bytes(&buf);
@@ -501,6 +1118,204 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_rr_get_rdata() {
+        let mut bytes = wire("example.com");
+        bytes.extend_from_slice(&[0, 5, 0, 1, 0, 0, 0, 60, 0, 6]);
+        bytes.extend_from_slice(&[3, b'w', b'w', b'w', 0b11000000, 0]);
+        let (rr, _) = ResourceRecord::from_bytes(&bytes, 0).unwrap();
+
+        let expected = "www.example.com".parse().unwrap();
+        assert_eq!(rr.get_rdata(&bytes).unwrap(), RData::Cname(expected));
+    }
+
+    #[test]
+    fn test_message_get_answer_records() {
+        let mut bytes = vec![0, 1, 0x80, 0, 0, 0, 0, 2, 0, 0, 0, 0];
+        bytes.extend_from_slice(&wire("example.com"));
+        bytes.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1]);
+        bytes.extend_from_slice(&[0b11000000, 12, 0, 28, 0, 1, 0, 0, 0, 60, 0, 16]);
+        bytes.extend_from_slice(&[0; 16]);
+        let message = Message::from_bytes(&bytes).unwrap();
+
+        let records = message
+            .get_answer_records(&bytes, RecordType::A, RecordClass::In)
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].rdata, RData::A([192, 0, 2, 1].into()));
+        assert_eq!(records[0].name.as_wire(), wire("example.com"));
+
+        let records = message
+            .get_answer_records(&bytes, RecordType::Any, RecordClass::In)
+            .unwrap();
+        assert_eq!(records.len(), 2);
+
+        let ips = message.get_answer_ips();
+        assert_eq!(
+            ips,
+            [
+                IpAddr::from([192, 0, 2, 1]),
+                IpAddr::from(std::net::Ipv6Addr::UNSPECIFIED)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_message_get_type() {
+        // Negative response with the SOA of example.com in the authority section
+        let mut bytes = vec![0, 1, 0x80, 0, 0, 0, 0, 0, 0, 1, 0, 0];
+        bytes.extend_from_slice(&wire("example.com"));
+        bytes.extend_from_slice(&[0, 6, 0, 1, 0, 0, 0, 60, 0, 22, 0, 0]);
+        bytes.extend_from_slice(&[0; 20]);
+        let message = Message::from_bytes(&bytes).unwrap();
+        assert!(matches!(message.get_type(), ResponseType::NoData));
+
+        bytes[3] = u16::from(Rcode::NxDomain) as u8;
+        let message = Message::from_bytes(&bytes).unwrap();
+        assert!(matches!(message.get_type(), ResponseType::NxDomain));
+    }
+
+    #[test]
+    fn test_message_encode_compressed_input() {
+        // www.example.com CNAME cdn.example.com, with pointers in the rdata and to it
+        let mut bytes = vec![0, 1, 0x80, 0, 0, 1, 0, 2, 0, 0, 0, 0];
+        bytes.extend_from_slice(&wire("www.example.com"));
+        bytes.extend_from_slice(&[0, 1, 0, 1]);
+        bytes.extend_from_slice(&wire("www.example.com"));
+        bytes.extend_from_slice(&[0, 5, 0, 1, 0, 0, 0, 60, 0, 6]);
+        bytes.extend_from_slice(&[3, b'c', b'd', b'n', 0xC0, 16]);
+        bytes.extend_from_slice(&[0xC0, 60, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1]);
+        let message = Message::from_bytes(&bytes).unwrap();
+        let records: Vec<_> = message
+            .answers
+            .iter()
+            .map(|rr| rr.to_record(&bytes).unwrap())
+            .collect();
+        assert_eq!(records[1].name.to_string(), "cdn.example.com.");
+
+        // Borrowed messages keep their layout, so the pointers stay valid
+        let mut buf = Vec::new();
+        message.encode(&mut buf);
+        assert_eq!(buf, bytes);
+
+        // Owned ones are decompressed, and compressed again from scratch
+        let mut buf = Vec::new();
+        OwnedMessage::from_bytes(&bytes).unwrap().encode(&mut buf);
+        assert!(buf.len() < bytes.len());
+        let output = Message::from_bytes(&buf).unwrap();
+        for (rr, record) in output.answers.iter().zip(&records) {
+            assert_eq!(&rr.to_record(&buf).unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn test_additional_info_skips_opt() {
+        let mut bytes = vec![0, 1, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+        bytes.extend_from_slice(&wire("ns1.example.com"));
+        bytes.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 53]);
+        bytes.extend_from_slice(&[0, 0, 41, 16, 0, 0, 0, 0, 0, 0, 0]);
+        let message = Message::from_bytes(&bytes).unwrap();
+
+        let servers = message.get_additional_info(&bytes).unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].ip, Some(IpAddr::from([192, 0, 2, 53])));
+        assert_eq!(
+            message
+                .get_edns(&bytes)
+                .unwrap()
+                .map(|e| e.udp_payload_size),
+            Some(4096)
+        );
+    }
+
+    #[test]
+    fn test_error_in_message_extended_rcode() {
+        // NOERROR in the header, but BADVERS (16) through the OPT record
+        let mut bytes = vec![0, 1, 0x80, 0, 0, 0, 0, 0, 0, 1, 0, 1];
+        bytes.extend_from_slice(&wire("example.com"));
+        bytes.extend_from_slice(&[0, 6, 0, 1, 0, 0, 0, 60, 0, 0]);
+        bytes.extend_from_slice(&[0, 0, 41, 4, 0xd0, 1, 0, 0, 0, 0, 0]);
+
+        let result = error_in_message(1, &bytes);
+        assert!(matches!(
+            result,
+            Err(DnsError::ResponseCodeError(ResponseCodeError::Other(16)))
+        ));
+
+        // BADCOOKIE (23), whose lower bits in the header would read as YXRRSET
+        bytes[3] = 7;
+        let result = error_in_message(1, &bytes);
+        assert!(matches!(
+            result,
+            Err(DnsError::ResponseCodeError(ResponseCodeError::Other(23)))
+        ));
+    }
+
+    #[test]
+    fn test_rcode_from_response_code_error() {
+        for rcode in [
+            Rcode::FormErr,
+            Rcode::NxDomain,
+            Rcode::Refused,
+            Rcode::BadCookie,
+        ] {
+            assert_eq!(Rcode::from(rcode.check().unwrap_err()), rcode);
+        }
+    }
+
+    #[test]
+    fn test_dissect() {
+        let mut bytes = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+        bytes.extend_from_slice(&wire("example.com"));
+        bytes.extend_from_slice(&[0, 1, 0, 1]);
+        bytes.extend_from_slice(&[0b11000000, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1]);
+
+        let output = dissect(&bytes);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "0000  12 34                    ID        4660");
+        assert_eq!(
+            lines[1],
+            "0002  81 80                    Flags     qr rd ra opcode QUERY, rcode NOERROR"
+        );
+        assert_eq!(lines[6], ";; Question 1");
+        assert_eq!(
+            lines[7],
+            "000c  07 65 78 61 6d 70 6c 65  Label     \"example\""
+        );
+        assert_eq!(
+            lines[13],
+            "001d  c0 0c                    Pointer   -> 0x000c example.com."
+        );
+        assert_eq!(
+            lines.last(),
+            Some(&"0029  c0 00 02 01              RDATA     192.0.2.1")
+        );
+
+        // Everything up to the error is still shown
+        let output = dissect(&bytes[..bytes.len() - 2]);
+        assert!(output.contains("QCLASS    IN"));
+        assert!(output.ends_with("error: ResourceRecord at offset 29 is not large enough\n"));
+    }
+
+    #[test]
+    fn test_message_from_bytes_error_offset() {
+        let mut bytes = vec![0, 1, 0x80, 0, 0, 1, 0, 1, 0, 0, 0, 0];
+        bytes.extend_from_slice(&wire("example.com"));
+        bytes.extend_from_slice(&[0, 1, 0, 1]);
+        let answer_offset = bytes.len();
+        bytes.extend_from_slice(&[0b11000000, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192]);
+
+        let result = Message::from_bytes(&bytes);
+        assert!(matches!(result, Err(ParseError::InvalidResourcRecord(o)) if o == answer_offset));
+
+        // Counts claiming far more records than there are bytes for
+        bytes[4..12].copy_from_slice(&[0xff; 8]);
+        assert!(matches!(
+            Message::from_bytes(&bytes),
+            Err(ParseError::InvalidQuestion(_))
+        ));
+    }
+
     #[test]
     fn test_rr_from_bytes_out_of_bounds() {
         // minimal invalid record: too short for rdata
@@ -512,7 +1327,7 @@ mod test {
             1, 2, // but only 2 bytes of rdata!
         ];
         let result = ResourceRecord::from_bytes(&bad, 0);
-        assert!(matches!(result, Err(ParseError::InvalidResourcRecord)));
+        assert!(matches!(result, Err(ParseError::InvalidResourcRecord(0))));
     }
 
     #[test]
@@ -523,4 +1338,38 @@ mod test {
         let res = header.check_error();
         assert!(matches!(res, Err(ResponseCodeError::NameError)));
     }
+
+    #[test]
+    fn test_header_rcode_above_5_is_error() {
+        let mut bytes = [0u8; 12];
+        bytes[3] = 9; // NOTAUTH
+        let header = Header::from_bytes(&bytes).unwrap();
+        assert_eq!(header.get_rcode(), Rcode::NotAuth);
+        assert!(matches!(
+            header.check_error(),
+            Err(ResponseCodeError::Other(9))
+        ));
+    }
+
+    #[test]
+    fn test_header_flag_accessors() {
+        let mut header = Header::new(1, false, false, false, false, 0);
+        header.set_qr(true);
+        header.set_opcode(Opcode::Update);
+        header.set_aa(true);
+        header.set_ra(true);
+        header.set_cd(true);
+        header.set_rcode(Rcode::BadCookie);
+
+        let mut buf = Vec::new();
+        header.encode(&mut buf);
+        assert_eq!(u16::from_be_bytes([buf[2], buf[3]]), 0xac97);
+
+        let output = Header::from_bytes(&buf).unwrap();
+        assert!(output.get_qr() && output.get_aa() && output.get_ra() && output.get_cd());
+        assert!(!output.get_tc() && !output.get_rd() && !output.get_ad());
+        assert_eq!(output.get_opcode(), Opcode::Update);
+        // Only the lower 4 bits of BADCOOKIE (23) fit in the header
+        assert_eq!(output.get_rcode(), Rcode::YxRrset);
+    }
 }
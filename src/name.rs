@@ -0,0 +1,497 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use crate::{
+    compression::{MAX_LABEL_LEN, MAX_NAME_LEN, is_pointer},
+    error::ParseError,
+};
+
+/// Domain name, stored in uncompressed wire format ending with the root label
+///
+/// The name is always valid: every label is between 1 and 63 octets, and the whole name is at
+/// most 255 octets
+///
+/// Like in DNS, names compare and hash without regard to ASCII case, and are ordered in the
+/// canonical order of RFC 4034 section 6.1
+#[derive(Debug, Clone)]
+pub struct Name {
+    wire: Vec<u8>,
+}
+
+impl Name {
+    pub fn root() -> Self {
+        Self { wire: vec![0] }
+    }
+
+    /// Reads the uncompressed name at the start of `bytes`, ignoring anything after it
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the name contains a pointer, is truncated or
+    /// breaks the length limits
+    pub fn from_wire(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut i = 0;
+        loop {
+            let len = *bytes.get(i).ok_or(ParseError::TruncatedName)?;
+            if is_pointer(len) {
+                return Err(ParseError::InvalidPointer);
+            }
+            if len > MAX_LABEL_LEN {
+                return Err(ParseError::LabelTooLong);
+            }
+            i += 1 + len as usize;
+            if i > MAX_NAME_LEN {
+                return Err(ParseError::NameTooLong);
+            }
+            if len == 0 {
+                return Ok(Self {
+                    wire: bytes[..i].to_vec(),
+                });
+            }
+            if i > bytes.len() {
+                return Err(ParseError::TruncatedName);
+            }
+        }
+    }
+
+    /// Wraps `wire` without checking it, for names already validated while decompressing
+    pub(crate) fn from_wire_unchecked(wire: Vec<u8>) -> Self {
+        Self { wire }
+    }
+
+    /// Parses a name typed by a user, converting international labels to A-labels with the
+    /// UTS #46 processing of IDNA 2008
+    ///
+    /// ASCII input is parsed as presentation format, keeping its escapes
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if IDNA rejects the name or the result is not valid
+    pub fn from_unicode(s: &str) -> Result<Self, ParseError> {
+        if s.is_ascii() {
+            return s.parse();
+        }
+        let ascii = idna::domain_to_ascii(s).map_err(|_| ParseError::InvalidIdna)?;
+        ascii.parse()
+    }
+
+    /// Formats the name like [`Display`](fmt::Display), but with A-labels shown as Unicode
+    ///
+    /// Labels that do not decode are left as they are
+    pub fn to_unicode(&self) -> String {
+        let mut s = String::new();
+        for label in self.labels() {
+            let is_a_label = label.len() > 4 && label[..4].eq_ignore_ascii_case(b"xn--");
+            let unicode = std::str::from_utf8(label)
+                .ok()
+                .filter(|_| is_a_label)
+                .and_then(|l| {
+                    let (unicode, result) = idna::domain_to_unicode(l);
+                    result.ok().map(|_| unicode)
+                });
+            match unicode {
+                Some(unicode) => s.push_str(&unicode),
+                None => {
+                    let _ = write_label(&mut s, label);
+                }
+            }
+            s.push('.');
+        }
+        if s.is_empty() {
+            s.push('.');
+        }
+        s
+    }
+
+    /// Builds a name from its labels, without the root label
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a label is empty or the length limits are broken
+    pub fn from_labels<'a>(labels: impl IntoIterator<Item = &'a [u8]>) -> Result<Self, ParseError> {
+        let mut wire = Vec::new();
+        for label in labels {
+            push_label(&mut wire, label)?;
+        }
+        wire.push(0);
+        Ok(Self { wire })
+    }
+
+    pub fn as_wire(&self) -> &[u8] {
+        &self.wire
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.wire.len() == 1
+    }
+
+    /// Returns the name with every ASCII letter lowercased, the canonical form of RFC 4034 6.2
+    pub fn to_lowercase(&self) -> Name {
+        // Length octets are at most 63, below every uppercase letter
+        Self {
+            wire: self.wire.to_ascii_lowercase(),
+        }
+    }
+
+    /// Returns true if the first label is `*`
+    pub fn is_wildcard(&self) -> bool {
+        self.wire.starts_with(&[1, b'*'])
+    }
+
+    /// Returns the labels of the name, without the root label
+    pub fn labels(&self) -> impl DoubleEndedIterator<Item = &[u8]> + Clone {
+        let mut labels = Vec::new();
+        let mut i = 0;
+        while self.wire[i] != 0 {
+            let len = self.wire[i] as usize;
+            labels.push(&self.wire[i + 1..i + 1 + len]);
+            i += 1 + len;
+        }
+        labels.into_iter()
+    }
+
+    /// Number of labels, not counting the root label
+    pub fn label_count(&self) -> usize {
+        self.labels().count()
+    }
+
+    /// Returns true if `self` is `other` or below it
+    pub fn is_subdomain_of(&self, other: &Name) -> bool {
+        let mut labels = self.labels().rev();
+        other
+            .labels()
+            .rev()
+            .all(|label| labels.next().is_some_and(|l| l.eq_ignore_ascii_case(label)))
+    }
+
+    /// Returns the name with its first label removed, or `None` for the root
+    pub fn parent(&self) -> Option<Name> {
+        let &len = self.wire.first()?;
+        if len == 0 {
+            return None;
+        }
+        Some(Self {
+            wire: self.wire[1 + len as usize..].to_vec(),
+        })
+    }
+
+    /// Returns the longest name that both `self` and `other` are subdomains of
+    pub fn common_ancestor(&self, other: &Name) -> Name {
+        let shared = self
+            .labels()
+            .rev()
+            .zip(other.labels().rev())
+            .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+            .count();
+
+        self.suffix(shared)
+    }
+
+    /// Returns the ancestor made of the last `labels` labels, or the name itself if it
+    /// doesn't have more
+    pub fn suffix(&self, labels: usize) -> Name {
+        let mut ancestor = self.clone();
+        for _ in labels..self.label_count() {
+            ancestor = ancestor.parent().unwrap_or_else(Name::root);
+        }
+        ancestor
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.wire.eq_ignore_ascii_case(&other.wire)
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for b in &self.wire {
+            state.write_u8(b.to_ascii_lowercase());
+        }
+    }
+}
+
+impl Ord for Name {
+    /// Canonical order: labels are compared from the root down as lowercase octet strings,
+    /// and a name sorts before its subdomains
+    fn cmp(&self, other: &Self) -> Ordering {
+        let lowercase = |label: &[u8]| -> Vec<u8> { label.to_ascii_lowercase() };
+        self.labels()
+            .rev()
+            .map(lowercase)
+            .cmp(other.labels().rev().map(lowercase))
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Appends `label` to a name in wire format, checking the length limits
+fn push_label(wire: &mut Vec<u8>, label: &[u8]) -> Result<(), ParseError> {
+    if label.is_empty() {
+        return Err(ParseError::EmptyLabel);
+    }
+    if label.len() > MAX_LABEL_LEN as usize {
+        return Err(ParseError::LabelTooLong);
+    }
+    // Leave room for the root label
+    if wire.len() + 1 + label.len() + 1 > MAX_NAME_LEN {
+        return Err(ParseError::NameTooLong);
+    }
+    wire.push(label.len() as u8);
+    wire.extend_from_slice(label);
+    Ok(())
+}
+
+impl FromStr for Name {
+    type Err = ParseError;
+
+    /// Parses a name in presentation format (RFC 1035 section 5.1), with `\X` and `\DDD`
+    /// escapes
+    ///
+    /// Every name is taken as absolute, so the trailing dot is optional
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "." {
+            return Ok(Self::root());
+        }
+
+        let mut wire = Vec::new();
+        let mut label = Vec::new();
+        let mut bytes = s.bytes().peekable();
+        while let Some(b) = bytes.next() {
+            match b {
+                b'.' => {
+                    push_label(&mut wire, &label)?;
+                    label.clear();
+                    // A trailing dot ends the name
+                    if bytes.peek().is_none() {
+                        wire.push(0);
+                        return Ok(Self { wire });
+                    }
+                }
+                b'\\' => {
+                    let first = bytes.next().ok_or(ParseError::InvalidEscape)?;
+                    if first.is_ascii_digit() {
+                        let mut value = u32::from(first - b'0');
+                        for _ in 0..2 {
+                            match bytes.next() {
+                                Some(d) if d.is_ascii_digit() => {
+                                    value = value * 10 + u32::from(d - b'0')
+                                }
+                                _ => return Err(ParseError::InvalidEscape),
+                            }
+                        }
+                        label.push(u8::try_from(value).map_err(|_| ParseError::InvalidEscape)?);
+                    } else {
+                        label.push(first);
+                    }
+                }
+                _ => label.push(b),
+            }
+        }
+        push_label(&mut wire, &label)?;
+        wire.push(0);
+        Ok(Self { wire })
+    }
+}
+
+impl fmt::Display for Name {
+    /// Writes the absolute name with a trailing dot, escaping so that it parses back to the
+    /// same name
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_labels(f, self.labels())
+    }
+}
+
+/// Writes `labels` in presentation format, the root name as a single dot
+pub(crate) fn write_labels<'a>(
+    f: &mut fmt::Formatter<'_>,
+    labels: impl Iterator<Item = &'a [u8]>,
+) -> fmt::Result {
+    let mut empty = true;
+    for label in labels {
+        write_label(f, label)?;
+        f.write_str(".")?;
+        empty = false;
+    }
+    if empty {
+        f.write_str(".")?;
+    }
+    Ok(())
+}
+
+/// Writes a single label, escaping special and non printable characters
+fn write_label(out: &mut impl fmt::Write, label: &[u8]) -> fmt::Result {
+    for &b in label {
+        match b {
+            b'.' | b'\\' | b'"' | b';' | b'(' | b')' | b'@' | b'$' => {
+                write!(out, "\\{}", b as char)?
+            }
+            0x21..=0x7e => write!(out, "{}", b as char)?,
+            _ => write!(out, "\\{:03}", b)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Name, ParseError> {
+        s.parse()
+    }
+
+    #[test]
+    fn test_parse_google_dns() {
+        let expected = [
+            3, b'd', b'n', b's', 6, b'g', b'o', b'o', b'g', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        ];
+        assert_eq!(parse("dns.google.com").unwrap().as_wire(), expected);
+        assert_eq!(parse("dns.google.com.").unwrap().as_wire(), expected);
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        let name = parse(r"a\.b.\099\ d").unwrap();
+        assert_eq!(
+            name.as_wire(),
+            [3, b'a', b'.', b'b', 3, b'c', b' ', b'd', 0]
+        );
+        assert_eq!(name.to_string(), r"a\.b.c\032d.");
+        assert_eq!(parse(&name.to_string()), Ok(name));
+
+        assert_eq!(parse(r"\256"), Err(ParseError::InvalidEscape));
+        assert_eq!(parse(r"a\1"), Err(ParseError::InvalidEscape));
+        assert_eq!(parse("a\\"), Err(ParseError::InvalidEscape));
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(parse("."), Ok(Name::root()));
+        assert_eq!(Name::root().to_string(), ".");
+        assert_eq!(parse(""), Err(ParseError::EmptyLabel));
+        assert_eq!(parse("a..b"), Err(ParseError::EmptyLabel));
+        assert_eq!(parse(".a"), Err(ParseError::EmptyLabel));
+
+        assert!(parse(&"a".repeat(63)).is_ok());
+        assert_eq!(parse(&"a".repeat(64)), Err(ParseError::LabelTooLong));
+
+        // 4 labels of 63 octets make a 257 octet name
+        let label = "a".repeat(63);
+        let name = [label.as_str(); 4].join(".");
+        assert_eq!(parse(&name), Err(ParseError::NameTooLong));
+        assert!(parse(&name[..name.len() - 2]).is_ok());
+    }
+
+    #[test]
+    fn test_name_algebra() {
+        let name = parse("www.Example.com").unwrap();
+        let zone = parse("example.COM.").unwrap();
+        assert_eq!(name.parent(), Some(parse("example.com").unwrap()));
+        assert_eq!(Name::root().parent(), None);
+        assert_eq!(name.label_count(), 3);
+
+        assert!(name.is_subdomain_of(&zone));
+        assert!(zone.is_subdomain_of(&zone));
+        assert!(name.is_subdomain_of(&Name::root()));
+        assert!(!zone.is_subdomain_of(&name));
+        assert!(!parse("wwwexample.com").unwrap().is_subdomain_of(&zone));
+
+        let other = parse("mail.example.com").unwrap();
+        assert_eq!(name.common_ancestor(&other), zone);
+        assert_eq!(name.common_ancestor(&parse("org").unwrap()), Name::root());
+
+        assert_eq!(name.suffix(2), zone);
+        assert_eq!(name.suffix(0), Name::root());
+        assert_eq!(name.suffix(4), name);
+    }
+
+    #[test]
+    fn test_name_eq_ignores_case() {
+        use std::collections::HashSet;
+
+        let name = parse("WWW.example.com").unwrap();
+        assert_eq!(name, parse("www.EXAMPLE.com").unwrap());
+        assert_ne!(name, parse("www.example.org").unwrap());
+        let set: HashSet<Name> = [name, parse("www.example.com").unwrap()].into();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        // Example from RFC 4034 section 6.1
+        let names = [
+            "example",
+            "a.example",
+            "yljkjljk.a.example",
+            "Z.a.example",
+            "zABC.a.EXAMPLE",
+            "z.example",
+            "\\001.z.example",
+            "*.z.example",
+            "\\200.z.example",
+        ];
+        let mut parsed: Vec<Name> = names.iter().map(|s| parse(s).unwrap()).collect();
+        parsed.reverse();
+        parsed.sort();
+        let sorted: Vec<Name> = names.iter().map(|s| parse(s).unwrap()).collect();
+        assert_eq!(parsed, sorted);
+    }
+
+    #[test]
+    fn test_idna() {
+        let name = Name::from_unicode("Bücher.example").unwrap();
+        assert_eq!(name, parse("xn--bcher-kva.example").unwrap());
+        assert_eq!(name.to_unicode(), "bücher.example.");
+        assert_eq!(Name::from_unicode("example.com"), parse("example.com"));
+        assert_eq!(parse(r"a\.b").unwrap().to_unicode(), r"a\.b.");
+        // Not valid punycode, so it is shown as it is
+        assert_eq!(
+            parse("xn--a.example").unwrap().to_unicode(),
+            "xn--a.example."
+        );
+        assert_eq!(
+            Name::from_unicode("\u{0301}bücher.example"),
+            Err(ParseError::InvalidIdna)
+        );
+        // Raw UTF-8 off the wire, with a character across the first 4 bytes
+        let name = Name::from_labels(["aéé".as_bytes(), b"com"]).unwrap();
+        assert_eq!(name.to_unicode(), name.to_string());
+    }
+
+    #[test]
+    fn test_from_wire() {
+        let name = parse("www.example.com").unwrap();
+        let mut bytes = name.as_wire().to_vec();
+        bytes.push(0xff);
+        assert_eq!(Name::from_wire(&bytes), Ok(name.clone()));
+        assert_eq!(
+            name.labels().collect::<Vec<_>>(),
+            [&b"www"[..], b"example", b"com"]
+        );
+
+        assert_eq!(
+            Name::from_wire(&[3, b'w', b'w']),
+            Err(ParseError::TruncatedName)
+        );
+        assert_eq!(
+            Name::from_wire(&[0b11000000, 0]),
+            Err(ParseError::InvalidPointer)
+        );
+        assert_eq!(
+            Name::from_labels([&b"www"[..], b"example", b"com"]),
+            Ok(name)
+        );
+    }
+}
@@ -0,0 +1,1058 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use crate::{
+    compression::{NameCompressor, decompress, name_end},
+    dnssec::{Dnskey, Ds, Nsec, Nsec3, Nsec3Param, Rrsig, decode_type_bitmap, parse_timestamp},
+    edns::EdnsOption,
+    encoding::{from_base32hex, from_base64, from_hex, to_hex},
+    error::ParseError,
+    message::Encodable,
+    name::Name,
+    record::RecordType,
+    svcb::{ServiceBinding, SvcParam},
+    tsig::Tsig,
+};
+
+/// Decoded RDATA of a resource record
+///
+/// Names are fully expanded, so the value does not borrow from the message it was read from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ns(Name),
+    Cname(Name),
+    Soa {
+        mname: Name,
+        rname: Name,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+    Ptr(Name),
+    Hinfo {
+        cpu: Vec<u8>,
+        os: Vec<u8>,
+    },
+    Mx {
+        preference: u16,
+        exchange: Name,
+    },
+    Txt(Vec<Vec<u8>>),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: Name,
+    },
+    Dname(Name),
+    Opt(Vec<EdnsOption>),
+    Ds(Ds),
+    Rrsig(Rrsig),
+    Nsec(Nsec),
+    Dnskey(Dnskey),
+    Nsec3(Nsec3),
+    Nsec3Param(Nsec3Param),
+    Svcb(ServiceBinding),
+    Https(ServiceBinding),
+    Tsig(Tsig),
+    Caa {
+        flags: u8,
+        tag: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Unknown {
+        rtype: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl RData {
+    /// Decodes `rdata` of type `rtype`, expanding compressed names against `message`
+    pub fn from_bytes(rtype: RecordType, rdata: &[u8], message: &[u8]) -> Result<Self, ParseError> {
+        let mut reader = Reader {
+            rdata,
+            message,
+            offset: 0,
+        };
+        let value = match rtype {
+            RecordType::A => Self::A(Ipv4Addr::from(reader.array::<4>()?)),
+            RecordType::Ns => Self::Ns(reader.name()?),
+            RecordType::Cname => Self::Cname(reader.name()?),
+            RecordType::Soa => Self::Soa {
+                mname: reader.name()?,
+                rname: reader.name()?,
+                serial: reader.u32()?,
+                refresh: reader.u32()?,
+                retry: reader.u32()?,
+                expire: reader.u32()?,
+                minimum: reader.u32()?,
+            },
+            RecordType::Ptr => Self::Ptr(reader.name()?),
+            RecordType::Hinfo => Self::Hinfo {
+                cpu: reader.character_string()?,
+                os: reader.character_string()?,
+            },
+            RecordType::Mx => Self::Mx {
+                preference: reader.u16()?,
+                exchange: reader.name()?,
+            },
+            RecordType::Txt => {
+                let mut strings = Vec::new();
+                while !reader.is_empty() {
+                    strings.push(reader.character_string()?);
+                }
+                Self::Txt(strings)
+            }
+            RecordType::Aaaa => Self::Aaaa(Ipv6Addr::from(reader.array::<16>()?)),
+            RecordType::Srv => Self::Srv {
+                priority: reader.u16()?,
+                weight: reader.u16()?,
+                port: reader.u16()?,
+                target: reader.name()?,
+            },
+            RecordType::Dname => Self::Dname(reader.name()?),
+            RecordType::Opt => {
+                let mut options = Vec::new();
+                while !reader.is_empty() {
+                    let code = reader.u16()?;
+                    let len = reader.u16()? as usize;
+                    options.push(EdnsOption {
+                        code,
+                        data: reader.take(len)?.to_vec(),
+                    });
+                }
+                Self::Opt(options)
+            }
+            RecordType::Ds => Self::Ds(Ds {
+                key_tag: reader.u16()?,
+                algorithm: reader.u8()?,
+                digest_type: reader.u8()?,
+                digest: reader.rest().to_vec(),
+            }),
+            RecordType::Rrsig => Self::Rrsig(Rrsig {
+                type_covered: reader.u16()?.into(),
+                algorithm: reader.u8()?,
+                labels: reader.u8()?,
+                original_ttl: reader.u32()?,
+                expiration: reader.u32()?,
+                inception: reader.u32()?,
+                key_tag: reader.u16()?,
+                signer: reader.uncompressed_name()?,
+                signature: reader.rest().to_vec(),
+            }),
+            RecordType::Nsec => Self::Nsec(Nsec {
+                next: reader.uncompressed_name()?,
+                types: decode_type_bitmap(reader.rest())?,
+            }),
+            RecordType::Dnskey => Self::Dnskey(Dnskey {
+                flags: reader.u16()?,
+                protocol: reader.u8()?,
+                algorithm: reader.u8()?,
+                public_key: reader.rest().to_vec(),
+            }),
+            RecordType::Nsec3 => Self::Nsec3(Nsec3 {
+                hash_algorithm: reader.u8()?,
+                flags: reader.u8()?,
+                iterations: reader.u16()?,
+                salt: reader.character_string()?,
+                next_hashed: reader.character_string()?,
+                types: decode_type_bitmap(reader.rest())?,
+            }),
+            RecordType::Nsec3Param => Self::Nsec3Param(Nsec3Param {
+                hash_algorithm: reader.u8()?,
+                flags: reader.u8()?,
+                iterations: reader.u16()?,
+                salt: reader.character_string()?,
+            }),
+            RecordType::Svcb => Self::Svcb(reader.service_binding()?),
+            RecordType::Https => Self::Https(reader.service_binding()?),
+            RecordType::Tsig => Self::Tsig(Tsig {
+                algorithm: reader.uncompressed_name()?,
+                time_signed: (u64::from(reader.u16()?) << 32) | u64::from(reader.u32()?),
+                fudge: reader.u16()?,
+                mac: {
+                    let len = reader.u16()? as usize;
+                    reader.take(len)?.to_vec()
+                },
+                original_id: reader.u16()?,
+                error: reader.u16()?.into(),
+                other: {
+                    let len = reader.u16()? as usize;
+                    reader.take(len)?.to_vec()
+                },
+            }),
+            RecordType::Caa => Self::Caa {
+                flags: reader.u8()?,
+                tag: reader.character_string()?,
+                value: reader.rest().to_vec(),
+            },
+            _ => Self::Unknown {
+                rtype: rtype.into(),
+                data: reader.rest().to_vec(),
+            },
+        };
+
+        if !reader.is_empty() {
+            return Err(ParseError::InvalidRData);
+        }
+        Ok(value)
+    }
+
+    /// Parses rdata of type `rtype` in presentation format, which may also be the generic
+    /// `\\# <length> <hex>` form of RFC 3597 for any type
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the text does not match the type, or if the type
+    /// has no presentation format of its own and the generic form is not used
+    pub fn from_presentation(rtype: RecordType, text: &str) -> Result<Self, ParseError> {
+        Self::from_tokens(rtype, &tokenize(text)?)
+    }
+
+    pub(crate) fn from_tokens(rtype: RecordType, tokens: &[&str]) -> Result<Self, ParseError> {
+        if tokens.first() == Some(&"\\#") {
+            return Self::from_generic(rtype, &tokens[1..]);
+        }
+
+        let mut tokens = Tokens { tokens, offset: 0 };
+        let value = match rtype {
+            RecordType::A => Self::A(tokens.parse()?),
+            RecordType::Aaaa => Self::Aaaa(tokens.parse()?),
+            RecordType::Ns => Self::Ns(tokens.parse()?),
+            RecordType::Cname => Self::Cname(tokens.parse()?),
+            RecordType::Soa => Self::Soa {
+                mname: tokens.parse()?,
+                rname: tokens.parse()?,
+                serial: tokens.parse()?,
+                refresh: tokens.parse()?,
+                retry: tokens.parse()?,
+                expire: tokens.parse()?,
+                minimum: tokens.parse()?,
+            },
+            RecordType::Ptr => Self::Ptr(tokens.parse()?),
+            RecordType::Hinfo => Self::Hinfo {
+                cpu: tokens.character_string()?,
+                os: tokens.character_string()?,
+            },
+            RecordType::Mx => Self::Mx {
+                preference: tokens.parse()?,
+                exchange: tokens.parse()?,
+            },
+            RecordType::Txt => {
+                let mut strings = vec![tokens.character_string()?];
+                while !tokens.is_empty() {
+                    strings.push(tokens.character_string()?);
+                }
+                Self::Txt(strings)
+            }
+            RecordType::Srv => Self::Srv {
+                priority: tokens.parse()?,
+                weight: tokens.parse()?,
+                port: tokens.parse()?,
+                target: tokens.parse()?,
+            },
+            RecordType::Dname => Self::Dname(tokens.parse()?),
+            RecordType::Ds => Self::Ds(Ds {
+                key_tag: tokens.parse()?,
+                algorithm: tokens.parse()?,
+                digest_type: tokens.parse()?,
+                digest: from_hex(&tokens.rest().concat()).ok_or(ParseError::InvalidPresentation)?,
+            }),
+            RecordType::Rrsig => Self::Rrsig(Rrsig {
+                type_covered: tokens.parse()?,
+                algorithm: tokens.parse()?,
+                labels: tokens.parse()?,
+                original_ttl: tokens.parse()?,
+                expiration: parse_timestamp(tokens.next()?)?,
+                inception: parse_timestamp(tokens.next()?)?,
+                key_tag: tokens.parse()?,
+                signer: tokens.parse()?,
+                signature: tokens.base64()?,
+            }),
+            RecordType::Nsec => Self::Nsec(Nsec {
+                next: tokens.parse()?,
+                types: tokens.types()?,
+            }),
+            RecordType::Dnskey => Self::Dnskey(Dnskey {
+                flags: tokens.parse()?,
+                protocol: tokens.parse()?,
+                algorithm: tokens.parse()?,
+                public_key: tokens.base64()?,
+            }),
+            RecordType::Nsec3 => Self::Nsec3(Nsec3 {
+                hash_algorithm: tokens.parse()?,
+                flags: tokens.parse()?,
+                iterations: tokens.parse()?,
+                salt: tokens.salt()?,
+                next_hashed: from_base32hex(tokens.next()?)
+                    .ok_or(ParseError::InvalidPresentation)?,
+                types: tokens.types()?,
+            }),
+            RecordType::Nsec3Param => Self::Nsec3Param(Nsec3Param {
+                hash_algorithm: tokens.parse()?,
+                flags: tokens.parse()?,
+                iterations: tokens.parse()?,
+                salt: tokens.salt()?,
+            }),
+            RecordType::Svcb => Self::Svcb(ServiceBinding::from_tokens(tokens.rest())?),
+            RecordType::Https => Self::Https(ServiceBinding::from_tokens(tokens.rest())?),
+            RecordType::Caa => Self::Caa {
+                flags: tokens.parse()?,
+                tag: tokens.next()?.as_bytes().to_vec(),
+                value: tokens.character_string()?,
+            },
+            _ => return Err(ParseError::InvalidPresentation),
+        };
+
+        if !tokens.is_empty() {
+            return Err(ParseError::InvalidPresentation);
+        }
+        Ok(value)
+    }
+
+    /// Parses the length and hex words following `\\#`, decoding them as rdata of `rtype`
+    fn from_generic(rtype: RecordType, tokens: &[&str]) -> Result<Self, ParseError> {
+        let (len, words) = tokens
+            .split_first()
+            .ok_or(ParseError::InvalidPresentation)?;
+        let len: usize = len.parse().map_err(|_| ParseError::InvalidPresentation)?;
+        let data = from_hex(&words.concat()).ok_or(ParseError::InvalidPresentation)?;
+        if data.len() != len {
+            return Err(ParseError::InvalidPresentation);
+        }
+        // The rdata stands alone, so any compression pointer in it is invalid
+        Self::from_bytes(rtype, &data, &data)
+    }
+
+    pub fn get_type(&self) -> RecordType {
+        match self {
+            Self::A(_) => RecordType::A,
+            Self::Ns(_) => RecordType::Ns,
+            Self::Cname(_) => RecordType::Cname,
+            Self::Soa { .. } => RecordType::Soa,
+            Self::Ptr(_) => RecordType::Ptr,
+            Self::Hinfo { .. } => RecordType::Hinfo,
+            Self::Mx { .. } => RecordType::Mx,
+            Self::Txt(_) => RecordType::Txt,
+            Self::Aaaa(_) => RecordType::Aaaa,
+            Self::Srv { .. } => RecordType::Srv,
+            Self::Dname(_) => RecordType::Dname,
+            Self::Opt(_) => RecordType::Opt,
+            Self::Ds(_) => RecordType::Ds,
+            Self::Rrsig(_) => RecordType::Rrsig,
+            Self::Nsec(_) => RecordType::Nsec,
+            Self::Dnskey(_) => RecordType::Dnskey,
+            Self::Nsec3(_) => RecordType::Nsec3,
+            Self::Nsec3Param(_) => RecordType::Nsec3Param,
+            Self::Svcb(_) => RecordType::Svcb,
+            Self::Https(_) => RecordType::Https,
+            Self::Tsig(_) => RecordType::Tsig,
+            Self::Caa { .. } => RecordType::Caa,
+            Self::Unknown { rtype, .. } => RecordType::from(*rtype),
+        }
+    }
+
+    pub fn get_ip(&self) -> Option<IpAddr> {
+        match self {
+            Self::A(ip) => Some(IpAddr::V4(*ip)),
+            Self::Aaaa(ip) => Some(IpAddr::V6(*ip)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RData {
+    /// Writes the rdata in presentation format, using the generic `\\#` form of RFC 3597 for
+    /// types without one
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A(ip) => write!(f, "{}", ip),
+            Self::Aaaa(ip) => write!(f, "{}", ip),
+            Self::Ns(name) | Self::Cname(name) | Self::Ptr(name) | Self::Dname(name) => {
+                write!(f, "{}", name)
+            }
+            Self::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                mname, rname, serial, refresh, retry, expire, minimum
+            ),
+            Self::Hinfo { cpu, os } => {
+                write_character_string(f, cpu)?;
+                f.write_str(" ")?;
+                write_character_string(f, os)
+            }
+            Self::Mx {
+                preference,
+                exchange,
+            } => write!(f, "{} {}", preference, exchange),
+            Self::Txt(strings) => {
+                for (i, s) in strings.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write_character_string(f, s)?;
+                }
+                Ok(())
+            }
+            Self::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{} {} {} {}", priority, weight, port, target),
+            Self::Ds(ds) => write!(f, "{}", ds),
+            Self::Rrsig(rrsig) => write!(f, "{}", rrsig),
+            Self::Nsec(nsec) => write!(f, "{}", nsec),
+            Self::Dnskey(key) => write!(f, "{}", key),
+            Self::Nsec3(nsec3) => write!(f, "{}", nsec3),
+            Self::Nsec3Param(param) => write!(f, "{}", param),
+            Self::Svcb(binding) | Self::Https(binding) => write!(f, "{}", binding),
+            Self::Tsig(tsig) => write!(f, "{}", tsig),
+            Self::Caa { flags, tag, value } => {
+                write!(f, "{} {} ", flags, String::from_utf8_lossy(tag))?;
+                write_character_string(f, value)
+            }
+            Self::Opt(_) | Self::Unknown { .. } => {
+                let mut data = Vec::new();
+                self.encode(&mut data);
+                write!(f, "\\# {}", data.len())?;
+                if !data.is_empty() {
+                    write!(f, " {}", to_hex(&data))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Writes `s` as a quoted character-string, escaping quotes, backslashes and non printable
+/// bytes
+pub(crate) fn write_character_string(f: &mut fmt::Formatter<'_>, s: &[u8]) -> fmt::Result {
+    f.write_str("\"")?;
+    for &b in s {
+        match b {
+            b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+            0x20..=0x7e => write!(f, "{}", b as char)?,
+            _ => write!(f, "\\{:03}", b)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl Encodable for RData {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::A(ip) => buf.extend_from_slice(&ip.octets()),
+            Self::Aaaa(ip) => buf.extend_from_slice(&ip.octets()),
+            Self::Ns(name) | Self::Cname(name) | Self::Ptr(name) | Self::Dname(name) => {
+                buf.extend_from_slice(name.as_wire())
+            }
+            Self::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                buf.extend_from_slice(mname.as_wire());
+                buf.extend_from_slice(rname.as_wire());
+                for value in [serial, refresh, retry, expire, minimum] {
+                    buf.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            Self::Hinfo { cpu, os } => {
+                encode_character_string(cpu, buf);
+                encode_character_string(os, buf);
+            }
+            Self::Mx {
+                preference,
+                exchange,
+            } => {
+                buf.extend_from_slice(&preference.to_be_bytes());
+                buf.extend_from_slice(exchange.as_wire());
+            }
+            Self::Txt(strings) => {
+                for s in strings {
+                    encode_character_string(s, buf);
+                }
+            }
+            Self::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                buf.extend_from_slice(&priority.to_be_bytes());
+                buf.extend_from_slice(&weight.to_be_bytes());
+                buf.extend_from_slice(&port.to_be_bytes());
+                buf.extend_from_slice(target.as_wire());
+            }
+            Self::Opt(options) => {
+                for option in options {
+                    buf.extend_from_slice(&option.code.to_be_bytes());
+                    buf.extend_from_slice(&(option.data.len() as u16).to_be_bytes());
+                    buf.extend_from_slice(&option.data);
+                }
+            }
+            Self::Ds(ds) => ds.encode(buf),
+            Self::Rrsig(rrsig) => rrsig.encode(buf),
+            Self::Nsec(nsec) => nsec.encode(buf),
+            Self::Dnskey(key) => key.encode(buf),
+            Self::Nsec3(nsec3) => nsec3.encode(buf),
+            Self::Nsec3Param(param) => param.encode(buf),
+            Self::Svcb(binding) | Self::Https(binding) => {
+                buf.extend_from_slice(&binding.priority.to_be_bytes());
+                buf.extend_from_slice(binding.target.as_wire());
+                for param in &binding.params {
+                    let mut value = Vec::new();
+                    param.encode_value(&mut value);
+                    buf.extend_from_slice(&param.key().to_be_bytes());
+                    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                    buf.extend_from_slice(&value);
+                }
+            }
+            Self::Tsig(tsig) => tsig.encode(buf),
+            Self::Caa { flags, tag, value } => {
+                buf.push(*flags);
+                encode_character_string(tag, buf);
+                buf.extend_from_slice(value);
+            }
+            Self::Unknown { data, .. } => buf.extend_from_slice(data),
+        }
+    }
+
+    /// Only names in the types defined by RFC 1035 are compressed (RFC 3597 4)
+    fn encode_compressed(&self, buf: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        match self {
+            Self::Ns(name) | Self::Cname(name) | Self::Ptr(name) => {
+                compressor.write_name(name.as_wire(), buf)
+            }
+            Self::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                compressor.write_name(mname.as_wire(), buf);
+                compressor.write_name(rname.as_wire(), buf);
+                for value in [serial, refresh, retry, expire, minimum] {
+                    buf.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            Self::Mx {
+                preference,
+                exchange,
+            } => {
+                buf.extend_from_slice(&preference.to_be_bytes());
+                compressor.write_name(exchange.as_wire(), buf);
+            }
+            _ => self.encode(buf),
+        }
+    }
+}
+
+/// Writes a <character-string>, truncating it to 255 bytes
+fn encode_character_string(s: &[u8], buf: &mut Vec<u8>) {
+    let len = s.len().min(255);
+    buf.push(len as u8);
+    buf.extend_from_slice(&s[..len]);
+}
+
+/// Splits presentation format text on whitespace, keeping quoted strings and escaped
+/// characters inside their token
+///
+/// Quotes can also start inside a token, as in the `key="value"` parameters of SVCB
+pub(crate) fn tokenize(text: &str) -> Result<Vec<&str>, ParseError> {
+    let mut tokens = Vec::new();
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut quoted = false;
+        while let Some(&b) = bytes.get(i) {
+            match b {
+                b'\\' => i += 2,
+                b'"' => {
+                    quoted = !quoted;
+                    i += 1;
+                }
+                _ if !quoted && b.is_ascii_whitespace() => break,
+                _ => i += 1,
+            }
+        }
+        if quoted {
+            return Err(ParseError::InvalidPresentation);
+        }
+        tokens.push(&text[start..i.min(bytes.len())]);
+    }
+    Ok(tokens)
+}
+
+/// Resolves the `\X` and `\DDD` escapes of a token, removing the quotes around it if any
+pub(crate) fn unescape(token: &str) -> Result<Vec<u8>, ParseError> {
+    let token = token.as_bytes();
+    let token = match token {
+        [b'"', inner @ .., b'"'] => inner,
+        _ => token,
+    };
+
+    let mut s = Vec::with_capacity(token.len());
+    let mut i = 0;
+    while i < token.len() {
+        if token[i] != b'\\' {
+            s.push(token[i]);
+            i += 1;
+            continue;
+        }
+        match token.get(i + 1..i + 4) {
+            Some(digits) if digits.iter().all(u8::is_ascii_digit) => {
+                let value = std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|d| d.parse::<u8>().ok())
+                    .ok_or(ParseError::InvalidPresentation)?;
+                s.push(value);
+                i += 4;
+            }
+            _ => {
+                s.push(*token.get(i + 1).ok_or(ParseError::InvalidPresentation)?);
+                i += 2;
+            }
+        }
+    }
+    Ok(s)
+}
+
+/// Cursor over presentation format tokens
+struct Tokens<'a, 'b> {
+    tokens: &'b [&'a str],
+    offset: usize,
+}
+
+impl<'a, 'b> Tokens<'a, 'b> {
+    fn is_empty(&self) -> bool {
+        self.offset >= self.tokens.len()
+    }
+
+    fn next(&mut self) -> Result<&'a str, ParseError> {
+        let token = self
+            .tokens
+            .get(self.offset)
+            .ok_or(ParseError::InvalidPresentation)?;
+        self.offset += 1;
+        Ok(token)
+    }
+
+    fn rest(&mut self) -> &'b [&'a str] {
+        let rest = &self.tokens[self.offset..];
+        self.offset = self.tokens.len();
+        rest
+    }
+
+    /// Reads base64 split over the remaining tokens
+    fn base64(&mut self) -> Result<Vec<u8>, ParseError> {
+        from_base64(&self.rest().concat()).ok_or(ParseError::InvalidPresentation)
+    }
+
+    /// Reads an NSEC3 salt in hex, `-` standing for an empty one
+    fn salt(&mut self) -> Result<Vec<u8>, ParseError> {
+        match self.next()? {
+            "-" => Ok(Vec::new()),
+            hex => from_hex(hex)
+                .filter(|salt| salt.len() <= u8::MAX as usize)
+                .ok_or(ParseError::InvalidPresentation),
+        }
+    }
+
+    /// Reads the type mnemonics in the remaining tokens
+    fn types(&mut self) -> Result<Vec<RecordType>, ParseError> {
+        self.rest().iter().map(|t| t.parse()).collect()
+    }
+
+    fn parse<T: std::str::FromStr>(&mut self) -> Result<T, ParseError> {
+        self.next()?
+            .parse()
+            .map_err(|_| ParseError::InvalidPresentation)
+    }
+
+    /// Reads a character-string, quoted or not, resolving its escapes
+    fn character_string(&mut self) -> Result<Vec<u8>, ParseError> {
+        let s = unescape(self.next()?)?;
+        if s.len() > u8::MAX as usize {
+            return Err(ParseError::InvalidPresentation);
+        }
+        Ok(s)
+    }
+}
+
+/// Cursor over the rdata of a single record
+struct Reader<'a> {
+    rdata: &'a [u8],
+    message: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.offset >= self.rdata.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        let end = self.offset + len;
+        if end > self.rdata.len() {
+            return Err(ParseError::InvalidRData);
+        }
+        let bytes = &self.rdata[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.rdata[self.offset..];
+        self.offset = self.rdata.len();
+        bytes
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ParseError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn character_string(&mut self) -> Result<Vec<u8>, ParseError> {
+        let len = self.u8()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    /// Reads a name that must not be compressed, like the names in the rdata of types defined
+    /// after RFC 1035
+    fn uncompressed_name(&mut self) -> Result<Name, ParseError> {
+        let name = Name::from_wire(&self.rdata[self.offset..])?;
+        self.offset += name.as_wire().len();
+        Ok(name)
+    }
+
+    /// Reads SVCB or HTTPS rdata, whose target name must not be compressed (RFC 9460 2.2)
+    fn service_binding(&mut self) -> Result<ServiceBinding, ParseError> {
+        let priority = self.u16()?;
+        let target = self.uncompressed_name()?;
+
+        let mut params = Vec::new();
+        let mut last_key = None;
+        while !self.is_empty() {
+            let key = self.u16()?;
+            if last_key.is_some_and(|last| key <= last) {
+                return Err(ParseError::InvalidRData);
+            }
+            last_key = Some(key);
+            let len = self.u16()? as usize;
+            params.push(SvcParam::from_wire(key, self.take(len)?)?);
+        }
+
+        Ok(ServiceBinding {
+            priority,
+            target,
+            params,
+        })
+    }
+
+    fn name(&mut self) -> Result<Name, ParseError> {
+        let end = name_end(self.rdata, self.offset).ok_or(ParseError::InvalidRData)?;
+        let name = decompress(&self.rdata[self.offset..end], self.message)?;
+        self.offset = end;
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::name;
+
+    fn round_trip(rdata: RData) {
+        let mut buf = Vec::new();
+        rdata.encode(&mut buf);
+        let output = RData::from_bytes(rdata.get_type(), &buf, &buf).unwrap();
+        assert_eq!(output, rdata);
+    }
+
+    #[test]
+    fn test_rdata_encode_from_bytes_eq() {
+        round_trip(RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+        round_trip(RData::Aaaa(Ipv6Addr::LOCALHOST));
+        round_trip(RData::Ns(name("ns1.example.com")));
+        round_trip(RData::Soa {
+            mname: name("ns1.example.com"),
+            rname: name("hostmaster.example.com"),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        });
+        round_trip(RData::Hinfo {
+            cpu: b"x86".to_vec(),
+            os: b"linux".to_vec(),
+        });
+        round_trip(RData::Mx {
+            preference: 10,
+            exchange: name("mail.example.com"),
+        });
+        round_trip(RData::Txt(vec![b"v=spf1".to_vec(), b"-all".to_vec()]));
+        round_trip(RData::Srv {
+            priority: 1,
+            weight: 2,
+            port: 443,
+            target: name("www.example.com"),
+        });
+        round_trip(RData::Caa {
+            flags: 0,
+            tag: b"issue".to_vec(),
+            value: b"letsencrypt.org".to_vec(),
+        });
+        round_trip(RData::Unknown {
+            rtype: 99,
+            data: vec![1, 2, 3],
+        });
+    }
+
+    #[test]
+    fn test_rdata_compressed_name() {
+        // "example.com" at offset 0, followed by MX rdata pointing to it
+        let mut message = name("example.com").as_wire().to_vec();
+        let rdata_start = message.len();
+        message.extend_from_slice(&[0, 10, 4, b'm', b'a', b'i', b'l', 0b11000000, 0]);
+
+        let output = RData::from_bytes(RecordType::Mx, &message[rdata_start..], &message).unwrap();
+        let RData::Mx {
+            preference,
+            exchange,
+        } = output
+        else {
+            panic!("Expected MX record, got {:?}", output);
+        };
+        assert_eq!(preference, 10);
+        assert_eq!(exchange, name("mail.example.com"));
+    }
+
+    #[test]
+    fn test_rdata_service_binding() {
+        let https = RData::Https(ServiceBinding {
+            priority: 1,
+            target: Name::root(),
+            params: vec![
+                SvcParam::Alpn(vec![b"h2".to_vec()]),
+                SvcParam::Port(443),
+                SvcParam::Ipv6Hint(vec![Ipv6Addr::LOCALHOST]),
+            ],
+        });
+        round_trip(https.clone());
+
+        let mut buf = Vec::new();
+        https.encode(&mut buf);
+        assert_eq!(buf[..3], [0, 1, 0]);
+        assert_eq!(buf[3..5], [0, 1]);
+
+        // Keys have to be in increasing order
+        let mut swapped = buf[..3].to_vec();
+        swapped.extend_from_slice(&buf[10..16]);
+        swapped.extend_from_slice(&buf[3..10]);
+        swapped.extend_from_slice(&buf[16..]);
+        assert_eq!(
+            RData::from_bytes(RecordType::Https, &swapped, &swapped),
+            Err(ParseError::InvalidRData)
+        );
+
+        // The target can't be compressed
+        let message = [0, 1, 0b11000000, 0];
+        assert_eq!(
+            RData::from_bytes(RecordType::Svcb, &message, &message),
+            Err(ParseError::InvalidPointer)
+        );
+    }
+
+    #[test]
+    fn test_rdata_dnssec_presentation() {
+        for (rtype, text) in [
+            (
+                RecordType::Dnskey,
+                "256 3 13 oJMRESz5E4gYzS/q6XDrvU1qMPYIjCWzJaOau8XNEZeqCYKD5ar0IRd8KqXXFJkqmVfRvMGPmM1x8fGAa2XhSA==",
+            ),
+            (
+                RecordType::Rrsig,
+                "A 13 2 3600 20240301000000 20240201000000 2371 example.com. sG9vKJ9GkRP3ahW5aI9I6q0Xiiw2sBa8yjSzJUr6Mzk=",
+            ),
+            (
+                RecordType::Ds,
+                "2371 13 2 1F987CC6583E92DF0890718C42B53D5B7A1D2C0F1AA9B1F3C4E7D3C5E0FE8D0E",
+            ),
+            (
+                RecordType::Nsec,
+                "host.example.com. A MX RRSIG NSEC TYPE1234",
+            ),
+            (
+                RecordType::Nsec3,
+                "1 1 0 AABBCCDD 2T7B4G4VSA5SMI47K61MV5BV1A22BOJR A RRSIG",
+            ),
+            (
+                RecordType::Nsec3,
+                "1 0 0 - 2T7B4G4VSA5SMI47K61MV5BV1A22BOJR",
+            ),
+            (RecordType::Nsec3Param, "1 0 10 AABBCCDD"),
+        ] {
+            let rdata = RData::from_presentation(rtype, text).unwrap();
+            assert_eq!(rdata.to_string(), text);
+            round_trip(rdata);
+        }
+
+        // Base64 and hex may be split over several tokens
+        let split = RData::from_presentation(
+            RecordType::Ds,
+            "60485 5 1 2BB183AF 5F22588179A53B0A98631FAD1A292118",
+        )
+        .unwrap();
+        let RData::Ds(ds) = split else {
+            panic!("Expected DS record, got {:?}", split);
+        };
+        assert_eq!(ds.digest.len(), 20);
+
+        for (rtype, text) in [
+            (
+                RecordType::Rrsig,
+                "A 13 2 3600 20241301000000 20240201000000 2371 example.com. AA==",
+            ),
+            (RecordType::Nsec, "host.example.com. A BOGUS"),
+            (
+                RecordType::Nsec3,
+                "1 0 0 - 2T7B4G4VSA5SMI47K61MV5BV1A22BOJW",
+            ),
+            (RecordType::Dnskey, "256 3 13 not-base64"),
+        ] {
+            assert!(RData::from_presentation(rtype, text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_rdata_display() {
+        let soa = RData::Soa {
+            mname: name("ns1.example.com"),
+            rname: name("hostmaster.example.com"),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        };
+        assert_eq!(
+            soa.to_string(),
+            "ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300"
+        );
+        let txt = RData::Txt(vec![b"say \"hi\"".to_vec(), vec![b'\\', 7]]);
+        assert_eq!(txt.to_string(), r#""say \"hi\"" "\\\007""#);
+        let unknown = RData::Unknown {
+            rtype: 99,
+            data: vec![0xde, 0xad],
+        };
+        assert_eq!(unknown.to_string(), "\\# 2 dead");
+        assert_eq!(RData::Opt(Vec::new()).to_string(), "\\# 0");
+    }
+
+    #[test]
+    fn test_rdata_from_presentation() {
+        for rdata in [
+            RData::Soa {
+                mname: name("ns1.example.com"),
+                rname: name("hostmaster.example.com"),
+                serial: 2024010101,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300,
+            },
+            RData::Txt(vec![b"say \"hi\"".to_vec(), vec![b'\\', 7], Vec::new()]),
+            RData::Caa {
+                flags: 128,
+                tag: b"issue".to_vec(),
+                value: b"ca.example.net".to_vec(),
+            },
+            RData::Unknown {
+                rtype: 65280,
+                data: vec![0xde, 0xad],
+            },
+        ] {
+            let text = rdata.to_string();
+            assert_eq!(RData::from_presentation(rdata.get_type(), &text), Ok(rdata));
+        }
+
+        // The generic form works for known types too, with the hex split anywhere
+        assert_eq!(
+            RData::from_presentation(RecordType::A, "\\# 4 c000 02 01"),
+            Ok(RData::A([192, 0, 2, 1].into()))
+        );
+        assert_eq!(
+            RData::from_presentation(RecordType::Txt, "unquoted \\065"),
+            Ok(RData::Txt(vec![b"unquoted".to_vec(), b"A".to_vec()]))
+        );
+
+        for (rtype, text) in [
+            (RecordType::A, "\\# 3 c00002"),
+            (RecordType::A, "\\# 5 c000020101"),
+            (RecordType::A, "192.0.2.1 extra"),
+            (RecordType::Mx, "10"),
+            (RecordType::Txt, "\"unterminated"),
+            (RecordType::Unknown(65280), "dead"),
+        ] {
+            assert!(RData::from_presentation(rtype, text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_rdata_invalid_length() {
+        assert!(matches!(
+            RData::from_bytes(RecordType::A, &[1, 2, 3], &[]),
+            Err(ParseError::InvalidRData)
+        ));
+        assert!(matches!(
+            RData::from_bytes(RecordType::A, &[1, 2, 3, 4, 5], &[]),
+            Err(ParseError::InvalidRData)
+        ));
+        assert!(matches!(
+            RData::from_bytes(RecordType::Cname, &[0b11000000, 40], &[0; 12]),
+            Err(ParseError::InvalidPointer)
+        ));
+    }
+}
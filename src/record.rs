@@ -0,0 +1,426 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    compression::NameCompressor,
+    error::ParseError,
+    message::Encodable,
+    name::Name,
+    rdata::{RData, tokenize},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum RecordType {
+    A,
+    Ns,
+    Cname,
+    Soa,
+    Ptr,
+    Hinfo,
+    Mx,
+    Txt,
+    Aaaa,
+    Srv,
+    Dname,
+    Opt,
+    Ds,
+    Rrsig,
+    Nsec,
+    Dnskey,
+    Nsec3,
+    Nsec3Param,
+    Svcb,
+    Https,
+    Tsig,
+    Any,
+    Caa,
+    Unknown(u16),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum RecordClass {
+    In,
+    Ch,
+    Hs,
+    None,
+    Any,
+    Unknown(u16),
+}
+
+/// Resource record that owns its name and decoded rdata
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Record {
+    pub name: Name,
+    pub rtype: RecordType,
+    pub rclass: RecordClass,
+    pub ttl: u32,
+    pub rdata: RData,
+}
+
+impl From<u16> for RecordType {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Self::A,
+            2 => Self::Ns,
+            5 => Self::Cname,
+            6 => Self::Soa,
+            12 => Self::Ptr,
+            13 => Self::Hinfo,
+            15 => Self::Mx,
+            16 => Self::Txt,
+            28 => Self::Aaaa,
+            33 => Self::Srv,
+            39 => Self::Dname,
+            41 => Self::Opt,
+            43 => Self::Ds,
+            46 => Self::Rrsig,
+            47 => Self::Nsec,
+            48 => Self::Dnskey,
+            50 => Self::Nsec3,
+            51 => Self::Nsec3Param,
+            64 => Self::Svcb,
+            65 => Self::Https,
+            250 => Self::Tsig,
+            255 => Self::Any,
+            257 => Self::Caa,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+impl From<RecordType> for u16 {
+    fn from(value: RecordType) -> Self {
+        match value {
+            RecordType::A => 1,
+            RecordType::Ns => 2,
+            RecordType::Cname => 5,
+            RecordType::Soa => 6,
+            RecordType::Ptr => 12,
+            RecordType::Hinfo => 13,
+            RecordType::Mx => 15,
+            RecordType::Txt => 16,
+            RecordType::Aaaa => 28,
+            RecordType::Srv => 33,
+            RecordType::Dname => 39,
+            RecordType::Opt => 41,
+            RecordType::Ds => 43,
+            RecordType::Rrsig => 46,
+            RecordType::Nsec => 47,
+            RecordType::Dnskey => 48,
+            RecordType::Nsec3 => 50,
+            RecordType::Nsec3Param => 51,
+            RecordType::Svcb => 64,
+            RecordType::Https => 65,
+            RecordType::Tsig => 250,
+            RecordType::Any => 255,
+            RecordType::Caa => 257,
+            RecordType::Unknown(other) => other,
+        }
+    }
+}
+
+impl From<u16> for RecordClass {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Self::In,
+            3 => Self::Ch,
+            4 => Self::Hs,
+            254 => Self::None,
+            255 => Self::Any,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+impl From<RecordClass> for u16 {
+    fn from(value: RecordClass) -> Self {
+        match value {
+            RecordClass::In => 1,
+            RecordClass::Ch => 3,
+            RecordClass::Hs => 4,
+            RecordClass::None => 254,
+            RecordClass::Any => 255,
+            RecordClass::Unknown(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    /// Writes the mnemonic, or the generic `TYPE` form of RFC 3597 for unknown types
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self {
+            RecordType::A => "A",
+            RecordType::Ns => "NS",
+            RecordType::Cname => "CNAME",
+            RecordType::Soa => "SOA",
+            RecordType::Ptr => "PTR",
+            RecordType::Hinfo => "HINFO",
+            RecordType::Mx => "MX",
+            RecordType::Txt => "TXT",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Srv => "SRV",
+            RecordType::Dname => "DNAME",
+            RecordType::Opt => "OPT",
+            RecordType::Ds => "DS",
+            RecordType::Rrsig => "RRSIG",
+            RecordType::Nsec => "NSEC",
+            RecordType::Dnskey => "DNSKEY",
+            RecordType::Nsec3 => "NSEC3",
+            RecordType::Nsec3Param => "NSEC3PARAM",
+            RecordType::Svcb => "SVCB",
+            RecordType::Https => "HTTPS",
+            RecordType::Tsig => "TSIG",
+            RecordType::Any => "ANY",
+            RecordType::Caa => "CAA",
+            RecordType::Unknown(other) => return write!(f, "TYPE{}", other),
+        };
+        f.write_str(mnemonic)
+    }
+}
+
+impl fmt::Display for RecordClass {
+    /// Writes the mnemonic, or the generic `CLASS` form of RFC 3597 for unknown classes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self {
+            RecordClass::In => "IN",
+            RecordClass::Ch => "CH",
+            RecordClass::Hs => "HS",
+            RecordClass::None => "NONE",
+            RecordClass::Any => "ANY",
+            RecordClass::Unknown(other) => return write!(f, "CLASS{}", other),
+        };
+        f.write_str(mnemonic)
+    }
+}
+
+impl FromStr for RecordType {
+    type Err = ParseError;
+
+    /// Parses a mnemonic ignoring case, or the generic `TYPE` form of RFC 3597
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rtype = match s.to_ascii_uppercase().as_str() {
+            "A" => RecordType::A,
+            "NS" => RecordType::Ns,
+            "CNAME" => RecordType::Cname,
+            "SOA" => RecordType::Soa,
+            "PTR" => RecordType::Ptr,
+            "HINFO" => RecordType::Hinfo,
+            "MX" => RecordType::Mx,
+            "TXT" => RecordType::Txt,
+            "AAAA" => RecordType::Aaaa,
+            "SRV" => RecordType::Srv,
+            "DNAME" => RecordType::Dname,
+            "OPT" => RecordType::Opt,
+            "DS" => RecordType::Ds,
+            "RRSIG" => RecordType::Rrsig,
+            "NSEC" => RecordType::Nsec,
+            "DNSKEY" => RecordType::Dnskey,
+            "NSEC3" => RecordType::Nsec3,
+            "NSEC3PARAM" => RecordType::Nsec3Param,
+            "SVCB" => RecordType::Svcb,
+            "HTTPS" => RecordType::Https,
+            "TSIG" => RecordType::Tsig,
+            "ANY" => RecordType::Any,
+            "CAA" => RecordType::Caa,
+            other => generic_value(other, "TYPE")?.into(),
+        };
+        Ok(rtype)
+    }
+}
+
+impl FromStr for RecordClass {
+    type Err = ParseError;
+
+    /// Parses a mnemonic ignoring case, or the generic `CLASS` form of RFC 3597
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rclass = match s.to_ascii_uppercase().as_str() {
+            "IN" => RecordClass::In,
+            "CH" => RecordClass::Ch,
+            "HS" => RecordClass::Hs,
+            "NONE" => RecordClass::None,
+            "ANY" => RecordClass::Any,
+            other => generic_value(other, "CLASS")?.into(),
+        };
+        Ok(rclass)
+    }
+}
+
+/// Reads the number of a generic mnemonic like `TYPE65280`
+fn generic_value(s: &str, prefix: &str) -> Result<u16, ParseError> {
+    s.strip_prefix(prefix)
+        .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|digits| digits.parse().ok())
+        .ok_or(ParseError::InvalidPresentation)
+}
+
+impl RecordType {
+    /// Returns true if a record of type `rtype` answers a question for `self`
+    pub fn matches(self, rtype: RecordType) -> bool {
+        self == RecordType::Any || self == rtype
+    }
+}
+
+impl RecordClass {
+    /// Returns true if a record of class `rclass` answers a question for `self`
+    pub fn matches(self, rclass: RecordClass) -> bool {
+        self == RecordClass::Any || self == rclass
+    }
+}
+
+impl fmt::Display for Record {
+    /// Writes the record in presentation format, as found in zone files
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            self.name, self.ttl, self.rclass, self.rtype, self.rdata
+        )
+    }
+}
+
+impl FromStr for Record {
+    type Err = ParseError;
+
+    /// Parses a record written as `owner ttl class type rdata`, the way [`Display`] writes it
+    ///
+    /// [`Display`]: fmt::Display
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let [name, ttl, rclass, rtype, rdata @ ..] = tokens.as_slice() else {
+            return Err(ParseError::InvalidPresentation);
+        };
+        let rtype = rtype.parse()?;
+        Ok(Record {
+            name: name.parse()?,
+            rtype,
+            rclass: rclass.parse()?,
+            ttl: ttl.parse().map_err(|_| ParseError::InvalidPresentation)?,
+            rdata: RData::from_tokens(rtype, rdata)?,
+        })
+    }
+}
+
+impl Record {
+    /// Writes everything after the owner name, computing rdlength from the written rdata
+    fn encode_fields(&self, buf: &mut Vec<u8>, encode_rdata: impl FnOnce(&mut Vec<u8>)) {
+        buf.extend_from_slice(&u16::from(self.rtype).to_be_bytes());
+        buf.extend_from_slice(&u16::from(self.rclass).to_be_bytes());
+        buf.extend_from_slice(&self.ttl.to_be_bytes());
+        let rdlength_at = buf.len();
+        buf.extend_from_slice(&[0, 0]);
+        encode_rdata(buf);
+        let rdlength = (buf.len() - rdlength_at - 2) as u16;
+        buf[rdlength_at..rdlength_at + 2].copy_from_slice(&rdlength.to_be_bytes());
+    }
+}
+
+impl Encodable for Record {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.name.as_wire());
+        self.encode_fields(buf, |buf| self.rdata.encode(buf));
+    }
+
+    fn encode_compressed(&self, buf: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        compressor.write_name(self.name.as_wire(), buf);
+        self.encode_fields(buf, |buf| self.rdata.encode_compressed(buf, compressor));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{message::ResourceRecord, test_util::name};
+
+    #[test]
+    fn test_record_encode_compressed() {
+        let record = Record {
+            name: name("example.com"),
+            rtype: RecordType::Mx,
+            rclass: RecordClass::In,
+            ttl: 300,
+            rdata: RData::Mx {
+                preference: 10,
+                exchange: name("mail.example.com"),
+            },
+        };
+        let mut plain = Vec::new();
+        record.encode(&mut plain);
+
+        let mut buf = Vec::new();
+        let mut compressor = NameCompressor::new();
+        record.encode_compressed(&mut buf, &mut compressor);
+        assert_eq!(buf.len(), plain.len() - 11);
+
+        let (rr, end) = ResourceRecord::from_bytes(&buf, 0).unwrap();
+        assert_eq!(end, buf.len());
+        assert_eq!(rr.to_record(&buf).unwrap(), record);
+    }
+
+    #[test]
+    fn test_record_display() {
+        let record = Record {
+            name: name("example.com"),
+            rtype: RecordType::Mx,
+            rclass: RecordClass::In,
+            ttl: 300,
+            rdata: RData::Mx {
+                preference: 10,
+                exchange: name("mail.example.com"),
+            },
+        };
+        assert_eq!(
+            record.to_string(),
+            "example.com.\t300\tIN\tMX\t10 mail.example.com."
+        );
+        assert_eq!(RecordType::Unknown(65280).to_string(), "TYPE65280");
+        assert_eq!(RecordClass::Unknown(42).to_string(), "CLASS42");
+    }
+
+    #[test]
+    fn test_record_from_str() {
+        let record: Record = "example.com. 300 IN MX 10 mail.example.com."
+            .parse()
+            .unwrap();
+        assert_eq!(record.rdata.to_string(), "10 mail.example.com.");
+        assert_eq!(record.to_string().parse::<Record>().unwrap(), record);
+
+        // Unknown types and classes only have the generic forms
+        let unknown = "example.com.\t60\tCLASS99\tTYPE65280\t\\# 3 0a0b0c";
+        let record: Record = unknown.parse().unwrap();
+        assert_eq!(record.rclass, RecordClass::Unknown(99));
+        assert_eq!(
+            record.rdata,
+            RData::Unknown {
+                rtype: 65280,
+                data: vec![10, 11, 12],
+            }
+        );
+        assert_eq!(record.to_string(), unknown);
+
+        assert_eq!("type1".parse::<RecordType>(), Ok(RecordType::A));
+        assert_eq!("CLASS1".parse::<RecordClass>(), Ok(RecordClass::In));
+        assert_eq!("aaaa".parse::<RecordType>(), Ok(RecordType::Aaaa));
+        assert!("TYPE65536".parse::<RecordType>().is_err());
+        assert!("TYPE+1".parse::<RecordType>().is_err());
+        assert!("example.com. 60 IN TYPE65280 0a".parse::<Record>().is_err());
+        assert!("example.com. 60 IN".parse::<Record>().is_err());
+    }
+
+    #[test]
+    fn test_record_type_u16_round_trip() {
+        for value in 0..=1024u16 {
+            assert_eq!(u16::from(RecordType::from(value)), value);
+            assert_eq!(u16::from(RecordClass::from(value)), value);
+        }
+        assert_eq!(RecordType::from(28), RecordType::Aaaa);
+        assert_eq!(RecordClass::from(1), RecordClass::In);
+    }
+
+    #[test]
+    fn test_record_type_matches() {
+        assert!(RecordType::Any.matches(RecordType::Mx));
+        assert!(RecordType::A.matches(RecordType::A));
+        assert!(!RecordType::A.matches(RecordType::Aaaa));
+        assert!(RecordClass::Any.matches(RecordClass::Ch));
+        assert!(!RecordClass::In.matches(RecordClass::Ch));
+    }
+}
@@ -1,16 +1,16 @@
 use std::{cmp::Reverse, net::IpAddr};
 
-use crate::compression::CompressedName;
+use crate::name::Name;
 
 #[derive(Clone)]
 pub struct ServerInfo {
-    pub name: CompressedName,
+    pub name: Name,
     pub ip: Option<IpAddr>,
 }
 
 /// Assumes server list is not empty
-pub fn get_best_server(servers: &[ServerInfo], target: &[u8]) -> usize {
-    let mut max_score: u32 = 0;
+pub fn get_best_server(servers: &[ServerInfo], target: &Name) -> usize {
+    let mut max_score: usize = 0;
     let mut index: usize = 0;
     for (i, server) in servers.iter().enumerate() {
         let score = score_server(server, target);
@@ -23,55 +23,44 @@ pub fn get_best_server(servers: &[ServerInfo], target: &[u8]) -> usize {
     index
 }
 
-pub fn sort_server_list(servers: &mut [ServerInfo], target: &[u8]) {
+pub fn sort_server_list(servers: &mut [ServerInfo], target: &Name) {
     servers.sort_by_key(|s| Reverse(score_server(s, target)));
 }
 
-fn score_server(server: &ServerInfo, target: &[u8]) -> u32 {
-    let v: &Vec<Vec<u8>> = &server.name.0;
-    let mut score = 0;
-    let target_len = target.len();
-
-    for (i, &b) in v.iter().flat_map(|s| s.iter()).rev().enumerate() {
-        if i > 0 && b < 32 && b != 0 {
-            score += 1;
-        }
-        if i >= target_len {
-            break;
-        }
-        if b != target[target_len - 1 - i] {
-            break;
-        }
-    }
-    score
+/// Number of labels the server name has in common with the end of `target`
+fn score_server(server: &ServerInfo, target: &Name) -> usize {
+    server.name.common_ancestor(target).label_count()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::compression::CompressedName;
-
-    #[test]
-    fn test_decompress_domain() {
-        let message = [
-            1, b'f', 3, b'i', b's', b'i', 4, b'a', b'r', b'p', b'a', 0, 3, b'f', b'o', b'o',
-            0b11000000, 0, 0,
-        ];
-        let name = CompressedName(vec![message[12..12 + 4].to_vec(), message[..11].to_vec()]); // foo.fisi.arpa
 
-        let server = ServerInfo { name, ip: None };
-        let target = [1, b'f', 4, b'a', b'r', b'p', b'a']; // f.arpa
-        let score = score_server(&server, &target);
-        assert_eq!(score, 1);
+    fn server(name: &str) -> ServerInfo {
+        ServerInfo {
+            name: name.parse().unwrap(),
+            ip: None,
+        }
+    }
 
-        let target = [4, b'f', b'i', b's', b'i', 4, b'a', b'r', b'p', b'a']; // fisi.arpa
-        let score = score_server(&server, &target);
-        assert_eq!(score, 2);
+    #[test]
+    fn test_score_server() {
+        let server = server("foo.f.isi.arpa");
+        let score = |target: &str| score_server(&server, &target.parse().unwrap());
+        assert_eq!(score("f.arpa"), 1);
+        assert_eq!(score("fisi.arpa"), 1);
+        assert_eq!(score("foo.fisi.arpa"), 1);
+        assert_eq!(score("www.ISI.arpa"), 2);
+        assert_eq!(score("foo.f.isi.arpa"), 4);
+        assert_eq!(score("com"), 0);
+    }
 
-        let target = [
-            3, b'f', b'o', b'o', 4, b'f', b'i', b's', b'i', 4, b'a', b'r', b'p', b'a',
-        ]; // fisi.arpa
-        let score = score_server(&server, &target);
-        assert_eq!(score, 2);
+    #[test]
+    fn test_sort_server_list() {
+        let mut servers = [server("a.gtld-servers.net"), server("ns1.example.com")];
+        let target = "www.example.com".parse().unwrap();
+        sort_server_list(&mut servers, &target);
+        assert_eq!(servers[0].name, "ns1.example.com".parse().unwrap());
+        assert_eq!(get_best_server(&servers, &target), 0);
     }
 }
@@ -0,0 +1,462 @@
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use crate::{
+    encoding::{from_base64, to_base64},
+    error::ParseError,
+    name::Name,
+    rdata::{unescape, write_character_string},
+};
+
+/// Rdata of the SVCB and HTTPS records (RFC 9460)
+///
+/// A priority of 0 is AliasMode, where `target` is another name for the service and there are
+/// no parameters, anything else is ServiceMode
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ServiceBinding {
+    pub priority: u16,
+    /// `.` stands for the owner name in ServiceMode
+    pub target: Name,
+    /// Parameters in increasing order of key, which the wire format requires
+    pub params: Vec<SvcParam>,
+}
+
+/// Service parameter, with the keys registered by IANA decoded
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SvcParam {
+    /// Keys the client has to understand to use the record
+    Mandatory(Vec<u16>),
+    /// ALPN protocol ids, like `h2` or `h3`
+    Alpn(Vec<Vec<u8>>),
+    NoDefaultAlpn,
+    Port(u16),
+    Ipv4Hint(Vec<Ipv4Addr>),
+    /// ECHConfigList of TLS Encrypted Client Hello
+    Ech(Vec<u8>),
+    Ipv6Hint(Vec<Ipv6Addr>),
+    /// URI template of a DNS over HTTPS endpoint (RFC 9461)
+    DohPath(String),
+    /// Oblivious HTTP support (RFC 9540)
+    Ohttp,
+    Unknown {
+        key: u16,
+        value: Vec<u8>,
+    },
+}
+
+const KEY_NAMES: [&str; 9] = [
+    "mandatory",
+    "alpn",
+    "no-default-alpn",
+    "port",
+    "ipv4hint",
+    "ech",
+    "ipv6hint",
+    "dohpath",
+    "ohttp",
+];
+
+impl ServiceBinding {
+    pub fn is_alias(&self) -> bool {
+        self.priority == 0
+    }
+
+    /// Returns false if a key listed as mandatory is not one this crate decodes, in which
+    /// case the record has to be ignored
+    pub fn is_supported(&self) -> bool {
+        match self.get_param(0) {
+            Some(SvcParam::Mandatory(keys)) => {
+                keys.iter().all(|&key| (key as usize) < KEY_NAMES.len())
+            }
+            _ => true,
+        }
+    }
+
+    pub fn get_param(&self, key: u16) -> Option<&SvcParam> {
+        self.params.iter().find(|p| p.key() == key)
+    }
+
+    /// Parses the fields of the record after the type, with the parameters in any order
+    pub(crate) fn from_tokens(tokens: &[&str]) -> Result<Self, ParseError> {
+        let [priority, target, params @ ..] = tokens else {
+            return Err(ParseError::InvalidPresentation);
+        };
+        let mut params = params
+            .iter()
+            .map(|token| SvcParam::from_presentation(token))
+            .collect::<Result<Vec<_>, _>>()?;
+        params.sort_by_key(SvcParam::key);
+        if params.windows(2).any(|w| w[0].key() == w[1].key()) {
+            return Err(ParseError::InvalidPresentation);
+        }
+
+        Ok(Self {
+            priority: priority
+                .parse()
+                .map_err(|_| ParseError::InvalidPresentation)?,
+            target: target.parse()?,
+            params,
+        })
+    }
+}
+
+impl fmt::Display for ServiceBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.priority, self.target)?;
+        for param in &self.params {
+            write!(f, " {}", param)?;
+        }
+        Ok(())
+    }
+}
+
+impl SvcParam {
+    pub fn key(&self) -> u16 {
+        match self {
+            Self::Mandatory(_) => 0,
+            Self::Alpn(_) => 1,
+            Self::NoDefaultAlpn => 2,
+            Self::Port(_) => 3,
+            Self::Ipv4Hint(_) => 4,
+            Self::Ech(_) => 5,
+            Self::Ipv6Hint(_) => 6,
+            Self::DohPath(_) => 7,
+            Self::Ohttp => 8,
+            Self::Unknown { key, .. } => *key,
+        }
+    }
+
+    /// Decodes the wire format `value` of the parameter `key`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the value is malformed for a registered key
+    pub fn from_wire(key: u16, value: &[u8]) -> Result<Self, ParseError> {
+        let param = match key {
+            0 if !value.is_empty() && value.len().is_multiple_of(2) => Self::Mandatory(
+                value
+                    .chunks(2)
+                    .map(|k| u16::from_be_bytes([k[0], k[1]]))
+                    .collect(),
+            ),
+            1 => {
+                let mut ids = Vec::new();
+                let mut rest = value;
+                while let Some((&len, tail)) = rest.split_first() {
+                    if len == 0 || tail.len() < len as usize {
+                        return Err(ParseError::InvalidRData);
+                    }
+                    let (id, tail) = tail.split_at(len as usize);
+                    ids.push(id.to_vec());
+                    rest = tail;
+                }
+                if ids.is_empty() {
+                    return Err(ParseError::InvalidRData);
+                }
+                Self::Alpn(ids)
+            }
+            2 if value.is_empty() => Self::NoDefaultAlpn,
+            3 if value.len() == 2 => Self::Port(u16::from_be_bytes([value[0], value[1]])),
+            4 if !value.is_empty() && value.len().is_multiple_of(4) => Self::Ipv4Hint(
+                value
+                    .chunks(4)
+                    .map(|ip| Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]))
+                    .collect(),
+            ),
+            5 => Self::Ech(value.to_vec()),
+            6 if !value.is_empty() && value.len().is_multiple_of(16) => Self::Ipv6Hint(
+                value
+                    .chunks(16)
+                    .map(|ip| Ipv6Addr::from(<[u8; 16]>::try_from(ip).unwrap()))
+                    .collect(),
+            ),
+            7 => Self::DohPath(
+                String::from_utf8(value.to_vec()).map_err(|_| ParseError::InvalidRData)?,
+            ),
+            8 if value.is_empty() => Self::Ohttp,
+            0..=8 => return Err(ParseError::InvalidRData),
+            _ => Self::Unknown {
+                key,
+                value: value.to_vec(),
+            },
+        };
+        Ok(param)
+    }
+
+    /// Writes the wire format value, without the key and length
+    pub fn encode_value(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::Mandatory(keys) => {
+                for key in keys {
+                    buf.extend_from_slice(&key.to_be_bytes());
+                }
+            }
+            Self::Alpn(ids) => {
+                for id in ids {
+                    buf.push(id.len() as u8);
+                    buf.extend_from_slice(id);
+                }
+            }
+            Self::NoDefaultAlpn | Self::Ohttp => {}
+            Self::Port(port) => buf.extend_from_slice(&port.to_be_bytes()),
+            Self::Ipv4Hint(ips) => {
+                for ip in ips {
+                    buf.extend_from_slice(&ip.octets());
+                }
+            }
+            Self::Ipv6Hint(ips) => {
+                for ip in ips {
+                    buf.extend_from_slice(&ip.octets());
+                }
+            }
+            Self::Ech(value) | Self::Unknown { value, .. } => buf.extend_from_slice(value),
+            Self::DohPath(template) => buf.extend_from_slice(template.as_bytes()),
+        }
+    }
+
+    /// Parses a `key=value` or `key` token, the value possibly quoted
+    fn from_presentation(token: &str) -> Result<Self, ParseError> {
+        let (key, value) = match token.split_once('=') {
+            Some((key, value)) => (key, Some(unescape(value)?)),
+            None => (token, None),
+        };
+        let key = key_from_name(key)?;
+        let value = value.unwrap_or_default();
+        let text = || std::str::from_utf8(&value).map_err(|_| ParseError::InvalidPresentation);
+
+        let param = match key {
+            0 => Self::Mandatory(
+                text()?
+                    .split(',')
+                    .map(key_from_name)
+                    .collect::<Result<_, _>>()?,
+            ),
+            1 => Self::Alpn(split_value_list(&value)),
+            3 => Self::Port(
+                text()?
+                    .parse()
+                    .map_err(|_| ParseError::InvalidPresentation)?,
+            ),
+            4 => Self::Ipv4Hint(
+                text()?
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| ParseError::InvalidPresentation)?,
+            ),
+            5 => Self::Ech(from_base64(text()?).ok_or(ParseError::InvalidPresentation)?),
+            6 => Self::Ipv6Hint(
+                text()?
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| ParseError::InvalidPresentation)?,
+            ),
+            7 => Self::DohPath(text()?.to_string()),
+            _ => Self::Unknown { key, value },
+        };
+
+        // Checks the constraints of the wire format, like empty lists or keys without values
+        let mut wire = Vec::new();
+        param.encode_value(&mut wire);
+        Self::from_wire(key, &wire).map_err(|_| ParseError::InvalidPresentation)
+    }
+}
+
+impl fmt::Display for SvcParam {
+    /// Writes the parameter the way it appears in zone files, like `alpn="h2,h3"`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_key(f, self.key())?;
+        match self {
+            Self::Mandatory(keys) => {
+                f.write_str("=")?;
+                for (i, &key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_key(f, key)?;
+                }
+                Ok(())
+            }
+            Self::Alpn(ids) => {
+                // Commas and backslashes inside an id are escaped before the value itself
+                let mut value = Vec::new();
+                for (i, id) in ids.iter().enumerate() {
+                    if i > 0 {
+                        value.push(b',');
+                    }
+                    for &b in id {
+                        if b == b',' || b == b'\\' {
+                            value.push(b'\\');
+                        }
+                        value.push(b);
+                    }
+                }
+                f.write_str("=")?;
+                write_character_string(f, &value)
+            }
+            Self::NoDefaultAlpn | Self::Ohttp => Ok(()),
+            Self::Port(port) => write!(f, "={}", port),
+            Self::Ipv4Hint(ips) => write_list(f, ips),
+            Self::Ech(value) => write!(f, "={}", to_base64(value)),
+            Self::Ipv6Hint(ips) => write_list(f, ips),
+            Self::DohPath(template) => {
+                f.write_str("=")?;
+                write_character_string(f, template.as_bytes())
+            }
+            Self::Unknown { value, .. } => {
+                f.write_str("=")?;
+                write_character_string(f, value)
+            }
+        }
+    }
+}
+
+fn write_key(f: &mut fmt::Formatter<'_>, key: u16) -> fmt::Result {
+    match KEY_NAMES.get(key as usize) {
+        Some(name) => f.write_str(name),
+        None => write!(f, "key{}", key),
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        f.write_str(if i == 0 { "=" } else { "," })?;
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+/// Parses a key name, or the generic `keyNNNNN` form
+fn key_from_name(name: &str) -> Result<u16, ParseError> {
+    if let Some(key) = KEY_NAMES.iter().position(|&n| n == name) {
+        return Ok(key as u16);
+    }
+    name.strip_prefix("key")
+        .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|digits| digits.parse().ok())
+        .ok_or(ParseError::InvalidPresentation)
+}
+
+/// Splits a comma separated value, where `\,` and `\\` stand for a literal comma and
+/// backslash (RFC 9460 A.1)
+fn split_value_list(value: &[u8]) -> Vec<Vec<u8>> {
+    let mut items = vec![Vec::new()];
+    let mut bytes = value.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\\' => items.last_mut().unwrap().extend(bytes.next().copied()),
+            b',' => items.push(Vec::new()),
+            _ => items.last_mut().unwrap().push(b),
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn binding(text: &str) -> ServiceBinding {
+        let tokens = crate::rdata::tokenize(text).unwrap();
+        ServiceBinding::from_tokens(&tokens).unwrap()
+    }
+
+    #[test]
+    fn test_service_binding_presentation() {
+        // Parameters are sorted, and values may be quoted
+        let output = binding(
+            r#"1 svc.example.com. port="8443" alpn=h2,h3 ipv4hint=192.0.2.1,192.0.2.2 mandatory=alpn,port no-default-alpn ech=AEj+DQ== ipv6hint=2001:db8::1 key65000="a b""#,
+        );
+        assert_eq!(
+            output.params,
+            [
+                SvcParam::Mandatory(vec![1, 3]),
+                SvcParam::Alpn(vec![b"h2".to_vec(), b"h3".to_vec()]),
+                SvcParam::NoDefaultAlpn,
+                SvcParam::Port(8443),
+                SvcParam::Ipv4Hint(vec![[192, 0, 2, 1].into(), [192, 0, 2, 2].into()]),
+                SvcParam::Ech(vec![0x00, 0x48, 0xfe, 0x0d]),
+                SvcParam::Ipv6Hint(vec!["2001:db8::1".parse().unwrap()]),
+                SvcParam::Unknown {
+                    key: 65000,
+                    value: b"a b".to_vec(),
+                },
+            ]
+        );
+        assert_eq!(
+            output.to_string(),
+            r#"1 svc.example.com. mandatory=alpn,port alpn="h2,h3" no-default-alpn port=8443 ipv4hint=192.0.2.1,192.0.2.2 ech=AEj+DQ== ipv6hint=2001:db8::1 key65000="a b""#
+        );
+        assert_eq!(binding(&output.to_string()), output);
+
+        // Comma inside an alpn id, from RFC 9460 D.2
+        let output = binding(r#"16 foo.example.org. alpn="f\\\\oo\\,bar,h2""#);
+        assert_eq!(
+            output.params,
+            [SvcParam::Alpn(vec![b"f\\oo,bar".to_vec(), b"h2".to_vec()])]
+        );
+        assert_eq!(binding(&output.to_string()), output);
+
+        let alias = binding("0 pool.svc.example.");
+        assert!(alias.is_alias());
+        assert!(alias.params.is_empty());
+    }
+
+    #[test]
+    fn test_service_binding_invalid_presentation() {
+        for text in [
+            "1 . port=1 port=2",
+            "1 . port=",
+            "1 . port",
+            "1 . no-default-alpn=1",
+            "1 . alpn=",
+            "1 . mandatory=unknown",
+            "1 . ipv4hint=2001:db8::1",
+            "1 . key65536=x",
+            "1",
+        ] {
+            let tokens = crate::rdata::tokenize(text).unwrap();
+            assert!(ServiceBinding::from_tokens(&tokens).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_svc_param_from_wire() {
+        assert_eq!(
+            SvcParam::from_wire(3, &[0x01, 0xbb]),
+            Ok(SvcParam::Port(443))
+        );
+        assert_eq!(
+            SvcParam::from_wire(1, &[2, b'h', b'2', 2, b'h', b'3']),
+            Ok(SvcParam::Alpn(vec![b"h2".to_vec(), b"h3".to_vec()]))
+        );
+        assert_eq!(
+            SvcParam::from_wire(9, &[1]),
+            Ok(SvcParam::Unknown {
+                key: 9,
+                value: vec![1]
+            })
+        );
+        for (key, value) in [
+            (0, &[0][..]),
+            (1, &[3, b'h', b'2']),
+            (1, &[0]),
+            (2, &[0]),
+            (3, &[1]),
+            (4, &[192, 0, 2]),
+            (6, &[]),
+            (7, &[0xff]),
+            (8, &[1]),
+        ] {
+            assert_eq!(
+                SvcParam::from_wire(key, value),
+                Err(ParseError::InvalidRData),
+                "{}",
+                key
+            );
+        }
+    }
+}
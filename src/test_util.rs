@@ -0,0 +1,22 @@
+//! Fixtures shared by the unit tests
+
+use crate::{
+    name::Name,
+    rdata::RData,
+    record::{Record, RecordClass},
+};
+
+pub fn name(domain: &str) -> Name {
+    domain.parse().unwrap()
+}
+
+/// IN record of `domain` with an hour of TTL
+pub fn record(domain: &str, rdata: RData) -> Record {
+    Record {
+        name: name(domain),
+        rtype: rdata.get_type(),
+        rclass: RecordClass::In,
+        ttl: 3600,
+        rdata,
+    }
+}
@@ -0,0 +1,540 @@
+use std::fmt;
+
+use ring::hmac;
+
+use crate::{
+    builder::OwnedMessage,
+    encoding::to_base64,
+    error::DnsError,
+    message::{Encodable, Header, Question, Rcode, ResourceRecord},
+    name::Name,
+    rdata::RData,
+    record::{Record, RecordClass, RecordType},
+};
+
+/// Allowed difference between the signing time and the local clock, in seconds, recommended
+/// by RFC 8945 5.2.3
+pub const DEFAULT_FUDGE: u16 = 300;
+
+/// Unsigned messages allowed between two signed ones of a multi-message response
+/// (RFC 8945 5.3.1)
+const MAX_UNSIGNED_MESSAGES: usize = 99;
+
+/// BADSIG shares its value with BADVERS, which is what [`Rcode`] calls it
+const BADSIG: Rcode = Rcode::BadVers;
+
+/// Transaction signature, always the last additional record of a message (RFC 8945 4.2)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Tsig {
+    pub algorithm: Name,
+    /// Seconds since the epoch, 48 bits on the wire
+    pub time_signed: u64,
+    pub fudge: u16,
+    pub mac: Vec<u8>,
+    /// Id of the message when it was signed, which forwarders may change
+    pub original_id: u16,
+    pub error: Rcode,
+    pub other: Vec<u8>,
+}
+
+/// HMAC algorithms mandated by RFC 8945 6
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+/// Shared secret known to both ends, and the name they know it by
+#[derive(Clone)]
+pub struct TsigKey {
+    pub name: Name,
+    pub algorithm: TsigAlgorithm,
+    secret: Vec<u8>,
+}
+
+/// Signs and verifies the messages of one exchange: a request and its response, or the
+/// sequence of responses to a request on a TCP connection, like a zone transfer
+///
+/// Every MAC covers the one before it, so the messages have to go through the same exchange
+/// in the order they are sent, whichever side signs them
+#[derive(Debug, Clone)]
+pub struct TsigExchange {
+    key: TsigKey,
+    fudge: u16,
+    /// MAC of the last signed message
+    prior_mac: Option<Vec<u8>>,
+    /// Number of messages signed or verified so far
+    messages: usize,
+    /// Messages sent or received unsigned since the last signed one, covered by the next MAC
+    unsigned: Vec<u8>,
+    unsigned_count: usize,
+}
+
+impl TsigAlgorithm {
+    pub fn name(self) -> Name {
+        let name = match self {
+            Self::HmacSha256 => "hmac-sha256.",
+            Self::HmacSha512 => "hmac-sha512.",
+        };
+        name.parse().unwrap_or_else(|_| Name::root())
+    }
+
+    pub fn from_name(name: &Name) -> Option<Self> {
+        [Self::HmacSha256, Self::HmacSha512]
+            .into_iter()
+            .find(|algorithm| &algorithm.name() == name)
+    }
+
+    fn hmac(self) -> hmac::Algorithm {
+        match self {
+            Self::HmacSha256 => hmac::HMAC_SHA256,
+            Self::HmacSha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+impl TsigKey {
+    pub fn new(name: Name, algorithm: TsigAlgorithm, secret: &[u8]) -> Self {
+        Self {
+            name,
+            algorithm,
+            secret: secret.to_vec(),
+        }
+    }
+
+    fn hmac_key(&self) -> hmac::Key {
+        hmac::Key::new(self.algorithm.hmac(), &self.secret)
+    }
+}
+
+impl fmt::Debug for TsigKey {
+    /// Leaves out the secret, so keys can be logged
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TsigKey")
+            .field("name", &self.name)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TsigExchange {
+    pub fn new(key: TsigKey) -> Self {
+        Self::with_fudge(key, DEFAULT_FUDGE)
+    }
+
+    /// Same as [`TsigExchange::new`], allowing a difference of `fudge` seconds between the
+    /// clocks of both ends
+    pub fn with_fudge(key: TsigKey, fudge: u16) -> Self {
+        Self {
+            key,
+            fudge,
+            prior_mac: None,
+            messages: 0,
+            unsigned: Vec::new(),
+            unsigned_count: 0,
+        }
+    }
+
+    /// Encodes `message` followed by its TSIG record, signed at `now` in seconds since the
+    /// epoch
+    ///
+    /// Fails with [`DnsError::InvalidFormat`] if the additional section is already full, so
+    /// there is no room for the TSIG record
+    pub fn sign(&mut self, message: &OwnedMessage, now: u64) -> Result<Vec<u8>, DnsError> {
+        let mut bytes = Vec::new();
+        message.encode(&mut bytes);
+        let arcount = u16::from_be_bytes([bytes[10], bytes[11]])
+            .checked_add(1)
+            .ok_or(DnsError::InvalidFormat)?;
+        let mut tsig = Tsig {
+            algorithm: self.key.algorithm.name(),
+            time_signed: now & 0xffff_ffff_ffff,
+            fudge: self.fudge,
+            mac: Vec::new(),
+            original_id: message.get_id(),
+            error: Rcode::NoError,
+            other: Vec::new(),
+        };
+        let data = self.signed_data(&bytes, &tsig);
+        tsig.mac = hmac::sign(&self.key.hmac_key(), &data).as_ref().to_vec();
+        self.advance(&tsig.mac);
+
+        let record = Record {
+            name: self.key.name.clone(),
+            rtype: RecordType::Tsig,
+            rclass: RecordClass::Any,
+            ttl: 0,
+            rdata: RData::Tsig(tsig),
+        };
+        record.encode(&mut bytes);
+        bytes[10..12].copy_from_slice(&arcount.to_be_bytes());
+        Ok(bytes)
+    }
+
+    /// Encodes a message of a multi-message response without a TSIG record, the next signed
+    /// message covers it
+    pub fn encode_unsigned(&mut self, message: &OwnedMessage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        message.encode(&mut bytes);
+        self.unsigned.extend_from_slice(&bytes);
+        self.unsigned_count += 1;
+        bytes
+    }
+
+    /// Checks the TSIG record of the next message of the exchange, received at `now`
+    ///
+    /// Messages after the first response may come unsigned, see
+    /// [`TsigExchange::pending_unsigned`]. Truncated MACs are not accepted
+    pub fn verify(&mut self, bytes: &[u8], now: u64) -> Result<(), DnsError> {
+        let Some((offset, record)) = find_tsig(bytes)? else {
+            if self.messages >= 2 && self.unsigned_count < MAX_UNSIGNED_MESSAGES {
+                self.unsigned.extend_from_slice(bytes);
+                self.unsigned_count += 1;
+                return Ok(());
+            }
+            return Err(DnsError::BadSig);
+        };
+        let RData::Tsig(tsig) = &record.rdata else {
+            return Err(DnsError::InvalidFormat);
+        };
+
+        // The other end couldn't check our signature, and doesn't sign its answer
+        match tsig.error {
+            Rcode::BadKey => return Err(DnsError::BadKey),
+            BADSIG => return Err(DnsError::BadSig),
+            _ => {}
+        }
+        if record.name != self.key.name
+            || TsigAlgorithm::from_name(&tsig.algorithm) != Some(self.key.algorithm)
+        {
+            return Err(DnsError::BadKey);
+        }
+
+        // The MAC covers the message as it was before the TSIG record was added
+        let mut message = bytes[..offset].to_vec();
+        message[..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+        let arcount = u16::from_be_bytes([message[10], message[11]]) - 1;
+        message[10..12].copy_from_slice(&arcount.to_be_bytes());
+        let data = self.signed_data(&message, tsig);
+        hmac::verify(&self.key.hmac_key(), &data, &tsig.mac).map_err(|_| DnsError::BadSig)?;
+        self.advance(&tsig.mac);
+
+        if tsig.error == Rcode::BadTime || now.abs_diff(tsig.time_signed) > u64::from(tsig.fudge) {
+            return Err(DnsError::BadTime);
+        }
+        Ok(())
+    }
+
+    /// Number of messages accepted unsigned since the last signed one, the exchange must not
+    /// end before another signed message covers them
+    pub fn pending_unsigned(&self) -> usize {
+        self.unsigned_count
+    }
+
+    /// Builds the input of the MAC for `message`, without its TSIG record (RFC 8945 4.3)
+    fn signed_data(&self, message: &[u8], tsig: &Tsig) -> Vec<u8> {
+        let mut data = Vec::new();
+        if let Some(prior_mac) = &self.prior_mac {
+            data.extend_from_slice(&(prior_mac.len() as u16).to_be_bytes());
+            data.extend_from_slice(prior_mac);
+        }
+        data.extend_from_slice(&self.unsigned);
+        data.extend_from_slice(message);
+
+        // Messages after the first response only cover the timers (RFC 8945 5.3.1)
+        if self.messages < 2 {
+            data.extend_from_slice(self.key.name.to_lowercase().as_wire());
+            data.extend_from_slice(&u16::from(RecordClass::Any).to_be_bytes());
+            data.extend_from_slice(&0u32.to_be_bytes());
+            data.extend_from_slice(tsig.algorithm.to_lowercase().as_wire());
+        }
+        data.extend_from_slice(&tsig.time_signed.to_be_bytes()[2..]);
+        data.extend_from_slice(&tsig.fudge.to_be_bytes());
+        if self.messages < 2 {
+            data.extend_from_slice(&u16::from(tsig.error).to_be_bytes());
+            data.extend_from_slice(&(tsig.other.len() as u16).to_be_bytes());
+            data.extend_from_slice(&tsig.other);
+        }
+        data
+    }
+
+    fn advance(&mut self, mac: &[u8]) {
+        self.prior_mac = Some(mac.to_vec());
+        self.messages += 1;
+        self.unsigned.clear();
+        self.unsigned_count = 0;
+    }
+}
+
+/// Returns the TSIG record of a message and the offset it starts at, checking that it is
+/// the last record
+fn find_tsig(bytes: &[u8]) -> Result<Option<(usize, Record)>, DnsError> {
+    let header = Header::from_bytes(bytes)?;
+    let mut offset = 12;
+    for _ in 0..header.qdcount {
+        offset = Question::from_bytes(bytes, offset)?.1;
+    }
+    let records = u32::from(header.ancount) + u32::from(header.nscount) + u32::from(header.arcount);
+    let mut tsig = None;
+    for i in 0..records {
+        let (rr, end) = ResourceRecord::from_bytes(bytes, offset)?;
+        if rr.get_type() == RecordType::Tsig {
+            if i + 1 != records || header.arcount == 0 {
+                return Err(DnsError::InvalidFormat);
+            }
+            tsig = Some((offset, rr.to_record(bytes)?));
+        }
+        offset = end;
+    }
+    Ok(tsig)
+}
+
+impl Encodable for Tsig {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.algorithm.as_wire());
+        buf.extend_from_slice(&self.time_signed.to_be_bytes()[2..]);
+        buf.extend_from_slice(&self.fudge.to_be_bytes());
+        buf.extend_from_slice(&(self.mac.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.mac);
+        buf.extend_from_slice(&self.original_id.to_be_bytes());
+        buf.extend_from_slice(&u16::from(self.error).to_be_bytes());
+        buf.extend_from_slice(&(self.other.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.other);
+    }
+}
+
+impl fmt::Display for Tsig {
+    /// Writes the fields in the order dig shows them
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.algorithm,
+            self.time_signed,
+            self.fudge,
+            self.mac.len()
+        )?;
+        if !self.mac.is_empty() {
+            write!(f, " {}", to_base64(&self.mac))?;
+        }
+        write!(f, " {} ", self.original_id)?;
+        match self.error {
+            BADSIG => f.write_str("BADSIG")?,
+            error => write!(f, "{}", error)?,
+        }
+        write!(f, " {}", self.other.len())?;
+        if !self.other.is_empty() {
+            write!(f, " {}", to_base64(&self.other))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{builder::MessageBuilder, test_util::name};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn key(algorithm: TsigAlgorithm) -> TsigKey {
+        TsigKey::new(name("transfer.example"), algorithm, b"0123456789abcdef")
+    }
+
+    fn request() -> OwnedMessage {
+        MessageBuilder::new(0x1234)
+            .question(name("example.com"), RecordType::Soa, RecordClass::In)
+            .build()
+    }
+
+    fn response(id: u16, serial: u32) -> OwnedMessage {
+        MessageBuilder::new(id)
+            .qr(true)
+            .answer(Record {
+                name: name("example.com"),
+                rtype: RecordType::Soa,
+                rclass: RecordClass::In,
+                ttl: 3600,
+                rdata: RData::Soa {
+                    mname: name("ns.example.com"),
+                    rname: name("hostmaster.example.com"),
+                    serial,
+                    refresh: 3600,
+                    retry: 600,
+                    expire: 86400,
+                    minimum: 300,
+                },
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_tsig_round_trip() {
+        for algorithm in [TsigAlgorithm::HmacSha256, TsigAlgorithm::HmacSha512] {
+            let mut client = TsigExchange::new(key(algorithm));
+            let mut server = TsigExchange::new(key(algorithm));
+
+            let bytes = client.sign(&request(), NOW).unwrap();
+            let signed = OwnedMessage::from_bytes(&bytes).unwrap();
+            assert_eq!(signed.get_additionals().len(), 1);
+            let RData::Tsig(tsig) = &signed.get_additionals()[0].rdata else {
+                panic!("no TSIG record");
+            };
+            assert_eq!(tsig.algorithm, algorithm.name());
+            assert_eq!(tsig.original_id, 0x1234);
+            server.verify(&bytes, NOW + 10).unwrap();
+
+            let bytes = server.sign(&response(0x1234, 1), NOW + 10).unwrap();
+            client.verify(&bytes, NOW + 20).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_tsig_errors() {
+        let mut client = TsigExchange::new(key(TsigAlgorithm::HmacSha256));
+        let bytes = client.sign(&request(), NOW).unwrap();
+        let verify = |bytes: &[u8], key: TsigKey, now| TsigExchange::new(key).verify(bytes, now);
+
+        // Changed after signing
+        let mut changed = bytes.clone();
+        changed[13] = b'x';
+        assert!(matches!(
+            verify(&changed, key(TsigAlgorithm::HmacSha256), NOW),
+            Err(DnsError::BadSig)
+        ));
+        let other = TsigKey::new(name("transfer.example"), TsigAlgorithm::HmacSha256, b"x");
+        assert!(matches!(verify(&bytes, other, NOW), Err(DnsError::BadSig)));
+
+        // Unknown key name or algorithm
+        let other = TsigKey::new(name("other.example"), TsigAlgorithm::HmacSha256, b"x");
+        assert!(matches!(verify(&bytes, other, NOW), Err(DnsError::BadKey)));
+        assert!(matches!(
+            verify(&bytes, key(TsigAlgorithm::HmacSha512), NOW),
+            Err(DnsError::BadKey)
+        ));
+
+        // Outside of the fudge, either way
+        let key256 = || key(TsigAlgorithm::HmacSha256);
+        assert!(verify(&bytes, key256(), NOW + 300).is_ok());
+        assert!(matches!(
+            verify(&bytes, key256(), NOW + 301),
+            Err(DnsError::BadTime)
+        ));
+        assert!(matches!(
+            verify(&bytes, key256(), NOW - 301),
+            Err(DnsError::BadTime)
+        ));
+
+        // Unsigned message
+        let mut unsigned = Vec::new();
+        request().encode(&mut unsigned);
+        assert!(matches!(
+            verify(&unsigned, key256(), NOW),
+            Err(DnsError::BadSig)
+        ));
+    }
+
+    #[test]
+    fn test_tsig_error_response() {
+        let mut client = TsigExchange::new(key(TsigAlgorithm::HmacSha256));
+        client.sign(&request(), NOW).unwrap();
+
+        // Servers answer requests they can't verify with an unsigned TSIG record
+        let mut response = response(0x1234, 1);
+        response.get_header_mut().set_rcode(Rcode::NotAuth);
+        let mut bytes = Vec::new();
+        response.encode(&mut bytes);
+        let tsig = Tsig {
+            algorithm: TsigAlgorithm::HmacSha256.name(),
+            time_signed: NOW,
+            fudge: DEFAULT_FUDGE,
+            mac: Vec::new(),
+            original_id: 0x1234,
+            error: Rcode::BadKey,
+            other: Vec::new(),
+        };
+        let record = Record {
+            name: name("transfer.example"),
+            rtype: RecordType::Tsig,
+            rclass: RecordClass::Any,
+            ttl: 0,
+            rdata: RData::Tsig(tsig),
+        };
+        record.encode(&mut bytes);
+        bytes[11] = 1;
+        assert!(matches!(client.verify(&bytes, NOW), Err(DnsError::BadKey)));
+        assert_eq!(
+            record.to_string(),
+            "transfer.example.\t0\tANY\tTSIG\thmac-sha256. 1700000000 300 0 4660 BADKEY 0"
+        );
+    }
+
+    #[test]
+    fn test_tsig_multiple_messages() {
+        let key = key(TsigAlgorithm::HmacSha512);
+        let mut client = TsigExchange::new(key.clone());
+        let mut server = TsigExchange::new(key);
+        let signed = client.sign(&request(), NOW).unwrap();
+        server.verify(&signed, NOW).unwrap();
+
+        let messages = [
+            server.sign(&response(0x1234, 1), NOW).unwrap(),
+            server.sign(&response(0x1234, 2), NOW).unwrap(),
+            server.encode_unsigned(&response(0x1234, 3)),
+            server.encode_unsigned(&response(0x1234, 4)),
+            server.sign(&response(0x1234, 5), NOW + 1).unwrap(),
+        ];
+        for (i, message) in messages.iter().enumerate() {
+            client.verify(message, NOW + 2).unwrap();
+            assert_eq!(client.pending_unsigned(), [0, 0, 1, 2, 0][i]);
+        }
+
+        // Messages can't be skipped or reordered
+        let mut client = TsigExchange::new(client.key.clone());
+        client.sign(&request(), NOW).unwrap();
+        client.verify(&messages[0], NOW).unwrap();
+        assert!(matches!(
+            client.verify(&messages[4], NOW),
+            Err(DnsError::BadSig)
+        ));
+    }
+
+    #[test]
+    fn test_tsig_misplaced() {
+        // A TSIG record in the answer section
+        let mut exchange = TsigExchange::new(key(TsigAlgorithm::HmacSha256));
+        let bytes = exchange.sign(&request(), NOW).unwrap();
+        let mut moved = bytes.clone();
+        moved[7] = 1;
+        moved[11] = 0;
+        assert!(matches!(
+            TsigExchange::new(key(TsigAlgorithm::HmacSha256)).verify(&moved, NOW),
+            Err(DnsError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn test_tsig_full_additional_section() {
+        let glue = Record {
+            name: Name::root(),
+            rtype: RecordType::A,
+            rclass: RecordClass::In,
+            ttl: 0,
+            rdata: RData::A([192, 0, 2, 1].into()),
+        };
+        let mut builder = MessageBuilder::new(0x1234);
+        for _ in 0..u16::MAX {
+            builder = builder.additional(glue.clone());
+        }
+        let mut exchange = TsigExchange::new(key(TsigAlgorithm::HmacSha256));
+        assert!(matches!(
+            exchange.sign(&builder.build(), NOW),
+            Err(DnsError::InvalidFormat)
+        ));
+
+        // Nothing was signed, so the exchange goes on as if it hadn't been tried
+        let bytes = exchange.sign(&request(), NOW).unwrap();
+        let mut server = TsigExchange::new(key(TsigAlgorithm::HmacSha256));
+        assert!(server.verify(&bytes, NOW).is_ok());
+    }
+}
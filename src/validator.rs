@@ -0,0 +1,1065 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::{
+    digest,
+    signature::{self, RsaPublicKeyComponents, UnparsedPublicKey},
+};
+
+use crate::{
+    denial::{
+        Proof, prove_nodata, prove_nxdomain, prove_unsigned_delegation, prove_wildcard_expansion,
+    },
+    dnssec::{Dnskey, Ds, Rrsig},
+    message::Encodable,
+    name::Name,
+    rdata::RData,
+    record::{Record, RecordType},
+};
+
+const RSA_SHA256: u8 = 8;
+const ECDSA_P256_SHA256: u8 = 13;
+const ECDSA_P384_SHA384: u8 = 14;
+const ED25519: u8 = 15;
+
+const DIGEST_SHA256: u8 = 2;
+const DIGEST_SHA384: u8 = 4;
+
+/// DS records of the root KSKs published by IANA, KSK-2017 and KSK-2024
+const ROOT_TRUST_ANCHOR: [&str; 2] = [
+    "20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D",
+    "38696 8 2 683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16",
+];
+
+/// Result of validating an RRset (RFC 4035 4.3)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Security {
+    /// Signed and chained up to the trust anchor
+    Secure,
+    /// Proven to be in an unsigned zone, or one signed with unsupported algorithms
+    Insecure,
+    /// Should be signed, but the signatures are missing, expired or wrong
+    Bogus,
+    /// No trust anchor covers the name
+    Indeterminate,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ValidatedRecord {
+    pub record: Record,
+    pub security: Security,
+}
+
+/// Answer to a query, positive or negative, with its security
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ValidatedAnswer {
+    /// Records of the type asked for, with the security of their RRset
+    Records(Vec<ValidatedRecord>),
+    /// The name doesn't exist, with the security of the NSEC or NSEC3 proof
+    NxDomain(Security),
+    /// The name has no records of the type asked for
+    NoData(Security),
+}
+
+impl Security {
+    /// Security of a result relying on both `self` and `other`, which is the weaker of the two
+    pub fn and(self, other: Self) -> Self {
+        use Security::*;
+        match (self, other) {
+            (Bogus, _) | (_, Bogus) => Bogus,
+            (Indeterminate, _) | (_, Indeterminate) => Indeterminate,
+            (Insecure, _) | (_, Insecure) => Insecure,
+            (Secure, Secure) => Secure,
+        }
+    }
+}
+
+impl ValidatedAnswer {
+    /// Lowers the security of the answer to the weakest of the CNAME records leading to it,
+    /// since whoever forged one of them chose where the answer comes from
+    pub fn through_aliases(self, aliases: &[ValidatedRecord]) -> Self {
+        let chain = aliases.iter().fold(Security::Secure, |security, alias| {
+            security.and(alias.security)
+        });
+        match self {
+            Self::Records(records) => Self::Records(
+                records
+                    .into_iter()
+                    .map(|record| ValidatedRecord {
+                        security: record.security.and(chain),
+                        ..record
+                    })
+                    .collect(),
+            ),
+            Self::NxDomain(security) => Self::NxDomain(security.and(chain)),
+            Self::NoData(security) => Self::NoData(security.and(chain)),
+        }
+    }
+}
+
+/// What is known about a zone on the path from the trust anchor
+#[derive(Debug, Clone)]
+enum ZoneState {
+    /// Validated DS records, whose DNSKEY RRset has not been fetched yet
+    Ds(Vec<Ds>),
+    /// Validated DNSKEY RRset
+    Keys(Vec<Dnskey>),
+    Insecure,
+    Bogus,
+}
+
+/// Builds the chain of trust from the root down to the zones of the answers
+///
+/// The validator doesn't send anything itself: the resolver feeds it the DNSKEY RRsets and
+/// referrals it receives, so any sequence of responses can be validated offline
+#[derive(Debug, Clone)]
+pub struct Validator {
+    /// Time the signatures have to be valid at, in seconds since the epoch
+    now: u32,
+    zones: Vec<(Name, ZoneState)>,
+}
+
+/// Returns the DS records of the root zone KSKs
+pub fn root_trust_anchor() -> Vec<Ds> {
+    ROOT_TRUST_ANCHOR
+        .iter()
+        .filter_map(
+            |text| match RData::from_presentation(RecordType::Ds, text) {
+                Ok(RData::Ds(ds)) => Some(ds),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+impl Validator {
+    /// Starts a chain of trust at the root, whose keys have to match `trust_anchor`
+    pub fn new(trust_anchor: Vec<Ds>, now: u32) -> Self {
+        Self {
+            now,
+            zones: vec![(Name::root(), ZoneState::Ds(trust_anchor))],
+        }
+    }
+
+    /// Same as [`Validator::new`], checking signatures against the system time
+    pub fn with_system_time(trust_anchor: Vec<Ds>) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        // Signature times are compared with serial number arithmetic, so wrapping is fine
+        Self::new(trust_anchor, now as u32)
+    }
+
+    /// Returns true if `zone` has validated DS records but its keys are still missing
+    pub fn needs_keys(&self, zone: &Name) -> bool {
+        matches!(self.state(zone), Some(ZoneState::Ds(_)))
+    }
+
+    /// Returns the security of the deepest zone known to contain `name`
+    pub fn zone_security(&self, name: &Name) -> Security {
+        match self.closest_zone(name) {
+            Some((_, ZoneState::Keys(_))) => Security::Secure,
+            Some((_, ZoneState::Insecure)) => Security::Insecure,
+            Some((_, ZoneState::Bogus)) => Security::Bogus,
+            Some((_, ZoneState::Ds(_))) | None => Security::Indeterminate,
+        }
+    }
+
+    /// Validates the DNSKEY RRset of `zone` found in `records`, against the DS records of the
+    /// zone, and remembers the keys if it is secure
+    pub fn add_keys(&mut self, zone: &Name, records: &[Record]) -> Security {
+        let Some(ZoneState::Ds(ds_set)) = self.state(zone) else {
+            return self.zone_security(zone);
+        };
+
+        // A zone is insecure if the parent only vouches for algorithms we can't check (RFC 4035 5.2)
+        if !ds_set
+            .iter()
+            .any(|ds| is_supported_algorithm(ds.algorithm) && is_supported_digest(ds.digest_type))
+        {
+            return self.set_state(zone, ZoneState::Insecure);
+        }
+
+        let rrset: Vec<_> = records
+            .iter()
+            .filter(|r| r.rtype == RecordType::Dnskey && &r.name == zone)
+            .cloned()
+            .collect();
+        let keys: Vec<Dnskey> = rrset
+            .iter()
+            .filter_map(|r| match &r.rdata {
+                RData::Dnskey(key) => Some(key.clone()),
+                _ => None,
+            })
+            .collect();
+        let trusted: Vec<Dnskey> = keys
+            .iter()
+            .filter(|key| ds_set.iter().any(|ds| ds_matches(zone, key, ds)))
+            .cloned()
+            .collect();
+
+        let rrsigs = covering_rrsigs(records, zone, RecordType::Dnskey);
+        if !trusted.is_empty() && self.verify_rrset(&rrset, &rrsigs, zone, &trusted).is_some() {
+            self.set_state(zone, ZoneState::Keys(keys))
+        } else {
+            self.set_state(zone, ZoneState::Bogus)
+        }
+    }
+
+    /// Follows a referral to `child`, validating the DS RRset in `authorities` with the keys
+    /// of the parent zone
+    ///
+    /// Without DS records the delegation is insecure, as long as the NSEC or NSEC3 records
+    /// signed by the parent prove that there are none
+    pub fn add_delegation(&mut self, child: &Name, authorities: &[Record]) -> Security {
+        let Some((parent, state)) = self.closest_zone(child) else {
+            return Security::Indeterminate;
+        };
+        if parent == child {
+            return self.zone_security(child);
+        }
+        let keys = match state {
+            ZoneState::Keys(keys) => keys,
+            ZoneState::Insecure => return self.set_state(child, ZoneState::Insecure),
+            ZoneState::Bogus => return self.set_state(child, ZoneState::Bogus),
+            ZoneState::Ds(_) => return Security::Indeterminate,
+        };
+        let parent = parent.clone();
+
+        let ds_rrset: Vec<_> = authorities
+            .iter()
+            .filter(|r| r.rtype == RecordType::Ds && &r.name == child)
+            .cloned()
+            .collect();
+        if !ds_rrset.is_empty() {
+            let rrsigs = covering_rrsigs(authorities, child, RecordType::Ds);
+            if self
+                .verify_rrset(&ds_rrset, &rrsigs, &parent, keys)
+                .is_none()
+            {
+                return self.set_state(child, ZoneState::Bogus);
+            }
+            let ds_set = ds_rrset
+                .into_iter()
+                .filter_map(|r| match r.rdata {
+                    RData::Ds(ds) => Some(ds),
+                    _ => None,
+                })
+                .collect();
+            self.set_state(child, ZoneState::Ds(ds_set));
+            return Security::Secure;
+        }
+
+        let denials = self.verified_denials(&parent, keys, authorities);
+        match prove_unsigned_delegation(child, &parent, &denials) {
+            Proof::Proven | Proof::Insecure => self.set_state(child, ZoneState::Insecure),
+            Proof::Failed => self.set_state(child, ZoneState::Bogus),
+        }
+    }
+
+    /// Returns the signers of the RRSIGs in `records` that are zones below the ones already
+    /// known, which the resolver has to fetch the DS and DNSKEY records of first
+    pub fn unknown_signers(&self, records: &[Record]) -> Vec<Name> {
+        let mut signers = Vec::new();
+        for record in records {
+            let RData::Rrsig(rrsig) = &record.rdata else {
+                continue;
+            };
+            let known = match self.closest_zone(&rrsig.signer) {
+                Some((zone, ZoneState::Keys(_))) => zone == &rrsig.signer,
+                _ => true,
+            };
+            if !known && !signers.contains(&rrsig.signer) {
+                signers.push(rrsig.signer.clone());
+            }
+        }
+        signers
+    }
+
+    /// Validates every RRset in `records` with the RRSIGs found next to it, returning the
+    /// records other than RRSIGs with the security of their RRset
+    pub fn validate_records(&self, records: &[Record]) -> Vec<ValidatedRecord> {
+        let mut validated = Vec::new();
+        for rrset in rrsets(records) {
+            let security = self.validate_rrset(&rrset, records);
+            validated.extend(
+                rrset
+                    .into_iter()
+                    .map(|record| ValidatedRecord { record, security }),
+            );
+        }
+        validated
+    }
+
+    /// Validates `rrset` with the RRSIGs covering it in `records`
+    ///
+    /// RRsets expanded from a wildcard also need the NSEC or NSEC3 records in `records` that
+    /// prove the name doesn't exist itself
+    pub fn validate_rrset(&self, rrset: &[Record], records: &[Record]) -> Security {
+        let Some(first) = rrset.first() else {
+            return Security::Indeterminate;
+        };
+        let Some((zone, ZoneState::Keys(keys))) = self.closest_zone(&first.name) else {
+            return self.zone_security(&first.name);
+        };
+        let rrsigs = covering_rrsigs(records, &first.name, first.rtype);
+        let Some(rrsig) = self.verify_rrset(rrset, &rrsigs, zone, keys) else {
+            return Security::Bogus;
+        };
+        if (rrsig.labels as usize) < first.name.label_count() && !first.name.is_wildcard() {
+            let encloser = first.name.suffix(rrsig.labels as usize);
+            let denials = self.verified_denials(zone, keys, records);
+            return proof_security(prove_wildcard_expansion(
+                &first.name,
+                &encloser,
+                zone,
+                &denials,
+            ));
+        }
+        Security::Secure
+    }
+
+    /// Validates a response without records of type `qtype` for `qname`, with the NSEC or
+    /// NSEC3 records in `records`
+    ///
+    /// `nxdomain` tells whether the response claims the name doesn't exist at all
+    pub fn validate_denial(
+        &self,
+        qname: &Name,
+        qtype: RecordType,
+        nxdomain: bool,
+        records: &[Record],
+    ) -> Security {
+        // DS records are denied by the parent zone
+        let owner = match qname.parent() {
+            Some(parent) if qtype == RecordType::Ds => parent,
+            _ => qname.clone(),
+        };
+        let Some((zone, ZoneState::Keys(keys))) = self.closest_zone(&owner) else {
+            return self.zone_security(&owner);
+        };
+        let denials = self.verified_denials(zone, keys, records);
+        proof_security(if nxdomain {
+            prove_nxdomain(qname, zone, &denials)
+        } else {
+            prove_nodata(qname, qtype, zone, &denials)
+        })
+    }
+
+    /// Returns the NSEC and NSEC3 RRsets of `records` validly signed by `keys` of `zone`
+    fn verified_denials(&self, zone: &Name, keys: &[Dnskey], records: &[Record]) -> Vec<Record> {
+        rrsets(records)
+            .into_iter()
+            .filter(|rrset| matches!(rrset[0].rtype, RecordType::Nsec | RecordType::Nsec3))
+            .filter(|rrset| {
+                let rrsigs = covering_rrsigs(records, &rrset[0].name, rrset[0].rtype);
+                self.verify_rrset(rrset, &rrsigs, zone, keys).is_some()
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Returns the first of `rrsigs` that is a valid signature of `rrset` by one of `keys`,
+    /// which belong to `zone` (RFC 4035 5.3)
+    fn verify_rrset<'r>(
+        &self,
+        rrset: &[Record],
+        rrsigs: &'r [Rrsig],
+        zone: &Name,
+        keys: &[Dnskey],
+    ) -> Option<&'r Rrsig> {
+        let first = rrset.first()?;
+        let owner_labels = first.name.label_count() - usize::from(first.name.is_wildcard());
+
+        rrsigs.iter().find(|rrsig| {
+            if &rrsig.signer != zone
+                || !first.name.is_subdomain_of(zone)
+                || rrsig.type_covered != first.rtype
+                || rrsig.labels as usize > owner_labels
+                || !serial_le(rrsig.inception, self.now)
+                || !serial_le(self.now, rrsig.expiration)
+            {
+                return false;
+            }
+            let data = signed_data(rrset, rrsig);
+            keys.iter().any(|key| {
+                key.algorithm == rrsig.algorithm
+                    && key.key_tag() == rrsig.key_tag
+                    && key.protocol == 3
+                    && key.is_zone_key()
+                    && !key.is_revoked()
+                    && verify_signature(key, &rrsig.signature, &data)
+            })
+        })
+    }
+
+    fn state(&self, zone: &Name) -> Option<&ZoneState> {
+        self.zones
+            .iter()
+            .find(|(name, _)| name == zone)
+            .map(|(_, state)| state)
+    }
+
+    /// Deepest zone that `name` is in, or is
+    fn closest_zone(&self, name: &Name) -> Option<(&Name, &ZoneState)> {
+        self.zones
+            .iter()
+            .filter(|(zone, _)| name.is_subdomain_of(zone))
+            .max_by_key(|(zone, _)| zone.label_count())
+            .map(|(zone, state)| (zone, state))
+    }
+
+    fn set_state(&mut self, zone: &Name, state: ZoneState) -> Security {
+        match self.zones.iter_mut().find(|(name, _)| name == zone) {
+            Some((_, old)) => *old = state,
+            None => self.zones.push((zone.clone(), state)),
+        }
+        self.zone_security(zone)
+    }
+}
+
+fn proof_security(proof: Proof) -> Security {
+    match proof {
+        Proof::Proven => Security::Secure,
+        Proof::Insecure => Security::Insecure,
+        Proof::Failed => Security::Bogus,
+    }
+}
+
+/// Splits `records` into RRsets, leaving out the RRSIGs
+fn rrsets(records: &[Record]) -> Vec<Vec<Record>> {
+    let mut rrsets: Vec<Vec<Record>> = Vec::new();
+    for record in records.iter().filter(|r| r.rtype != RecordType::Rrsig) {
+        let rrset = rrsets.iter_mut().find(|rrset| {
+            rrset[0].name == record.name
+                && rrset[0].rtype == record.rtype
+                && rrset[0].rclass == record.rclass
+        });
+        match rrset {
+            Some(rrset) => rrset.push(record.clone()),
+            None => rrsets.push(vec![record.clone()]),
+        }
+    }
+    rrsets
+}
+
+/// Finds the RRSIGs in `records` over the RRset of type `rtype` at `owner`
+fn covering_rrsigs(records: &[Record], owner: &Name, rtype: RecordType) -> Vec<Rrsig> {
+    records
+        .iter()
+        .filter(|r| &r.name == owner)
+        .filter_map(|r| match &r.rdata {
+            RData::Rrsig(rrsig) if rrsig.type_covered == rtype => Some(rrsig.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns true if `a` is at or before `b` in serial number arithmetic (RFC 1982)
+fn serial_le(a: u32, b: u32) -> bool {
+    b.wrapping_sub(a) < 1 << 31
+}
+
+fn is_supported_algorithm(algorithm: u8) -> bool {
+    matches!(
+        algorithm,
+        RSA_SHA256 | ECDSA_P256_SHA256 | ECDSA_P384_SHA384 | ED25519
+    )
+}
+
+fn is_supported_digest(digest_type: u8) -> bool {
+    matches!(digest_type, DIGEST_SHA256 | DIGEST_SHA384)
+}
+
+/// Computes the digest of `key` the way DS records of `zone` store it (RFC 4034 5.1.4)
+pub fn ds_digest(zone: &Name, key: &Dnskey, digest_type: u8) -> Option<Vec<u8>> {
+    let algorithm = match digest_type {
+        DIGEST_SHA256 => &digest::SHA256,
+        DIGEST_SHA384 => &digest::SHA384,
+        _ => return None,
+    };
+    let mut data = zone.to_lowercase().as_wire().to_vec();
+    key.encode(&mut data);
+    Some(digest::digest(algorithm, &data).as_ref().to_vec())
+}
+
+fn ds_matches(zone: &Name, key: &Dnskey, ds: &Ds) -> bool {
+    ds.key_tag == key.key_tag()
+        && ds.algorithm == key.algorithm
+        && is_supported_algorithm(ds.algorithm)
+        && ds_digest(zone, key, ds.digest_type).is_some_and(|digest| digest == ds.digest)
+}
+
+/// Builds the data an RRSIG signs: its own rdata without the signature, followed by the
+/// RRset in canonical form and order (RFC 4034 3.1.8.1)
+pub fn signed_data(rrset: &[Record], rrsig: &Rrsig) -> Vec<u8> {
+    let mut data = Vec::new();
+    Rrsig {
+        signer: rrsig.signer.to_lowercase(),
+        ..rrsig.clone()
+    }
+    .encode_without_signature(&mut data);
+
+    let Some(first) = rrset.first() else {
+        return data;
+    };
+    // Records matching a wildcard are signed with the wildcard owner (RFC 4035 5.3.2)
+    let mut owner = first.name.to_lowercase();
+    if (rrsig.labels as usize) < owner.label_count() {
+        while owner.label_count() > rrsig.labels as usize {
+            owner = owner.parent().unwrap_or_else(Name::root);
+        }
+        let labels = std::iter::once(&b"*"[..]).chain(owner.labels());
+        owner = Name::from_labels(labels).unwrap_or(owner);
+    }
+
+    let mut rdatas: Vec<Vec<u8>> = rrset.iter().map(|r| canonical_rdata(&r.rdata)).collect();
+    rdatas.sort();
+    rdatas.dedup();
+    for rdata in rdatas {
+        data.extend_from_slice(owner.as_wire());
+        data.extend_from_slice(&u16::from(first.rtype).to_be_bytes());
+        data.extend_from_slice(&u16::from(first.rclass).to_be_bytes());
+        data.extend_from_slice(&rrsig.original_ttl.to_be_bytes());
+        data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        data.extend_from_slice(&rdata);
+    }
+    data
+}
+
+/// Encodes `rdata` with the names of the types listed in RFC 4034 6.2 lowercased, without
+/// NSEC as updated by RFC 6840 5.1
+fn canonical_rdata(rdata: &RData) -> Vec<u8> {
+    let lowered = match rdata {
+        RData::Ns(name) => RData::Ns(name.to_lowercase()),
+        RData::Cname(name) => RData::Cname(name.to_lowercase()),
+        RData::Ptr(name) => RData::Ptr(name.to_lowercase()),
+        RData::Dname(name) => RData::Dname(name.to_lowercase()),
+        RData::Soa {
+            mname,
+            rname,
+            serial,
+            refresh,
+            retry,
+            expire,
+            minimum,
+        } => RData::Soa {
+            mname: mname.to_lowercase(),
+            rname: rname.to_lowercase(),
+            serial: *serial,
+            refresh: *refresh,
+            retry: *retry,
+            expire: *expire,
+            minimum: *minimum,
+        },
+        RData::Mx {
+            preference,
+            exchange,
+        } => RData::Mx {
+            preference: *preference,
+            exchange: exchange.to_lowercase(),
+        },
+        RData::Srv {
+            priority,
+            weight,
+            port,
+            target,
+        } => RData::Srv {
+            priority: *priority,
+            weight: *weight,
+            port: *port,
+            target: target.to_lowercase(),
+        },
+        RData::Rrsig(rrsig) => RData::Rrsig(Rrsig {
+            signer: rrsig.signer.to_lowercase(),
+            ..rrsig.clone()
+        }),
+        other => other.clone(),
+    };
+    let mut buf = Vec::new();
+    lowered.encode(&mut buf);
+    buf
+}
+
+/// Checks `sig` over `data` with `key`, returning false for unsupported algorithms
+fn verify_signature(key: &Dnskey, sig: &[u8], data: &[u8]) -> bool {
+    match key.algorithm {
+        RSA_SHA256 => rsa_components(&key.public_key).is_some_and(|(e, n)| {
+            RsaPublicKeyComponents { n, e }
+                .verify(
+                    &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                    data,
+                    sig,
+                )
+                .is_ok()
+        }),
+        ECDSA_P256_SHA256 | ECDSA_P384_SHA384 => {
+            // DNSKEY stores the bare coordinates of the point (RFC 6605 4)
+            let algorithm = if key.algorithm == ECDSA_P256_SHA256 {
+                &signature::ECDSA_P256_SHA256_FIXED
+            } else {
+                &signature::ECDSA_P384_SHA384_FIXED
+            };
+            let mut point = vec![0x04];
+            point.extend_from_slice(&key.public_key);
+            UnparsedPublicKey::new(algorithm, point)
+                .verify(data, sig)
+                .is_ok()
+        }
+        ED25519 => UnparsedPublicKey::new(&signature::ED25519, &key.public_key)
+            .verify(data, sig)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+/// Splits an RSA key into exponent and modulus (RFC 3110 2)
+fn rsa_components(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (&len, rest) = key.split_first()?;
+    let (len, rest) = match len {
+        0 => {
+            let (len, rest) = rest.split_at_checked(2)?;
+            (u16::from_be_bytes([len[0], len[1]]) as usize, rest)
+        }
+        len => (len as usize, rest),
+    };
+    let (e, n) = rest.split_at_checked(len)?;
+    (!e.is_empty() && !n.is_empty()).then_some((e, n))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        record::RecordClass,
+        test_util::{name, record},
+    };
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair},
+    };
+
+    const NOW: u32 = 1_700_000_000;
+
+    /// Zone signed with an Ed25519 key derived from a fixed seed, so fixtures are reproducible
+    struct Zone {
+        name: Name,
+        key_pair: Ed25519KeyPair,
+        dnskey: Dnskey,
+    }
+
+    impl Zone {
+        fn new(domain: &str, seed: u8) -> Self {
+            let key_pair = Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap();
+            let dnskey = Dnskey {
+                flags: 257,
+                protocol: 3,
+                algorithm: ED25519,
+                public_key: key_pair.public_key().as_ref().to_vec(),
+            };
+            Self {
+                name: name(domain),
+                key_pair,
+                dnskey,
+            }
+        }
+
+        fn ds(&self) -> Ds {
+            Ds {
+                key_tag: self.dnskey.key_tag(),
+                algorithm: ED25519,
+                digest_type: DIGEST_SHA256,
+                digest: ds_digest(&self.name, &self.dnskey, DIGEST_SHA256).unwrap(),
+            }
+        }
+
+        fn sign_with(&self, rrset: &[Record], inception: u32, expiration: u32) -> Record {
+            let mut rrsig = Rrsig {
+                type_covered: rrset[0].rtype,
+                algorithm: ED25519,
+                labels: (rrset[0].name.label_count() - usize::from(rrset[0].name.is_wildcard()))
+                    as u8,
+                original_ttl: rrset[0].ttl,
+                expiration,
+                inception,
+                key_tag: self.dnskey.key_tag(),
+                signer: self.name.clone(),
+                signature: Vec::new(),
+            };
+            rrsig.signature = self
+                .key_pair
+                .sign(&signed_data(rrset, &rrsig))
+                .as_ref()
+                .to_vec();
+            Record {
+                name: rrset[0].name.clone(),
+                rtype: RecordType::Rrsig,
+                rclass: RecordClass::In,
+                ttl: rrset[0].ttl,
+                rdata: RData::Rrsig(rrsig),
+            }
+        }
+
+        /// Returns the RRset followed by its signature
+        fn signed(&self, rrset: Vec<Record>) -> Vec<Record> {
+            let rrsig = self.sign_with(&rrset, NOW - 3600, NOW + 3600);
+            let mut records = rrset;
+            records.push(rrsig);
+            records
+        }
+
+        fn dnskey_rrset(&self) -> Vec<Record> {
+            let domain = self.name.to_string();
+            self.signed(vec![record(&domain, RData::Dnskey(self.dnskey.clone()))])
+        }
+    }
+
+    /// Validator that went through the root, and the referral from the root to `com.`
+    fn validator(root: &Zone, com: &Zone) -> Validator {
+        let mut validator = Validator::new(vec![root.ds()], NOW);
+        assert_eq!(
+            validator.add_keys(&root.name, &root.dnskey_rrset()),
+            Security::Secure
+        );
+        let referral = root.signed(vec![record("com", RData::Ds(com.ds()))]);
+        assert_eq!(
+            validator.add_delegation(&com.name, &referral),
+            Security::Secure
+        );
+        assert!(validator.needs_keys(&com.name));
+        assert_eq!(
+            validator.add_keys(&com.name, &com.dnskey_rrset()),
+            Security::Secure
+        );
+        validator
+    }
+
+    #[test]
+    fn test_secure_chain() {
+        let (root, com) = (Zone::new(".", 1), Zone::new("com", 2));
+        let validator = validator(&root, &com);
+
+        let answers = com.signed(vec![
+            record("www.example.com", RData::A([192, 0, 2, 1].into())),
+            record("www.example.com", RData::A([192, 0, 2, 2].into())),
+        ]);
+        let validated = validator.validate_records(&answers);
+        assert_eq!(validated.len(), 2);
+        assert!(validated.iter().all(|v| v.security == Security::Secure));
+
+        // Names are compared in canonical form
+        let mut upper = answers.clone();
+        upper[0].name = name("WWW.Example.COM");
+        upper[1].name = name("WWW.Example.COM");
+        upper[2].name = name("WWW.Example.COM");
+        assert_eq!(
+            validator.validate_rrset(&upper[..2], &upper),
+            Security::Secure
+        );
+    }
+
+    #[test]
+    fn test_bogus_answers() {
+        let (root, com) = (Zone::new(".", 1), Zone::new("com", 2));
+        let validator = validator(&root, &com);
+        let rrset = vec![record("www.example.com", RData::A([192, 0, 2, 1].into()))];
+
+        // Changed after signing
+        let mut answers = com.signed(rrset.clone());
+        answers[0].rdata = RData::A([198, 51, 100, 1].into());
+        assert_eq!(
+            validator.validate_rrset(&answers[..1], &answers),
+            Security::Bogus
+        );
+
+        // Expired, or signed by a key of another zone
+        let expired = com.sign_with(&rrset, NOW - 7200, NOW - 3600);
+        assert_eq!(
+            validator.validate_rrset(&rrset, &[expired]),
+            Security::Bogus
+        );
+        let mut answers = Zone::new("com", 3).signed(rrset.clone());
+        assert_eq!(validator.validate_rrset(&rrset, &answers), Security::Bogus);
+
+        // Missing signature
+        answers.pop();
+        assert_eq!(validator.validate_rrset(&rrset, &answers), Security::Bogus);
+
+        // DNSKEY RRset that doesn't match the DS
+        let mut validator = Validator::new(vec![root.ds()], NOW);
+        let other = Zone::new(".", 4);
+        assert_eq!(
+            validator.add_keys(&root.name, &other.dnskey_rrset()),
+            Security::Bogus
+        );
+    }
+
+    #[test]
+    fn test_alias_chain() {
+        let (root, com) = (Zone::new(".", 1), Zone::new("com", 2));
+        let validator = validator(&root, &com);
+        let cname = vec![record(
+            "www.example.com",
+            RData::Cname(name("cdn.example.com")),
+        )];
+        let target = com.signed(vec![record(
+            "cdn.example.com",
+            RData::A([192, 0, 2, 1].into()),
+        )]);
+        let answer = ValidatedAnswer::Records(validator.validate_records(&target));
+        assert_eq!(
+            answer,
+            ValidatedAnswer::Records(vec![ValidatedRecord {
+                record: target[0].clone(),
+                security: Security::Secure,
+            }])
+        );
+
+        // A secure target is only as secure as the aliases leading to it
+        let aliases = validator.validate_records(&com.signed(cname.clone()));
+        assert_eq!(answer.clone().through_aliases(&aliases), answer);
+
+        let mut forged = com.signed(cname.clone());
+        forged[0].rdata = RData::Cname(name("evil.example.com"));
+        let aliases = validator.validate_records(&forged);
+        assert_eq!(aliases[0].security, Security::Bogus);
+        let ValidatedAnswer::Records(records) = answer.clone().through_aliases(&aliases) else {
+            panic!("Expected records");
+        };
+        assert!(records.iter().all(|r| r.security == Security::Bogus));
+
+        let unsigned = validator.validate_records(&cname);
+        assert_eq!(
+            ValidatedAnswer::NoData(Security::Secure).through_aliases(&unsigned),
+            ValidatedAnswer::NoData(Security::Bogus)
+        );
+        let insecure = [ValidatedRecord {
+            record: cname[0].clone(),
+            security: Security::Insecure,
+        }];
+        assert_eq!(
+            ValidatedAnswer::NxDomain(Security::Secure).through_aliases(&insecure),
+            ValidatedAnswer::NxDomain(Security::Insecure)
+        );
+    }
+
+    #[test]
+    fn test_insecure_delegation() {
+        let (root, com) = (Zone::new(".", 1), Zone::new("com", 2));
+        let mut validator = validator(&root, &com);
+        let nsec = crate::dnssec::Nsec {
+            next: name("example.net"),
+            types: vec![RecordType::Ns, RecordType::Rrsig, RecordType::Nsec],
+        };
+        let mut referral = vec![record("example.com", RData::Ns(name("ns.example.com")))];
+        referral.extend(com.signed(vec![record("example.com", RData::Nsec(nsec))]));
+
+        // Unsigned NSEC records don't prove anything
+        let mut unsigned = Validator::clone(&validator);
+        assert_eq!(
+            unsigned.add_delegation(&name("example.com"), &referral[..2]),
+            Security::Bogus
+        );
+
+        // Neither do ones saying there are DS records
+        let mut signed = Validator::clone(&validator);
+        let nsec = crate::dnssec::Nsec {
+            next: name("example.net"),
+            types: vec![RecordType::Ns, RecordType::Ds, RecordType::Nsec],
+        };
+        let mut with_ds = vec![referral[0].clone()];
+        with_ds.extend(com.signed(vec![record("example.com", RData::Nsec(nsec))]));
+        assert_eq!(
+            signed.add_delegation(&name("example.com"), &with_ds),
+            Security::Bogus
+        );
+
+        assert_eq!(
+            validator.add_delegation(&name("example.com"), &referral),
+            Security::Insecure
+        );
+        let answers = vec![record("www.example.com", RData::A([192, 0, 2, 1].into()))];
+        let validated = validator.validate_records(&answers);
+        assert_eq!(validated[0].security, Security::Insecure);
+
+        // Nothing is known outside of the trust anchor
+        let validator = Validator::new(vec![root.ds()], NOW);
+        assert_eq!(
+            validator.validate_rrset(&answers, &answers),
+            Security::Indeterminate
+        );
+    }
+
+    #[test]
+    fn test_unknown_signers() {
+        let (root, com) = (Zone::new(".", 1), Zone::new("com", 2));
+        let validator = validator(&root, &com);
+        let example = Zone::new("example.com", 5);
+        let answers = example.signed(vec![record(
+            "www.example.com",
+            RData::A([192, 0, 2, 1].into()),
+        )]);
+        assert_eq!(validator.unknown_signers(&answers), [name("example.com")]);
+        let answers = com.signed(vec![record(
+            "www.example.com",
+            RData::A([192, 0, 2, 1].into()),
+        )]);
+        assert!(validator.unknown_signers(&answers).is_empty());
+    }
+
+    #[test]
+    fn test_wildcard_signature() {
+        let (root, com) = (Zone::new(".", 1), Zone::new("com", 2));
+        let validator = validator(&root, &com);
+        let wildcard = vec![record("*.com", RData::A([192, 0, 2, 1].into()))];
+        let rrsig = com.sign_with(&wildcard, NOW - 3600, NOW + 3600);
+
+        // Synthesized from the wildcard, with the labels field of the wildcard
+        let expanded = vec![record("www.example.com", RData::A([192, 0, 2, 1].into()))];
+        let mut rrsig = rrsig;
+        rrsig.name = name("www.example.com");
+
+        // Only valid along with the proof that the name doesn't exist
+        assert_eq!(
+            validator.validate_rrset(&expanded, std::slice::from_ref(&rrsig)),
+            Security::Bogus
+        );
+        let nsec = |owner: &str, next: &str| {
+            let types = vec![RecordType::A, RecordType::Rrsig, RecordType::Nsec];
+            let nsec = crate::dnssec::Nsec {
+                next: name(next),
+                types,
+            };
+            com.signed(vec![record(owner, RData::Nsec(nsec))])
+        };
+        let mut records = vec![rrsig.clone()];
+        records.extend(nsec("a.com", "f.com"));
+        assert_eq!(
+            validator.validate_rrset(&expanded, &records),
+            Security::Secure
+        );
+
+        // example.com exists, so the wildcard doesn't apply
+        let mut records = vec![rrsig];
+        records.extend(nsec("example.com", "f.com"));
+        assert_eq!(
+            validator.validate_rrset(&expanded, &records),
+            Security::Bogus
+        );
+    }
+
+    #[test]
+    fn test_denial() {
+        let (root, com) = (Zone::new(".", 1), Zone::new("com", 2));
+        let validator = validator(&root, &com);
+        let nsec = |owner: &str, next: &str, types: Vec<RecordType>| {
+            let nsec = crate::dnssec::Nsec {
+                next: name(next),
+                types,
+            };
+            record(owner, RData::Nsec(nsec))
+        };
+        let apex = nsec(
+            "com",
+            "example.com",
+            vec![RecordType::Soa, RecordType::Ns, RecordType::Nsec],
+        );
+        let example = nsec("example.com", "com", vec![RecordType::A, RecordType::Nsec]);
+        let mut records = com.signed(vec![apex]);
+        records.extend(com.signed(vec![example]));
+
+        let nxdomain = name("nope.com");
+        assert_eq!(
+            validator.validate_denial(&nxdomain, RecordType::A, true, &records),
+            Security::Secure
+        );
+        assert_eq!(
+            validator.validate_denial(&name("example.com"), RecordType::Aaaa, false, &records),
+            Security::Secure
+        );
+        // The name exists, and so do its A records
+        assert_eq!(
+            validator.validate_denial(&name("example.com"), RecordType::A, true, &records),
+            Security::Bogus
+        );
+        assert_eq!(
+            validator.validate_denial(&name("example.com"), RecordType::A, false, &records),
+            Security::Bogus
+        );
+
+        // Unsigned records prove nothing
+        let unsigned: Vec<_> = records
+            .iter()
+            .filter(|r| r.rtype != RecordType::Rrsig)
+            .cloned()
+            .collect();
+        assert_eq!(
+            validator.validate_denial(&nxdomain, RecordType::A, true, &unsigned),
+            Security::Bogus
+        );
+
+        // Denials outside of the trust anchor can't be checked
+        let validator = Validator::new(vec![root.ds()], NOW);
+        assert_eq!(
+            validator.validate_denial(&nxdomain, RecordType::A, true, &records),
+            Security::Indeterminate
+        );
+    }
+
+    #[test]
+    fn test_ecdsa_signatures() {
+        let random = SystemRandom::new();
+        for (algorithm, signing, len) in [
+            (
+                ECDSA_P256_SHA256,
+                &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                64,
+            ),
+            (
+                ECDSA_P384_SHA384,
+                &signature::ECDSA_P384_SHA384_FIXED_SIGNING,
+                96,
+            ),
+        ] {
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(signing, &random).unwrap();
+            let key_pair = EcdsaKeyPair::from_pkcs8(signing, pkcs8.as_ref(), &random).unwrap();
+            let key = Dnskey {
+                flags: 256,
+                protocol: 3,
+                algorithm,
+                // Without the 0x04 prefix of uncompressed points
+                public_key: key_pair.public_key().as_ref()[1..].to_vec(),
+            };
+            assert_eq!(key.public_key.len(), len);
+            let sig = key_pair.sign(&random, b"signed data").unwrap();
+            assert!(verify_signature(&key, sig.as_ref(), b"signed data"));
+            assert!(!verify_signature(&key, sig.as_ref(), b"other data"));
+        }
+    }
+
+    #[test]
+    fn test_root_trust_anchor() {
+        let anchor = root_trust_anchor();
+        assert_eq!(anchor.len(), 2);
+        // KSK-2017 of the root zone
+        let RData::Dnskey(ksk) = RData::from_presentation(
+            RecordType::Dnskey,
+            "257 3 8 AwEAAaz/tAm8yTn4Mfeh5eyI96WSVexTBAvkMgJzkKTOiW1vkIbzxeF3+/4RgWOq7HrxRixHlFlExOLAJr5emLvN7SWXgnLh4+B5xQlNVz8Og8kvArMtNROxVQuCaSnIDdD5LKyWbRd2n9WGe2R8PzgCmr3EgVLrjyBxWezF0jLHwVN8efS3rCj/EWgvIWgb9tarpVUDK/b58Da+sqqls3eNbuv7pr+eoZG+SrDK6nWeL3c6H5Apxz7LjVc1uTIdsIXxuOLYA4/ilBmSVIzuDWfdRUfhHdY6+cn8HFRm+2hM8AnXGXws9555KrUB5qihylGa8subX2Nn6UwNR1AkUTV74bU=",
+        )
+        .unwrap() else {
+            panic!("Expected DNSKEY");
+        };
+        assert!(ds_matches(&Name::root(), &ksk, &anchor[0]));
+        assert!(!ds_matches(&Name::root(), &ksk, &anchor[1]));
+
+        let (e, n) = rsa_components(&ksk.public_key).unwrap();
+        assert_eq!(e, [1, 0, 1]);
+        assert_eq!(n.len(), 256);
+    }
+
+    #[test]
+    fn test_serial_le() {
+        assert!(serial_le(1, 2));
+        assert!(serial_le(u32::MAX, 1));
+        assert!(!serial_le(2, 1));
+    }
+}
@@ -0,0 +1,337 @@
+use std::fmt;
+
+use crate::{
+    compression::{Labels, check_name, decompress},
+    error::ParseError,
+    message::{Header, Question, ResourceRecord},
+    name::{Name, write_labels},
+    rdata::RData,
+    record::{Record, RecordClass, RecordType},
+};
+
+const HEADER_LEN: usize = 12;
+
+/// Read only view of a received message, which borrows everything from the packet
+///
+/// The whole message is validated once by [`MessageView::new`], after which the sections are
+/// read lazily by the iterators without allocating
+#[derive(Debug, Clone)]
+pub struct MessageView<'a> {
+    bytes: &'a [u8],
+    header: Header,
+    /// Offsets of the answer, authority and additional sections
+    sections: [usize; 3],
+}
+
+/// Name somewhere in a message, possibly compressed
+#[derive(Debug, Clone, Copy)]
+pub struct NameRef<'a> {
+    message: &'a [u8],
+    offset: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuestionRef<'a> {
+    name: NameRef<'a>,
+    qtype: u16,
+    qclass: u16,
+}
+
+#[derive(Debug)]
+pub struct RecordRef<'a> {
+    name: NameRef<'a>,
+    record: ResourceRecord<'a>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Questions<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    remaining: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    remaining: u16,
+}
+
+impl<'a> MessageView<'a> {
+    /// Validates the header, the bounds of every question and record, and every owner name
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any part of the message is malformed
+    pub fn new(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        let header = Header::from_bytes(bytes)?;
+        let mut offset = HEADER_LEN;
+
+        for _ in 0..header.qdcount {
+            let (_, end) = Question::from_bytes(bytes, offset)?;
+            check_name(bytes, offset)?;
+            offset = end;
+        }
+
+        let mut sections = [0; 3];
+        let counts = [header.ancount, header.nscount, header.arcount];
+        for (start, count) in sections.iter_mut().zip(counts) {
+            *start = offset;
+            for _ in 0..count {
+                let (_, end) = ResourceRecord::from_bytes(bytes, offset)?;
+                check_name(bytes, offset)?;
+                offset = end;
+            }
+        }
+
+        Ok(Self {
+            bytes,
+            header,
+            sections,
+        })
+    }
+
+    pub fn get_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn questions(&self) -> Questions<'a> {
+        Questions {
+            bytes: self.bytes,
+            offset: HEADER_LEN,
+            remaining: self.header.qdcount,
+        }
+    }
+
+    pub fn answers(&self) -> Records<'a> {
+        self.records(0, self.header.ancount)
+    }
+
+    pub fn authorities(&self) -> Records<'a> {
+        self.records(1, self.header.nscount)
+    }
+
+    pub fn additionals(&self) -> Records<'a> {
+        self.records(2, self.header.arcount)
+    }
+
+    fn records(&self, section: usize, count: u16) -> Records<'a> {
+        Records {
+            bytes: self.bytes,
+            offset: self.sections[section],
+            remaining: count,
+        }
+    }
+}
+
+impl<'a> Iterator for Questions<'a> {
+    type Item = QuestionRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (question, end) = Question::from_bytes(self.bytes, self.offset).ok()?;
+        let name = NameRef {
+            message: self.bytes,
+            offset: self.offset,
+        };
+        self.offset = end;
+        self.remaining -= 1;
+        Some(QuestionRef {
+            name,
+            qtype: question.qtype,
+            qclass: question.qclass,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = RecordRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (record, end) = ResourceRecord::from_bytes(self.bytes, self.offset).ok()?;
+        let name = NameRef {
+            message: self.bytes,
+            offset: self.offset,
+        };
+        self.offset = end;
+        self.remaining -= 1;
+        Some(RecordRef { name, record })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+impl ExactSizeIterator for Questions<'_> {}
+
+impl ExactSizeIterator for Records<'_> {}
+
+impl<'a> NameRef<'a> {
+    /// Returns the labels of the name, without the root label
+    pub fn labels(&self) -> Labels<'a> {
+        Labels::new(self.message, self.offset)
+    }
+
+    /// Compares the names label by label, ignoring ASCII case like DNS does
+    pub fn eq_ignore_ascii_case(&self, other: &NameRef) -> bool {
+        let mut labels = self.labels();
+        let mut other = other.labels();
+        loop {
+            match (labels.next(), other.next()) {
+                (None, None) => return true,
+                (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => {}
+                _ => return false,
+            }
+        }
+    }
+
+    /// Copies the name out of the message
+    pub fn to_name(&self) -> Result<Name, ParseError> {
+        decompress(&self.message[self.offset..], self.message)
+    }
+}
+
+impl fmt::Display for NameRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_labels(f, self.labels())
+    }
+}
+
+impl<'a> QuestionRef<'a> {
+    pub fn get_name(&self) -> NameRef<'a> {
+        self.name
+    }
+
+    pub fn get_type(&self) -> RecordType {
+        self.qtype.into()
+    }
+
+    pub fn get_class(&self) -> RecordClass {
+        self.qclass.into()
+    }
+}
+
+impl<'a> RecordRef<'a> {
+    pub fn get_name(&self) -> NameRef<'a> {
+        self.name
+    }
+
+    pub fn get_type(&self) -> RecordType {
+        self.record.get_type()
+    }
+
+    pub fn get_class(&self) -> RecordClass {
+        self.record.get_class()
+    }
+
+    pub fn get_ttl(&self) -> u32 {
+        self.record.get_ttl()
+    }
+
+    pub fn get_raw_rdata(&self) -> &'a [u8] {
+        self.record.get_raw_rdata()
+    }
+
+    /// Decodes the rdata, which is only checked for bounds when the view is created
+    pub fn get_rdata(&self) -> Result<RData, ParseError> {
+        self.record.get_rdata(self.name.message)
+    }
+
+    pub fn to_record(&self) -> Result<Record, ParseError> {
+        self.record.to_record(self.name.message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        builder::MessageBuilder,
+        message::{Encodable, Message},
+        test_util::{name, record},
+    };
+
+    fn encoded_response() -> Vec<u8> {
+        let message = MessageBuilder::new(0xbeef)
+            .question(name("www.example.com"), RecordType::A, RecordClass::In)
+            .qr(true)
+            .answer(record("www.example.com", RData::Cname(name("example.com"))))
+            .answer(record("example.com", RData::A([192, 0, 2, 1].into())))
+            .authority(record("example.com", RData::Ns(name("ns1.Example.com"))))
+            .additional(record("ns1.example.com", RData::A([192, 0, 2, 53].into())))
+            .build();
+        let mut buf = Vec::new();
+        message.encode(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_view_sections() {
+        let buf = encoded_response();
+        let view = MessageView::new(&buf).unwrap();
+        assert_eq!(view.get_header().get_id(), 0xbeef);
+
+        let questions: Vec<_> = view.questions().collect();
+        assert_eq!(questions.len(), 1);
+        assert_eq!(questions[0].get_name().to_string(), "www.example.com.");
+        assert_eq!(questions[0].get_type(), RecordType::A);
+
+        let answers: Vec<_> = view.answers().collect();
+        assert_eq!(answers.len(), 2);
+        // The owner of the second answer is a pointer into the first
+        assert_eq!(answers[1].get_name().to_string(), "example.com.");
+        assert_eq!(answers[1].get_rdata(), Ok(RData::A([192, 0, 2, 1].into())));
+        assert_eq!(view.authorities().len(), 1);
+
+        // Same records as the eager parser
+        let message = Message::from_bytes(&buf).unwrap();
+        let eager = message.get_additionals()[0].to_record(&buf).unwrap();
+        assert_eq!(view.additionals().next().unwrap().to_record(), Ok(eager));
+    }
+
+    #[test]
+    fn test_name_ref_labels() {
+        let buf = encoded_response();
+        let view = MessageView::new(&buf).unwrap();
+        let question = view.questions().next().unwrap().get_name();
+        let labels: Vec<&[u8]> = question.labels().collect();
+        assert_eq!(labels, [&b"www"[..], b"example", b"com"]);
+        assert_eq!(question.to_name(), Ok(name("www.example.com")));
+
+        let answer = view.answers().nth(1).unwrap().get_name();
+        let authority = view.authorities().next().unwrap().get_name();
+        assert!(answer.eq_ignore_ascii_case(&authority));
+        assert!(!answer.eq_ignore_ascii_case(&question));
+    }
+
+    #[test]
+    fn test_view_rejects_bad_names() {
+        let mut buf = encoded_response();
+        // Owner of the first answer pointing at itself
+        let offset = 12 + 17 + 4;
+        buf[offset..offset + 2].copy_from_slice(&[0xc0, offset as u8]);
+        assert_eq!(
+            MessageView::new(&buf).unwrap_err(),
+            ParseError::CompressionLoop
+        );
+
+        assert_eq!(
+            MessageView::new(&buf[..8]).unwrap_err(),
+            ParseError::InvalidHeader
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rust_dns::{
+    builder::{MessageBuilder, OwnedMessage},
+    compression::decompress,
+    edns::{Edns, EdnsOption},
+    message::{Encodable, Message, dissect},
+    name::Name,
+    rdata::RData,
+    record::{Record, RecordClass, RecordType},
+    view::MessageView,
+};
+
+const ITERATIONS: usize = 2000;
+
+fn random_label(rng: &mut StdRng) -> Vec<u8> {
+    let len = rng.random_range(1..=12);
+    (0..len).map(|_| rng.random_range(b'a'..=b'z')).collect()
+}
+
+/// Random name from a small set of labels, so that suffixes repeat and get compressed
+fn random_name(rng: &mut StdRng) -> Name {
+    const LABELS: [&[u8]; 6] = [b"www", b"example", b"com", b"mail", b"ns1", b"org"];
+    let mut wire = Vec::new();
+    for _ in 0..rng.random_range(0..4) {
+        let label = match rng.random_bool(0.8) {
+            true => LABELS[rng.random_range(0..LABELS.len())].to_vec(),
+            false => random_label(rng),
+        };
+        wire.push(label.len() as u8);
+        wire.extend_from_slice(&label);
+    }
+    wire.push(0);
+    Name::from_wire(&wire).unwrap()
+}
+
+fn random_bytes(rng: &mut StdRng, max_len: usize) -> Vec<u8> {
+    let len = rng.random_range(0..=max_len);
+    (0..len).map(|_| rng.random()).collect()
+}
+
+fn random_rdata(rng: &mut StdRng) -> RData {
+    match rng.random_range(0..11) {
+        0 => RData::A(rng.random::<[u8; 4]>().into()),
+        1 => RData::Aaaa(rng.random::<[u8; 16]>().into()),
+        2 => RData::Ns(random_name(rng)),
+        3 => RData::Cname(random_name(rng)),
+        4 => RData::Soa {
+            mname: random_name(rng),
+            rname: random_name(rng),
+            serial: rng.random(),
+            refresh: rng.random(),
+            retry: rng.random(),
+            expire: rng.random(),
+            minimum: rng.random(),
+        },
+        5 => RData::Mx {
+            preference: rng.random(),
+            exchange: random_name(rng),
+        },
+        6 => RData::Txt(
+            (0..rng.random_range(1..4))
+                .map(|_| random_bytes(rng, 40))
+                .collect(),
+        ),
+        7 => RData::Srv {
+            priority: rng.random(),
+            weight: rng.random(),
+            port: rng.random(),
+            target: random_name(rng),
+        },
+        8 => RData::Caa {
+            flags: rng.random(),
+            tag: random_label(rng),
+            value: random_bytes(rng, 30),
+        },
+        9 => RData::Ptr(random_name(rng)),
+        _ => RData::Unknown {
+            rtype: rng.random_range(1000..2000),
+            data: random_bytes(rng, 30),
+        },
+    }
+}
+
+fn random_record(rng: &mut StdRng) -> Record {
+    let rdata = random_rdata(rng);
+    Record {
+        name: random_name(rng),
+        rtype: rdata.get_type(),
+        rclass: RecordClass::In,
+        ttl: rng.random(),
+        rdata,
+    }
+}
+
+fn random_message(rng: &mut StdRng) -> OwnedMessage {
+    let mut builder = MessageBuilder::new(rng.random())
+        .qr(rng.random())
+        .aa(rng.random())
+        .rd(rng.random());
+    for _ in 0..rng.random_range(0..3) {
+        builder = builder.question(random_name(rng), RecordType::A, RecordClass::In);
+    }
+    for _ in 0..rng.random_range(0..5) {
+        builder = builder.answer(random_record(rng));
+    }
+    for _ in 0..rng.random_range(0..3) {
+        builder = builder.authority(random_record(rng));
+    }
+    for _ in 0..rng.random_range(0..3) {
+        builder = builder.additional(random_record(rng));
+    }
+    if rng.random() {
+        let mut edns = Edns::new(rng.random_range(512..4096));
+        edns.dnssec_ok = rng.random();
+        edns.options.push(EdnsOption {
+            code: rng.random(),
+            data: random_bytes(rng, 16),
+        });
+        builder = builder.edns(edns);
+    }
+    builder.build()
+}
+
+/// Runs every parsing entry point on `bytes`, which must not panic whatever the input
+fn parse_everything(bytes: &[u8]) {
+    if let Ok(message) = Message::from_bytes(bytes) {
+        let records = message
+            .get_answers()
+            .iter()
+            .chain(message.get_authorities())
+            .chain(message.get_additionals());
+        for rr in records {
+            let _ = rr.to_record(bytes);
+        }
+        let _ = message.get_cnames(bytes);
+        let _ = message.get_additional_info(bytes);
+        let _ = message.get_authorities_info(bytes);
+        let _ = message.get_edns(bytes);
+        let _ = message.get_answer_ips();
+        let _ = message.get_type();
+    }
+    let _ = OwnedMessage::from_bytes(bytes);
+    let _ = dissect(bytes);
+    if let Ok(view) = MessageView::new(bytes) {
+        for question in view.questions() {
+            let _ = question.get_name().to_string();
+        }
+        let records = view
+            .answers()
+            .chain(view.authorities())
+            .chain(view.additionals());
+        for rr in records {
+            let _ = rr.get_name().to_string();
+            let _ = rr.to_record();
+        }
+    }
+    for offset in 0..bytes.len().min(64) {
+        let _ = decompress(&bytes[offset..], bytes);
+    }
+}
+
+#[test]
+fn test_round_trip_random_messages() {
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+    for _ in 0..ITERATIONS {
+        let message = random_message(&mut rng);
+        let mut buf = Vec::new();
+        message.encode(&mut buf);
+
+        let output = OwnedMessage::from_bytes(&buf).expect("encoded message should parse");
+        assert_eq!(output, message);
+    }
+}
+
+#[test]
+fn test_random_bytes_do_not_panic() {
+    let mut rng = StdRng::seed_from_u64(0xbad);
+    for _ in 0..ITERATIONS {
+        let mut bytes = random_bytes(&mut rng, 600);
+        // Plausible counts make it past the header more often
+        if bytes.len() >= 12 {
+            for count in bytes[4..12].chunks_mut(2) {
+                count[0] = 0;
+                count[1] %= 8;
+            }
+        }
+        parse_everything(&bytes);
+    }
+}
+
+#[test]
+fn test_mutated_messages_do_not_panic() {
+    let mut rng = StdRng::seed_from_u64(0xf00d);
+    for _ in 0..ITERATIONS {
+        let mut buf = Vec::new();
+        random_message(&mut rng).encode(&mut buf);
+
+        for _ in 0..rng.random_range(1..8) {
+            let i = rng.random_range(0..buf.len());
+            match rng.random_range(0..3) {
+                0 => buf[i] = rng.random(),
+                1 => buf[i] |= 0b11000000,
+                _ => buf.truncate(i.max(1)),
+            }
+        }
+        parse_everything(&buf);
+    }
+}